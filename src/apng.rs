@@ -0,0 +1,1095 @@
+//! Animated PNG (APNG) frames, stored in `acTL`, `fcTL`, and `fdAT` chunks.
+//!
+//! An animated PNG holds an `acTL` chunk before the image data, giving the
+//! number of frames and how many times to play them. Each frame starts with
+//! an `fcTL` chunk, describing its region of the canvas, its delay, and how
+//! it is disposed of and blended, followed by its image data:
+//!
+//! - If the first `fcTL` chunk precedes the `IDAT` chunks, the default image
+//!   is the first frame, and covers the full canvas.
+//! - Otherwise, the default image is not part of the animation, and is only
+//!   displayed by decoders which do not support APNG.
+//! - The image data of every other frame is stored in `fdAT` chunks, holding a
+//!   sequence number followed by the same data an `IDAT` chunk would.
+//!
+//! The `fcTL` and `fdAT` chunks share a single sequence, numbered from 0
+//! without gaps, which lets decoders detect chunks that were lost or
+//! reordered. Message chunks appended with [`PNG::append_chunk`] are inserted
+//! after the last frame, so they never split a frame from its image data or
+//! disturb the sequence, and the animation keeps playing as before.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::idat::DEFAULT_CHUNK_SIZE;
+use crate::ihdr::Ihdr;
+use crate::png::PNG;
+
+/// Chunk types specific to animated PNGs, which are not copied to the
+/// standalone PNG of a frame.
+const ANIMATION_CHUNK_TYPES: [&[u8; 4]; 3] = [b"acTL", b"fcTL", b"fdAT"];
+
+/// Decoded contents of an `acTL` chunk.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AnimationControl {
+    /// Number of frames in the animation, which must equal the number of
+    /// `fcTL` chunks.
+    pub num_frames: u32,
+    /// Number of times to play the animation, or 0 to loop forever.
+    pub num_plays: u32,
+}
+
+impl AnimationControl {
+    /// Length of the `acTL` chunk data in bytes.
+    pub const LENGTH: usize = 8;
+
+    /// Returns the data of the `acTL` chunk.
+    pub fn as_bytes(&self) -> [u8; Self::LENGTH] {
+        let mut bytes = [0; Self::LENGTH];
+        bytes[..4].copy_from_slice(&self.num_frames.to_be_bytes());
+        bytes[4..].copy_from_slice(&self.num_plays.to_be_bytes());
+        bytes
+    }
+}
+
+impl TryFrom<&Chunk> for AnimationControl {
+    type Error = anyhow::Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = chunk_data(chunk, b"acTL", Self::LENGTH)?;
+
+        let control = AnimationControl {
+            num_frames: read_u32(data, 0),
+            num_plays: read_u32(data, 4),
+        };
+
+        anyhow::ensure!(
+            control.num_frames > 0,
+            "invalid acTL chunk: the number of frames must be non-zero"
+        );
+
+        Ok(control)
+    }
+}
+
+/// How the region of a frame is disposed of before rendering the next frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum DisposeOp {
+    /// The region is left as it is.
+    #[default]
+    None = 0,
+    /// The region is cleared to fully transparent black.
+    Background = 1,
+    /// The region is reverted to its contents before the frame was rendered.
+    Previous = 2,
+}
+
+impl TryFrom<u8> for DisposeOp {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(DisposeOp::None),
+            1 => Ok(DisposeOp::Background),
+            2 => Ok(DisposeOp::Previous),
+            _ => anyhow::bail!("invalid fcTL chunk: unknown dispose op {value}, expected 0-2"),
+        }
+    }
+}
+
+impl FromStr for DisposeOp {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(DisposeOp::None),
+            "background" => Ok(DisposeOp::Background),
+            "previous" => Ok(DisposeOp::Previous),
+            _ => anyhow::bail!(
+                "invalid dispose op '{s}': expected one of none, background, or previous"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for DisposeOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DisposeOp::None => "none",
+            DisposeOp::Background => "background",
+            DisposeOp::Previous => "previous",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// How a frame is blended onto the region of the canvas it covers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum BlendOp {
+    /// The frame replaces the region, including its alpha.
+    #[default]
+    Source = 0,
+    /// The frame is composited over the region using its alpha.
+    Over = 1,
+}
+
+impl TryFrom<u8> for BlendOp {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(BlendOp::Source),
+            1 => Ok(BlendOp::Over),
+            _ => anyhow::bail!("invalid fcTL chunk: unknown blend op {value}, expected 0-1"),
+        }
+    }
+}
+
+impl FromStr for BlendOp {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "source" => Ok(BlendOp::Source),
+            "over" => Ok(BlendOp::Over),
+            _ => anyhow::bail!("invalid blend op '{s}': expected one of source or over"),
+        }
+    }
+}
+
+impl fmt::Display for BlendOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BlendOp::Source => "source",
+            BlendOp::Over => "over",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// Decoded contents of an `fcTL` chunk.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameControl {
+    /// Position of the chunk in the sequence of `fcTL` and `fdAT` chunks.
+    pub sequence_number: u32,
+    /// Width of the frame in pixels.
+    pub width: u32,
+    /// Height of the frame in pixels.
+    pub height: u32,
+    /// Column of the canvas at which the frame is rendered.
+    pub x_offset: u32,
+    /// Row of the canvas at which the frame is rendered.
+    pub y_offset: u32,
+    /// Numerator of the delay after the frame, in seconds.
+    pub delay_num: u16,
+    /// Denominator of the delay after the frame, in seconds, where 0 stands
+    /// for 100.
+    pub delay_den: u16,
+    /// How the frame is disposed of before rendering the next frame.
+    pub dispose_op: DisposeOp,
+    /// How the frame is blended onto the canvas.
+    pub blend_op: BlendOp,
+}
+
+impl FrameControl {
+    /// Length of the `fcTL` chunk data in bytes.
+    pub const LENGTH: usize = 26;
+
+    /// Returns the delay after the frame is rendered.
+    pub fn delay(&self) -> Duration {
+        let den = match self.delay_den {
+            0 => 100,
+            den => den,
+        };
+
+        Duration::from_secs_f64(self.delay_num as f64 / den as f64)
+    }
+
+    /// Returns the data of the `fcTL` chunk.
+    pub fn as_bytes(&self) -> [u8; Self::LENGTH] {
+        let mut bytes = [0; Self::LENGTH];
+        bytes[..4].copy_from_slice(&self.sequence_number.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.width.to_be_bytes());
+        bytes[8..12].copy_from_slice(&self.height.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.x_offset.to_be_bytes());
+        bytes[16..20].copy_from_slice(&self.y_offset.to_be_bytes());
+        bytes[20..22].copy_from_slice(&self.delay_num.to_be_bytes());
+        bytes[22..24].copy_from_slice(&self.delay_den.to_be_bytes());
+        bytes[24] = self.dispose_op as u8;
+        bytes[25] = self.blend_op as u8;
+        bytes
+    }
+}
+
+impl TryFrom<&Chunk> for FrameControl {
+    type Error = anyhow::Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = chunk_data(chunk, b"fcTL", Self::LENGTH)?;
+
+        let control = FrameControl {
+            sequence_number: read_u32(data, 0),
+            width: read_u32(data, 4),
+            height: read_u32(data, 8),
+            x_offset: read_u32(data, 12),
+            y_offset: read_u32(data, 16),
+            delay_num: u16::from_be_bytes([data[20], data[21]]),
+            delay_den: u16::from_be_bytes([data[22], data[23]]),
+            dispose_op: DisposeOp::try_from(data[24])?,
+            blend_op: BlendOp::try_from(data[25])?,
+        };
+
+        anyhow::ensure!(
+            control.width > 0 && control.height > 0,
+            "invalid fcTL chunk: frame dimensions must be non-zero, but received: {}x{}",
+            control.width,
+            control.height
+        );
+
+        Ok(control)
+    }
+}
+
+/// Frame of an animated PNG.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    control: FrameControl,
+    default_image: bool,
+    image_data: Vec<u8>,
+}
+
+impl Frame {
+    /// Returns the frame control of the frame.
+    pub fn control(&self) -> &FrameControl {
+        &self.control
+    }
+
+    /// Returns `true` if the frame is the default image, stored in `IDAT`
+    /// chunks rather than `fdAT` chunks.
+    pub fn is_default_image(&self) -> bool {
+        self.default_image
+    }
+
+    /// Returns the compressed image data of the frame, joined from its `IDAT`
+    /// or `fdAT` chunks without their sequence numbers.
+    pub fn image_data(&self) -> &[u8] {
+        &self.image_data
+    }
+}
+
+impl PNG {
+    /// Returns the animation control of the PNG, or `None` if it is not an
+    /// animated PNG.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `acTL` chunk is invalid.
+    pub fn animation_control(&self) -> Result<Option<AnimationControl>> {
+        self.chunk_by_type("acTL")
+            .map(AnimationControl::try_from)
+            .transpose()
+    }
+
+    /// Returns the frames of the PNG, in order, after checking the sequence
+    /// numbers of its `fcTL` and `fdAT` chunks. A PNG which is not animated
+    /// has no frames.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `acTL`, `fcTL`, or `fdAT` chunks are invalid,
+    /// out of sequence, or do not match each other or the `IHDR` chunk.
+    pub fn frames(&self) -> Result<Vec<Frame>> {
+        let Some(animation) = self.animation_control()? else {
+            anyhow::ensure!(
+                self.chunks_by_type("fcTL").next().is_none()
+                    && self.chunks_by_type("fdAT").next().is_none(),
+                "invalid APNG datastream: missing acTL chunk"
+            );
+            return Ok(Vec::new());
+        };
+
+        let ihdr = self.ihdr()?;
+        let mut frames: Vec<Frame> = Vec::new();
+        let mut sequence_number = 0;
+        let mut seen_idat = false;
+
+        let mut check_sequence = |chunk: &Chunk, actual: u32| {
+            anyhow::ensure!(
+                actual == sequence_number,
+                "invalid APNG datastream: {} chunk has sequence number {}, expected {}",
+                chunk.chunk_type(),
+                actual,
+                sequence_number
+            );
+            sequence_number += 1;
+            Ok(())
+        };
+
+        for chunk in self.chunks() {
+            match &chunk.chunk_type().bytes() {
+                b"fcTL" => {
+                    let control = FrameControl::try_from(chunk)?;
+                    check_sequence(chunk, control.sequence_number)?;
+
+                    if let Some(frame) = frames.last() {
+                        check_frame_data(frames.len() - 1, frame)?;
+                    }
+
+                    frames.push(Frame {
+                        control,
+                        default_image: !seen_idat,
+                        image_data: Vec::new(),
+                    });
+                }
+                b"IDAT" => {
+                    seen_idat = true;
+
+                    if let Some(frame) = frames.last_mut().filter(|f| f.default_image) {
+                        frame.image_data.extend_from_slice(chunk.data());
+                    }
+                }
+                b"fdAT" => {
+                    anyhow::ensure!(
+                        chunk.data().len() >= 4,
+                        "invalid fdAT chunk: expected at least 4 bytes, but received {}",
+                        chunk.data().len()
+                    );
+                    check_sequence(chunk, read_u32(chunk.data(), 0))?;
+
+                    let frame = frames.last_mut().filter(|f| !f.default_image).context(
+                        "invalid APNG datastream: fdAT chunk does not follow an fcTL chunk",
+                    )?;
+                    frame.image_data.extend_from_slice(&chunk.data()[4..]);
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(frame) = frames.last() {
+            check_frame_data(frames.len() - 1, frame)?;
+        }
+
+        anyhow::ensure!(
+            frames.len() == animation.num_frames as usize,
+            "invalid APNG datastream: acTL chunk declares {} frames, but {} fcTL chunks were found",
+            animation.num_frames,
+            frames.len()
+        );
+
+        for (i, frame) in frames.iter().enumerate() {
+            check_frame_region(&ihdr, i, frame)?;
+        }
+
+        Ok(frames)
+    }
+
+    /// Returns the frame at the given index as a standalone, non-animated PNG.
+    ///
+    /// The PNG holds the `IHDR` chunk with the dimensions of the frame, every
+    /// other chunk preceding the image data except those of the animation,
+    /// and the image data of the frame. The frame is not composited onto the
+    /// frames before it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the frames are invalid, or if the index is out of
+    /// bounds.
+    pub fn extract_frame(&self, index: usize) -> Result<PNG> {
+        let frames = self.frames()?;
+        let frame = frames.get(index).with_context(|| {
+            format!(
+                "invalid frame {}: the PNG has {} frames",
+                index,
+                frames.len()
+            )
+        })?;
+
+        frame_png(self, frame)
+    }
+}
+
+/// Placement and timing of a frame added to an animated PNG with
+/// [`assemble`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameOptions {
+    /// Column of the canvas at which the frame is rendered.
+    pub x_offset: u32,
+    /// Row of the canvas at which the frame is rendered.
+    pub y_offset: u32,
+    /// Numerator of the delay after the frame, in seconds.
+    pub delay_num: u16,
+    /// Denominator of the delay after the frame, in seconds.
+    pub delay_den: u16,
+    /// How the frame is disposed of before rendering the next frame.
+    pub dispose_op: DisposeOp,
+    /// How the frame is blended onto the canvas.
+    pub blend_op: BlendOp,
+}
+
+impl Default for FrameOptions {
+    /// Returns the options of a frame at the top-left corner of the canvas,
+    /// shown for 100 milliseconds.
+    fn default() -> Self {
+        FrameOptions {
+            x_offset: 0,
+            y_offset: 0,
+            delay_num: 1,
+            delay_den: 10,
+            dispose_op: DisposeOp::None,
+            blend_op: BlendOp::Source,
+        }
+    }
+}
+
+/// Assembles an animated PNG from the frames, each a standalone PNG, played
+/// `num_plays` times or forever if 0.
+///
+/// The first frame is the default image, which sets the size of the canvas,
+/// and keeps its other chunks. The image data of the other frames is moved
+/// into `fdAT` chunks, and every `fcTL` and `fdAT` chunk is numbered in
+/// sequence.
+///
+/// # Errors
+///
+/// Returns an error if there are no frames, if a frame is already animated,
+/// if the frames differ in bit depth, color type, interlace method, palette,
+/// or transparency, or if a frame does not fit within the canvas.
+pub fn assemble(frames: &[(PNG, FrameOptions)], num_plays: u32) -> Result<PNG> {
+    let (first, _) = frames
+        .first()
+        .context("invalid APNG: at least one frame is required")?;
+    let canvas = first.ihdr()?;
+
+    let num_frames = u32::try_from(frames.len()).context("invalid APNG: too many frames")?;
+    let animation = AnimationControl {
+        num_frames,
+        num_plays,
+    };
+
+    let is_idat = |c: &&Chunk| c.chunk_type().bytes() == *b"IDAT";
+    let first_idat = first
+        .chunks()
+        .iter()
+        .position(|c| is_idat(&c))
+        .context("invalid frame 0: missing IDAT chunk")?;
+
+    let mut chunks: Vec<Chunk> = first.chunks()[..first_idat].to_vec();
+    chunks.insert(
+        1,
+        Chunk::new(ChunkType::from_str("acTL")?, animation.as_bytes().to_vec())?,
+    );
+
+    let mut sequence_number = 0;
+    let fctl = ChunkType::from_str("fcTL")?;
+    let fdat = ChunkType::from_str("fdAT")?;
+
+    for (index, (frame, options)) in frames.iter().enumerate() {
+        let ihdr = frame
+            .ihdr()
+            .with_context(|| format!("invalid frame {index}"))?;
+        check_frame_format(&canvas, first, index, frame)?;
+
+        let control = FrameControl {
+            sequence_number,
+            width: ihdr.width(),
+            height: ihdr.height(),
+            x_offset: options.x_offset,
+            y_offset: options.y_offset,
+            delay_num: options.delay_num,
+            delay_den: options.delay_den,
+            dispose_op: options.dispose_op,
+            blend_op: options.blend_op,
+        };
+        chunks.push(Chunk::new(fctl, control.as_bytes().to_vec())?);
+        sequence_number += 1;
+
+        let idats: Vec<&Chunk> = frame.chunks().iter().filter(is_idat).collect();
+        anyhow::ensure!(
+            !idats.is_empty(),
+            "invalid frame {index}: missing IDAT chunk"
+        );
+
+        if index == 0 {
+            chunks.extend(idats.into_iter().cloned());
+            continue;
+        }
+
+        for idat in idats {
+            let mut data = Vec::with_capacity(4 + idat.data().len());
+            data.extend_from_slice(&sequence_number.to_be_bytes());
+            data.extend_from_slice(idat.data());
+
+            chunks.push(Chunk::new(fdat, data)?);
+            sequence_number += 1;
+        }
+    }
+
+    // Chunks following the image data of the first frame, such as messages,
+    // are kept after the last frame.
+    chunks.extend(
+        first.chunks()[first_idat..]
+            .iter()
+            .filter(|c| !is_idat(c))
+            .cloned(),
+    );
+
+    let png = PNG::from_chunks(chunks);
+    png.frames()?;
+
+    Ok(png)
+}
+
+/// Splits an animated PNG into its frames, each a standalone PNG along with
+/// its frame control.
+///
+/// # Errors
+///
+/// Returns an error if the PNG is not animated, or if its frames are invalid.
+pub fn split(png: &PNG) -> Result<Vec<(PNG, FrameControl)>> {
+    let frames = png.frames()?;
+    anyhow::ensure!(
+        !frames.is_empty(),
+        "not an animated PNG: missing acTL chunk"
+    );
+
+    frames
+        .iter()
+        .map(|frame| Ok((frame_png(png, frame)?, frame.control)))
+        .collect()
+}
+
+/// Checks that a frame can be added to an animated PNG whose first frame is
+/// given.
+fn check_frame_format(canvas: &Ihdr, first: &PNG, index: usize, frame: &PNG) -> Result<()> {
+    let ihdr = frame.ihdr()?;
+
+    anyhow::ensure!(
+        frame.animation_control()?.is_none(),
+        "invalid frame {index}: frame is already an animated PNG"
+    );
+    anyhow::ensure!(
+        (ihdr.bit_depth(), ihdr.color_type(), ihdr.interlace_method())
+            == (
+                canvas.bit_depth(),
+                canvas.color_type(),
+                canvas.interlace_method()
+            ),
+        "invalid frame {index}: {}-bit {} image does not match the {}-bit {} first frame",
+        ihdr.bit_depth(),
+        ihdr.color_type(),
+        canvas.bit_depth(),
+        canvas.color_type()
+    );
+
+    for chunk_type in ["PLTE", "tRNS"] {
+        anyhow::ensure!(
+            frame.chunk_by_type(chunk_type).map(Chunk::data)
+                == first.chunk_by_type(chunk_type).map(Chunk::data),
+            "invalid frame {index}: {chunk_type} chunk does not match the first frame"
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns a frame as a standalone PNG, with the chunks of the PNG preceding
+/// its image data.
+fn frame_png(png: &PNG, frame: &Frame) -> Result<PNG> {
+    let ihdr = png.ihdr()?;
+    let frame_ihdr = Ihdr::new(
+        frame.control.width,
+        frame.control.height,
+        ihdr.bit_depth(),
+        ihdr.color_type(),
+        ihdr.interlace_method(),
+    )?;
+
+    let mut chunks = vec![Chunk::new(
+        ChunkType::from_str("IHDR")?,
+        frame_ihdr.as_bytes().to_vec(),
+    )?];
+
+    chunks.extend(
+        png.chunks()
+            .iter()
+            .skip(1)
+            .take_while(|c| c.chunk_type().bytes() != *b"IDAT")
+            .filter(|c| !ANIMATION_CHUNK_TYPES.contains(&&c.chunk_type().bytes()))
+            .cloned(),
+    );
+
+    let idat = ChunkType::from_str("IDAT")?;
+    for data in frame.image_data.chunks(DEFAULT_CHUNK_SIZE) {
+        chunks.push(Chunk::new(idat, data.to_vec())?);
+    }
+
+    chunks.push(Chunk::new(ChunkType::from_str("IEND")?, Vec::new())?);
+
+    Ok(PNG::from_chunks(chunks))
+}
+
+/// Checks that a frame holds image data.
+fn check_frame_data(index: usize, frame: &Frame) -> Result<()> {
+    anyhow::ensure!(
+        !frame.image_data.is_empty(),
+        "invalid APNG datastream: frame {} has no image data",
+        index
+    );
+
+    Ok(())
+}
+
+/// Checks that the region of a frame lies within the canvas, and that the
+/// default image covers all of it.
+fn check_frame_region(ihdr: &Ihdr, index: usize, frame: &Frame) -> Result<()> {
+    let control = &frame.control;
+    let right = control.x_offset as u64 + control.width as u64;
+    let bottom = control.y_offset as u64 + control.height as u64;
+
+    anyhow::ensure!(
+        right <= ihdr.width() as u64 && bottom <= ihdr.height() as u64,
+        "invalid fcTL chunk: frame {} of {}x{} at ({}, {}) exceeds the {}x{} canvas",
+        index,
+        control.width,
+        control.height,
+        control.x_offset,
+        control.y_offset,
+        ihdr.width(),
+        ihdr.height()
+    );
+    anyhow::ensure!(
+        !frame.default_image
+            || (
+                control.x_offset,
+                control.y_offset,
+                control.width,
+                control.height
+            ) == (0, 0, ihdr.width(), ihdr.height()),
+        "invalid fcTL chunk: frame {} is the default image, but does not cover the full canvas",
+        index
+    );
+
+    Ok(())
+}
+
+/// Returns the data of a chunk of the given type and length.
+fn chunk_data<'a>(chunk: &'a Chunk, chunk_type: &[u8; 4], len: usize) -> Result<&'a [u8]> {
+    let name = String::from_utf8_lossy(chunk_type);
+
+    anyhow::ensure!(
+        chunk.chunk_type().bytes() == *chunk_type,
+        "invalid {} chunk: chunk type is '{}'",
+        name,
+        chunk.chunk_type()
+    );
+    anyhow::ensure!(
+        chunk.data().len() == len,
+        "invalid {} chunk: expected {} bytes, but received {}",
+        name,
+        len,
+        chunk.data().len()
+    );
+
+    Ok(chunk.data())
+}
+
+/// Reads a four-byte unsigned integer at the offset.
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::PngBuilder;
+    use crate::commands;
+    use crate::ihdr::{ColorType, InterlaceMethod};
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data).unwrap()
+    }
+
+    fn frame_control(sequence_number: u32, width: u32, height: u32) -> FrameControl {
+        FrameControl {
+            sequence_number,
+            width,
+            height,
+            x_offset: 0,
+            y_offset: 0,
+            delay_num: 1,
+            delay_den: 10,
+            dispose_op: DisposeOp::None,
+            blend_op: BlendOp::Source,
+        }
+    }
+
+    /// Returns the `IDAT` chunks of a greyscale image filled with the value.
+    fn image_data(width: u32, height: u32, value: u8) -> Vec<Chunk> {
+        let pixels = vec![value; (width * height) as usize];
+        let png = PngBuilder::new(width, height, ColorType::Grayscale, 8, pixels)
+            .build()
+            .unwrap();
+
+        png.chunks_by_type("IDAT").cloned().collect()
+    }
+
+    /// Creates a 4x4 APNG of three frames, the first of which is the default
+    /// image, and the last of which covers a 2x2 region at (1, 2).
+    fn test_apng() -> PNG {
+        let ihdr = Ihdr::new(4, 4, 8, ColorType::Grayscale, InterlaceMethod::None).unwrap();
+        let control = AnimationControl {
+            num_frames: 3,
+            num_plays: 0,
+        };
+
+        let fdat = |sequence_number: u32, idat: &Chunk| {
+            let mut data = sequence_number.to_be_bytes().to_vec();
+            data.extend_from_slice(idat.data());
+            chunk("fdAT", data)
+        };
+
+        let mut last = frame_control(3, 2, 2);
+        last.x_offset = 1;
+        last.y_offset = 2;
+
+        let mut chunks = vec![
+            chunk("IHDR", ihdr.as_bytes().to_vec()),
+            chunk("acTL", control.as_bytes().to_vec()),
+            chunk("gAMA", vec![0, 0, 177, 143]),
+            chunk("fcTL", frame_control(0, 4, 4).as_bytes().to_vec()),
+        ];
+        chunks.extend(image_data(4, 4, 10));
+        chunks.push(chunk("fcTL", frame_control(1, 4, 4).as_bytes().to_vec()));
+        chunks.push(fdat(2, &image_data(4, 4, 20)[0]));
+        chunks.push(chunk("fcTL", last.as_bytes().to_vec()));
+        chunks.push(fdat(4, &image_data(2, 2, 30)[0]));
+        chunks.push(chunk("IEND", Vec::new()));
+
+        PNG::try_from(chunks).unwrap()
+    }
+
+    fn with_chunks(png: &PNG, f: impl FnOnce(&mut Vec<Chunk>)) -> PNG {
+        let mut chunks = png.chunks().to_vec();
+        f(&mut chunks);
+        PNG::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_control_roundtrip() {
+        let control = AnimationControl {
+            num_frames: 7,
+            num_plays: 2,
+        };
+        let parsed = AnimationControl::try_from(&chunk("acTL", control.as_bytes().to_vec()));
+        assert_eq!(parsed.unwrap(), control);
+
+        let mut frame = frame_control(5, 3, 2);
+        frame.dispose_op = DisposeOp::Previous;
+        frame.blend_op = BlendOp::Over;
+        let parsed = FrameControl::try_from(&chunk("fcTL", frame.as_bytes().to_vec()));
+        assert_eq!(parsed.unwrap(), frame);
+    }
+
+    #[test]
+    fn test_invalid_controls() {
+        assert!(AnimationControl::try_from(&chunk("acTL", vec![0; 8])).is_err());
+        assert!(AnimationControl::try_from(&chunk("acTL", vec![0; 7])).is_err());
+        assert!(AnimationControl::try_from(&chunk("fcTL", vec![0, 0, 0, 1, 0, 0, 0, 0])).is_err());
+
+        let mut bytes = frame_control(0, 1, 1).as_bytes();
+        bytes[24] = 3;
+        assert!(FrameControl::try_from(&chunk("fcTL", bytes.to_vec())).is_err());
+
+        let mut bytes = frame_control(0, 1, 1).as_bytes();
+        bytes[25] = 2;
+        assert!(FrameControl::try_from(&chunk("fcTL", bytes.to_vec())).is_err());
+
+        let bytes = frame_control(0, 0, 1).as_bytes();
+        assert!(FrameControl::try_from(&chunk("fcTL", bytes.to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_frame_delay() {
+        let mut control = frame_control(0, 1, 1);
+        assert_eq!(control.delay(), Duration::from_millis(100));
+
+        control.delay_num = 25;
+        control.delay_den = 0;
+        assert_eq!(control.delay(), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_frames() {
+        let png = test_apng();
+        assert_eq!(png.animation_control().unwrap().unwrap().num_frames, 3);
+
+        let frames = png.frames().unwrap();
+        assert_eq!(frames.len(), 3);
+        assert!(frames[0].is_default_image());
+        assert!(!frames[1].is_default_image());
+        assert_eq!(frames[2].control().x_offset, 1);
+        assert_eq!(frames[0].image_data(), image_data(4, 4, 10)[0].data());
+        assert_eq!(frames[2].image_data(), image_data(2, 2, 30)[0].data());
+    }
+
+    #[test]
+    fn test_static_png_has_no_frames() {
+        let png = PngBuilder::new(1, 1, ColorType::Grayscale, 8, vec![0])
+            .build()
+            .unwrap();
+
+        assert_eq!(png.animation_control().unwrap(), None);
+        assert!(png.frames().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_default_image_outside_animation() {
+        // Without an fcTL chunk before the image data, the default image is
+        // not a frame.
+        let png = with_chunks(&test_apng(), |chunks| {
+            chunks.remove(3);
+            chunks[1] = chunk("acTL", vec![0, 0, 0, 2, 0, 0, 0, 0]);
+        });
+
+        assert!(png.frames().is_err(), "sequence must restart at 0");
+
+        let png = with_chunks(&png, |chunks| {
+            for chunk in chunks.iter_mut() {
+                let mut data = chunk.data().to_vec();
+                match &chunk.chunk_type().bytes() {
+                    b"fcTL" | b"fdAT" => {
+                        let n = read_u32(&data, 0) - 1;
+                        data[..4].copy_from_slice(&n.to_be_bytes());
+                    }
+                    _ => continue,
+                }
+                *chunk = Chunk::new(chunk.chunk_type(), data).unwrap();
+            }
+        });
+
+        let frames = png.frames().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert!(frames.iter().all(|f| !f.is_default_image()));
+    }
+
+    #[test]
+    fn test_invalid_sequence() {
+        let apng = test_apng();
+
+        // Swapping the last fcTL and fdAT chunks breaks the sequence.
+        let png = with_chunks(&apng, |chunks| chunks.swap(7, 8));
+        assert!(png.frames().is_err());
+
+        // A missing fdAT chunk leaves a gap in the sequence.
+        let png = with_chunks(&apng, |chunks| {
+            chunks.remove(6);
+        });
+        assert!(png.frames().is_err());
+
+        // The number of frames must match the acTL chunk.
+        let png = with_chunks(&apng, |chunks| {
+            chunks[1] = chunk("acTL", vec![0, 0, 0, 4, 0, 0, 0, 0]);
+        });
+        assert!(png.frames().is_err());
+
+        // Frames must lie within the canvas.
+        let png = with_chunks(&apng, |chunks| {
+            let mut control = frame_control(3, 2, 2);
+            control.x_offset = 3;
+            chunks[7] = chunk("fcTL", control.as_bytes().to_vec());
+        });
+        assert!(png.frames().is_err());
+
+        // Frame chunks without an acTL chunk are invalid.
+        let png = with_chunks(&apng, |chunks| {
+            chunks.remove(1);
+        });
+        assert!(png.frames().is_err());
+    }
+
+    #[test]
+    fn test_extract_frame() {
+        let png = test_apng();
+
+        for (index, (size, value)) in [(4, 10), (4, 20), (2, 30)].into_iter().enumerate() {
+            let frame = png.extract_frame(index).unwrap();
+            let bytes = frame.as_bytes();
+            let frame = PNG::try_from(bytes.as_slice()).unwrap();
+
+            assert!(frame.violations().is_empty());
+            assert!(frame.animation_control().unwrap().is_none());
+            assert!(frame.chunk_by_type("gAMA").is_some());
+            assert_eq!(frame.ihdr().unwrap().width(), size);
+            assert_eq!(
+                frame.scanlines().unwrap(),
+                vec![value; (size * size) as usize]
+            );
+        }
+
+        assert!(png.extract_frame(3).is_err());
+    }
+
+    #[test]
+    fn test_embedding_keeps_animation() {
+        let mut png = test_apng();
+        let frames = png.frames().unwrap();
+
+        commands::encode(&mut png, "ruSt", "hidden message").unwrap();
+        commands::encode_fragmented(&mut png, "ruSt", [7; 100], 16).unwrap();
+
+        assert_eq!(png.frames().unwrap(), frames);
+        assert_eq!(
+            png.chunks().last().unwrap().chunk_type().to_string(),
+            "IEND"
+        );
+        assert_eq!(
+            commands::decode(&png, "ruSt").as_deref(),
+            Some("hidden message")
+        );
+
+        commands::remove(&mut png, "ruSt").unwrap();
+        assert_eq!(png.frames().unwrap(), frames);
+    }
+
+    /// Creates a standalone greyscale PNG filled with the value.
+    fn frame(width: u32, height: u32, value: u8) -> PNG {
+        let pixels = vec![value; (width * height) as usize];
+
+        PngBuilder::new(width, height, ColorType::Grayscale, 8, pixels)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_assemble() {
+        let mut first = frame(4, 4, 10);
+        commands::encode(&mut first, "ruSt", "kept").unwrap();
+
+        let options = FrameOptions {
+            x_offset: 1,
+            y_offset: 2,
+            delay_num: 1,
+            delay_den: 30,
+            dispose_op: DisposeOp::Previous,
+            blend_op: BlendOp::Over,
+        };
+        let png = assemble(
+            &[
+                (first, FrameOptions::default()),
+                (frame(4, 4, 20), FrameOptions::default()),
+                (frame(2, 2, 30), options),
+            ],
+            3,
+        )
+        .unwrap();
+
+        let png = PNG::try_from(png.as_bytes().as_slice()).unwrap();
+        assert!(png.violations().is_empty());
+        assert_eq!(
+            png.animation_control().unwrap(),
+            Some(AnimationControl {
+                num_frames: 3,
+                num_plays: 3
+            })
+        );
+        assert_eq!(commands::decode(&png, "ruSt").as_deref(), Some("kept"));
+
+        let sequence: Vec<u32> = png
+            .chunks()
+            .iter()
+            .filter(|c| ANIMATION_CHUNK_TYPES[1..].contains(&&c.chunk_type().bytes()))
+            .map(|c| read_u32(c.data(), 0))
+            .collect();
+        assert_eq!(sequence, [0, 1, 2, 3, 4]);
+
+        let frames = png.frames().unwrap();
+        assert!(frames[0].is_default_image());
+        assert_eq!(frames[2].control().x_offset, 1);
+        assert_eq!(frames[2].control().blend_op, BlendOp::Over);
+        assert_eq!(png.extract_frame(2).unwrap().scanlines().unwrap(), [30; 4]);
+    }
+
+    #[test]
+    fn test_split_and_reassemble() {
+        let apng = test_apng();
+
+        let frames = split(&apng).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[2].1.y_offset, 2);
+
+        let frames: Vec<_> = frames
+            .into_iter()
+            .map(|(png, control)| {
+                let options = FrameOptions {
+                    x_offset: control.x_offset,
+                    y_offset: control.y_offset,
+                    delay_num: control.delay_num,
+                    delay_den: control.delay_den,
+                    dispose_op: control.dispose_op,
+                    blend_op: control.blend_op,
+                };
+                (png, options)
+            })
+            .collect();
+
+        let png = assemble(&frames, 0).unwrap();
+        assert_eq!(png.frames().unwrap(), apng.frames().unwrap());
+
+        assert!(split(&frame(1, 1, 0)).is_err());
+    }
+
+    #[test]
+    fn test_assemble_invalid_frames() {
+        let options = FrameOptions::default();
+
+        assert!(assemble(&[], 0).is_err());
+
+        // Frames must share the format of the first frame.
+        let truecolor = PngBuilder::new(4, 4, ColorType::Truecolor, 8, vec![0; 48])
+            .build()
+            .unwrap();
+        assert!(assemble(&[(frame(4, 4, 0), options), (truecolor, options)], 0).is_err());
+
+        let indexed = |entry: u8| {
+            PngBuilder::new(1, 1, ColorType::Indexed, 8, vec![0])
+                .palette(vec![[entry, 0, 0, 255]])
+                .build()
+                .unwrap()
+        };
+        assert!(assemble(&[(indexed(0), options), (indexed(0), options)], 0).is_ok());
+        assert!(assemble(&[(indexed(0), options), (indexed(1), options)], 0).is_err());
+
+        // Frames must fit within the canvas set by the first frame.
+        assert!(assemble(&[(frame(2, 2, 0), options), (frame(4, 4, 0), options)], 0).is_err());
+
+        let offset = FrameOptions {
+            x_offset: 3,
+            ..options
+        };
+        assert!(assemble(&[(frame(4, 4, 0), options), (frame(2, 2, 0), offset)], 0).is_err());
+        assert!(assemble(&[(frame(4, 4, 0), offset)], 0).is_err());
+
+        // Frames must not already be animated.
+        assert!(assemble(&[(test_apng(), options)], 0).is_err());
+    }
+
+    #[test]
+    fn test_parse_ops() {
+        for op in [DisposeOp::None, DisposeOp::Background, DisposeOp::Previous] {
+            assert_eq!(DisposeOp::from_str(&op.to_string()).unwrap(), op);
+        }
+        for op in [BlendOp::Source, BlendOp::Over] {
+            assert_eq!(BlendOp::from_str(&op.to_string()).unwrap(), op);
+        }
+
+        assert!(DisposeOp::from_str("over").is_err());
+        assert!(BlendOp::from_str("none").is_err());
+    }
+}
@@ -0,0 +1,424 @@
+//! Construction of PNGs from raw pixel data.
+//!
+//! A [`PngBuilder`] takes the dimensions, color type, and bit depth of an
+//! image along with its pixels, and produces a [`PNG`] holding an `IHDR`
+//! chunk, the optional `PLTE` and `tRNS` chunks, the filtered and compressed
+//! image data in `IDAT` chunks, and an `IEND` chunk.
+//!
+//! The pixels are given as the scanlines of the full image, in the layout
+//! returned by [`PNG::scanlines`]: samples are stored most significant bit
+//! first, pixels of less than 8 bits are packed into bytes, and each scanline
+//! starts on a byte boundary, without a filter type byte.
+
+use std::str::FromStr;
+
+use anyhow::Result;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::filter::FilterStrategy;
+use crate::idat::DeflateOptions;
+use crate::ihdr::{ColorType, Ihdr, InterlaceMethod};
+use crate::png::PNG;
+
+/// Builder of a PNG from raw pixel data.
+///
+/// # Examples
+///
+/// ```
+/// use pngme::PngBuilder;
+/// use pngme::ihdr::ColorType;
+///
+/// // A 2x1 image of a red and a half-transparent blue pixel.
+/// let png = PngBuilder::new(2, 1, ColorType::Indexed, 8, vec![0, 1])
+///     .palette(vec![[255, 0, 0, 255], [0, 0, 255, 128]])
+///     .build()?;
+///
+/// let image = png.decode_pixels()?;
+/// assert_eq!(image.to_rgba8(), [[255, 0, 0, 255], [0, 0, 255, 128]]);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct PngBuilder {
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+    bit_depth: u8,
+    pixels: Vec<u8>,
+    interlace_method: InterlaceMethod,
+    palette: Option<Vec<[u8; 4]>>,
+    transparent_color: Option<Vec<u16>>,
+    strategy: FilterStrategy,
+    options: DeflateOptions,
+}
+
+impl PngBuilder {
+    /// Creates a builder of a non-interlaced image with the given dimensions,
+    /// color type, bit depth, and scanlines.
+    pub fn new(
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+        bit_depth: u8,
+        pixels: impl Into<Vec<u8>>,
+    ) -> Self {
+        PngBuilder {
+            width,
+            height,
+            color_type,
+            bit_depth,
+            pixels: pixels.into(),
+            interlace_method: InterlaceMethod::None,
+            palette: None,
+            transparent_color: None,
+            strategy: FilterStrategy::default(),
+            options: DeflateOptions::default(),
+        }
+    }
+
+    /// Sets the interlace method of the image data.
+    pub fn interlace_method(mut self, interlace_method: InterlaceMethod) -> Self {
+        self.interlace_method = interlace_method;
+        self
+    }
+
+    /// Sets the RGBA entries of the palette, stored in a `PLTE` chunk.
+    ///
+    /// The palette is required for indexed-color images, where the alpha of
+    /// the entries is stored in a `tRNS` chunk unless every entry is fully
+    /// opaque. Truecolor images may hold a suggested palette of opaque
+    /// entries.
+    pub fn palette(mut self, entries: impl Into<Vec<[u8; 4]>>) -> Self {
+        self.palette = Some(entries.into());
+        self
+    }
+
+    /// Sets the color of greyscale or truecolor pixels which are fully
+    /// transparent, stored in a `tRNS` chunk, given as one sample for
+    /// greyscale images or three for truecolor images.
+    pub fn transparent_color(mut self, color: impl Into<Vec<u16>>) -> Self {
+        self.transparent_color = Some(color.into());
+        self
+    }
+
+    /// Sets the strategy used to choose the filter type of each scanline.
+    pub fn filter_strategy(mut self, strategy: FilterStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Sets the options used to compress the image data.
+    pub fn deflate_options(mut self, options: DeflateOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Builds the PNG.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the dimensions, color type, and bit depth do not
+    /// form a valid `IHDR` chunk, if the length of the pixels does not match
+    /// it, if the palette or transparent color is missing or not allowed for
+    /// the image, or if a pixel references a palette entry which does not
+    /// exist.
+    pub fn build(self) -> Result<PNG> {
+        let ihdr = Ihdr::new(
+            self.width,
+            self.height,
+            self.bit_depth,
+            self.color_type,
+            self.interlace_method,
+        )?;
+
+        let mut chunks = vec![chunk("IHDR", ihdr.as_bytes().to_vec())?];
+
+        if let Some(palette) = &self.palette {
+            chunks.push(chunk(
+                "PLTE",
+                palette.iter().flat_map(|e| [e[0], e[1], e[2]]).collect(),
+            )?);
+        }
+        ihdr.check_palette(chunks.get(1))?;
+
+        if let Some(trns) = self.transparency(&ihdr)? {
+            chunks.push(chunk("tRNS", trns)?);
+        }
+        if let Some(palette) = &self.palette
+            && ihdr.color_type() == ColorType::Indexed
+        {
+            check_indices(&ihdr, &self.pixels, palette.len())?;
+        }
+
+        chunks.push(chunk("IEND", Vec::new())?);
+
+        let mut png = PNG::from_chunks(chunks);
+        png.set_scanlines(&self.pixels, self.strategy, &self.options)?;
+
+        Ok(png)
+    }
+
+    /// Returns the data of the `tRNS` chunk, if the image needs one.
+    fn transparency(&self, ihdr: &Ihdr) -> Result<Option<Vec<u8>>> {
+        let color_type = ihdr.color_type();
+
+        if let Some(palette) = &self.palette {
+            let opaque = palette
+                .iter()
+                .rposition(|e| e[3] != 255)
+                .map_or(0, |i| i + 1);
+
+            anyhow::ensure!(
+                opaque == 0 || color_type == ColorType::Indexed,
+                "invalid palette: entries must be fully opaque for color type {} ({})",
+                color_type as u8,
+                color_type
+            );
+
+            if opaque > 0 {
+                return Ok(Some(palette[..opaque].iter().map(|e| e[3]).collect()));
+            }
+        }
+
+        let Some(color) = &self.transparent_color else {
+            return Ok(None);
+        };
+
+        let channels = match color_type {
+            ColorType::Grayscale => 1,
+            ColorType::Truecolor => 3,
+            _ => anyhow::bail!(
+                "invalid transparent color: not allowed for color type {} ({})",
+                color_type as u8,
+                color_type
+            ),
+        };
+        let max = ((1u32 << ihdr.bit_depth()) - 1) as u16;
+
+        anyhow::ensure!(
+            color.len() == channels,
+            "invalid transparent color: expected {} samples for color type {}, but received {}",
+            channels,
+            color_type,
+            color.len()
+        );
+        anyhow::ensure!(
+            color.iter().all(|&sample| sample <= max),
+            "invalid transparent color: samples must not exceed {} for bit depth {}",
+            max,
+            ihdr.bit_depth()
+        );
+
+        Ok(Some(color.iter().flat_map(|s| s.to_be_bytes()).collect()))
+    }
+}
+
+/// Creates a chunk of the given type.
+fn chunk(chunk_type: &str, data: Vec<u8>) -> Result<Chunk> {
+    Ok(Chunk::new(ChunkType::from_str(chunk_type)?, data)?)
+}
+
+/// Checks that every palette index of the scanlines references an entry of
+/// the palette.
+fn check_indices(ihdr: &Ihdr, pixels: &[u8], entries: usize) -> Result<()> {
+    let depth = ihdr.bit_depth() as usize;
+    let row_len = ihdr.scanline_len(ihdr.width());
+
+    for (y, row) in pixels.chunks(row_len).enumerate() {
+        for x in 0..(ihdr.width() as usize).min(row.len() * 8 / depth) {
+            let offset = x * depth;
+            let shift = 8 - depth - offset % 8;
+            let index = ((row[offset / 8] >> shift) & ((1u16 << depth) - 1) as u8) as usize;
+
+            anyhow::ensure!(
+                index < entries,
+                "invalid pixels: palette index {} of pixel ({}, {}) exceeds the {} entries of the palette",
+                index,
+                x,
+                y,
+                entries
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixels::Pixels;
+
+    #[test]
+    fn test_build_valid_png() {
+        let png = PngBuilder::new(3, 2, ColorType::Truecolor, 8, (0..18).collect::<Vec<u8>>())
+            .build()
+            .unwrap();
+
+        let types: Vec<_> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "IDAT", "IEND"]);
+
+        let parsed = PNG::try_from(png.as_bytes().as_slice()).unwrap();
+        assert!(parsed.violations().is_empty());
+        assert_eq!(parsed.scanlines().unwrap(), (0..18).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_build_every_format() {
+        for (color_type, bit_depth) in [
+            (ColorType::Grayscale, 1),
+            (ColorType::Grayscale, 2),
+            (ColorType::Grayscale, 4),
+            (ColorType::Grayscale, 8),
+            (ColorType::Grayscale, 16),
+            (ColorType::Truecolor, 8),
+            (ColorType::Truecolor, 16),
+            (ColorType::GrayscaleAlpha, 8),
+            (ColorType::GrayscaleAlpha, 16),
+            (ColorType::TruecolorAlpha, 8),
+            (ColorType::TruecolorAlpha, 16),
+        ] {
+            for interlace_method in [InterlaceMethod::None, InterlaceMethod::Adam7] {
+                let ihdr = Ihdr::new(11, 7, bit_depth, color_type, interlace_method).unwrap();
+                let pixels: Vec<u8> = (0..ihdr.scanline_len(11) * 7)
+                    .map(|i| (i * 89 % 256) as u8)
+                    .collect();
+
+                let png = PngBuilder::new(11, 7, color_type, bit_depth, pixels.clone())
+                    .interlace_method(interlace_method)
+                    .build()
+                    .unwrap();
+
+                assert_eq!(png.ihdr().unwrap(), ihdr);
+                assert_eq!(
+                    PNG::try_from(png.as_bytes().as_slice())
+                        .unwrap()
+                        .decode_pixels()
+                        .unwrap()
+                        .width(),
+                    11
+                );
+
+                // Padding bits at the end of each scanline are not preserved
+                // by interlacing.
+                let mut expected = pixels;
+                if interlace_method == InterlaceMethod::Adam7 {
+                    let padding = ihdr.scanline_len(11) * 8 - 11 * ihdr.bits_per_pixel();
+                    let mask = !((1u16 << padding) - 1) as u8;
+                    for row in expected.chunks_exact_mut(ihdr.scanline_len(11)) {
+                        *row.last_mut().unwrap() &= mask;
+                    }
+                }
+                assert_eq!(png.scanlines().unwrap(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_build_indexed() {
+        let png = PngBuilder::new(4, 1, ColorType::Indexed, 2, vec![0b0001_1011])
+            .palette(vec![
+                [255, 0, 0, 0],
+                [0, 255, 0, 128],
+                [0, 0, 255, 255],
+                [9, 9, 9, 255],
+            ])
+            .build()
+            .unwrap();
+
+        assert_eq!(png.chunk_by_type("PLTE").unwrap().data().len(), 12);
+        // Trailing opaque entries are omitted from the tRNS chunk.
+        assert_eq!(png.chunk_by_type("tRNS").unwrap().data(), [0, 128]);
+        assert_eq!(
+            png.decode_pixels().unwrap().into_pixels(),
+            Pixels::Rgba8(vec![
+                [255, 0, 0, 0],
+                [0, 255, 0, 128],
+                [0, 0, 255, 255],
+                [9, 9, 9, 255]
+            ])
+        );
+
+        let opaque = PngBuilder::new(1, 1, ColorType::Indexed, 8, vec![0])
+            .palette(vec![[1, 2, 3, 255]])
+            .build()
+            .unwrap();
+        assert!(opaque.chunk_by_type("tRNS").is_none());
+    }
+
+    #[test]
+    fn test_build_transparent_color() {
+        let png = PngBuilder::new(2, 1, ColorType::Grayscale, 16, vec![0, 1, 0, 2])
+            .transparent_color(vec![2])
+            .build()
+            .unwrap();
+        assert_eq!(png.chunk_by_type("tRNS").unwrap().data(), [0, 2]);
+        assert_eq!(
+            png.decode_pixels().unwrap().into_pixels(),
+            Pixels::Rgba16(vec![[1, 1, 1, 0xffff], [2, 2, 2, 0]])
+        );
+
+        let png = PngBuilder::new(1, 1, ColorType::Truecolor, 8, vec![1, 2, 3])
+            .transparent_color(vec![1, 2, 3])
+            .build()
+            .unwrap();
+        assert_eq!(png.decode_pixels().unwrap().to_rgba8(), [[1, 2, 3, 0]]);
+    }
+
+    #[test]
+    fn test_build_invalid() {
+        let build = |builder: PngBuilder| builder.build().is_err();
+
+        assert!(build(PngBuilder::new(
+            0,
+            1,
+            ColorType::Grayscale,
+            8,
+            vec![]
+        )));
+        assert!(build(PngBuilder::new(
+            1,
+            1,
+            ColorType::Truecolor,
+            4,
+            vec![0]
+        )));
+        assert!(build(PngBuilder::new(
+            2,
+            2,
+            ColorType::Grayscale,
+            8,
+            vec![0; 3]
+        )));
+        assert!(build(PngBuilder::new(1, 1, ColorType::Indexed, 8, vec![0])));
+        assert!(build(
+            PngBuilder::new(2, 1, ColorType::Indexed, 8, vec![0, 1]).palette(vec![[0, 0, 0, 255]])
+        ));
+        assert!(build(
+            PngBuilder::new(1, 1, ColorType::Grayscale, 8, vec![0]).palette(vec![[0, 0, 0, 255]])
+        ));
+        assert!(build(
+            PngBuilder::new(1, 1, ColorType::Truecolor, 8, vec![0; 3]).palette(vec![[0, 0, 0, 0]])
+        ));
+        assert!(build(
+            PngBuilder::new(1, 1, ColorType::Grayscale, 4, vec![0]).transparent_color(vec![16])
+        ));
+        assert!(build(
+            PngBuilder::new(1, 1, ColorType::Truecolor, 8, vec![0; 3]).transparent_color(vec![0])
+        ));
+        assert!(build(
+            PngBuilder::new(1, 1, ColorType::GrayscaleAlpha, 8, vec![0; 2])
+                .transparent_color(vec![0])
+        ));
+
+        // A suggested palette of opaque entries is allowed for truecolor.
+        assert!(!build(
+            PngBuilder::new(1, 1, ColorType::Truecolor, 8, vec![0; 3])
+                .palette(vec![[0, 0, 0, 255]])
+        ));
+    }
+}
@@ -22,8 +22,7 @@ use std::convert::TryFrom;
 use std::{fmt, mem, result};
 
 use crate::chunk_type::ChunkType;
-
-use anyhow::Result;
+use crate::error::{Error, Result};
 
 /// Table of CRCs of all 8-bit messages, computed at compile-time.
 const CRC_TABLE: [u32; 256] = precompute_crc_table();
@@ -54,9 +53,38 @@ const fn precompute_crc_table() -> [u32; 256] {
     table
 }
 
+/// Running CRC (Cyclic Redundancy Check) of a chunk, for when the chunk type
+/// and chunk data are not available all at once.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Crc {
+    state: u32,
+}
+
+impl Crc {
+    /// Creates a new CRC with no bytes processed.
+    #[inline]
+    pub(crate) const fn new() -> Self {
+        Crc { state: 0xFFFFFFFF }
+    }
+
+    /// Updates the CRC with the provided bytes.
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            // Use precomputed CRC table for fast check.
+            self.state = CRC_TABLE[((self.state ^ *b as u32) & 0xFF) as usize] ^ (self.state >> 8);
+        }
+    }
+
+    /// Returns the CRC of all bytes processed so far.
+    #[inline]
+    pub(crate) const fn finalize(&self) -> u32 {
+        self.state ^ 0xFFFFFFFF
+    }
+}
+
 /// A single chunk within a PNG datastream.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub(crate) struct Chunk {
+pub struct Chunk {
     length: u32,
     chunk_type: ChunkType,
     data: Vec<u8>,
@@ -68,13 +96,12 @@ impl Chunk {
     ///
     /// # Errors
     ///
-    /// Returns an error if the data's length in bytes exceeds [`i32::MAX`].
-    pub(crate) fn new(chunk_type: ChunkType, data: Vec<u8>) -> Result<Chunk> {
-        anyhow::ensure!(
-            data.len() <= i32::MAX as usize,
-            "invalid PNG chunk: data length must not exceed i32::MAX bytes, but received: {}",
-            data.len()
-        );
+    /// Returns [`Error::DataTooLong`] if the data's length in bytes exceeds
+    /// [`i32::MAX`].
+    pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Result<Chunk> {
+        if data.len() > i32::MAX as usize {
+            return Err(Error::DataTooLong { length: data.len() });
+        }
 
         let crc = Chunk::compute_crc(chunk_type.bytes(), &data);
 
@@ -87,39 +114,54 @@ impl Chunk {
         })
     }
 
+    /// Creates a PNG chunk from parts whose CRC has already been verified
+    /// against the chunk type and data.
+    pub(crate) fn from_verified_parts(chunk_type: ChunkType, data: Vec<u8>, crc: u32) -> Chunk {
+        debug_assert!(data.len() <= i32::MAX as usize);
+        debug_assert_eq!(crc, Chunk::compute_crc(chunk_type.bytes(), &data));
+
+        Chunk {
+            // SAFETY: Verified chunks are read with a length <= i32::MAX.
+            length: data.len() as u32,
+            chunk_type,
+            data,
+            crc,
+        }
+    }
+
     /// Returns the length of the chunk data in bytes.
     #[inline]
-    pub(crate) const fn length(&self) -> u32 {
+    pub const fn length(&self) -> u32 {
         self.length
     }
 
     /// Returns the `ChunkType` of the chunk.
     #[inline]
-    pub(crate) const fn chunk_type(&self) -> ChunkType {
+    pub const fn chunk_type(&self) -> ChunkType {
         self.chunk_type
     }
 
     /// Returns a shared reference to the chunk data.
     #[inline]
-    pub(crate) fn data(&self) -> &[u8] {
+    pub fn data(&self) -> &[u8] {
         &self.data
     }
 
     /// Returns the CRC of the chunk.
     #[inline]
-    pub(crate) const fn crc(&self) -> u32 {
+    pub const fn crc(&self) -> u32 {
         self.crc
     }
 
     /// Returns the size in bytes of the chunk.
     #[inline]
-    pub(crate) const fn size(&self) -> usize {
+    pub const fn size(&self) -> usize {
         mem::size_of::<u32>() * 2 + mem::size_of::<ChunkType>() + self.length() as usize
     }
 
     /// Returns the memory representation of the chunk as a byte array in
     /// big-endian (network) byte order.
-    pub(crate) fn as_bytes(&self) -> Vec<u8> {
+    pub fn as_bytes(&self) -> Vec<u8> {
         let chunk_size = self.size();
 
         let mut bytes = Vec::with_capacity(chunk_size);
@@ -153,41 +195,43 @@ impl Chunk {
     /// Returns the computed CRC (Cyclic Redundancy Check) of the chunk using
     /// the `ChunkType` and chunk data bytes.
     fn compute_crc(chunk_ty: [u8; 4], data: &[u8]) -> u32 {
-        let mut crc: u32 = 0xFFFFFFFF;
+        let mut crc = Crc::new();
 
-        for b in chunk_ty.iter().chain(data.iter()) {
-            // Use precomputed CRC table for fast check.
-            crc = CRC_TABLE[((crc ^ *b as u32) & 0xFF) as usize] ^ (crc >> 8);
-        }
+        crc.update(&chunk_ty);
+        crc.update(data);
 
-        crc ^ 0xFFFFFFFF
+        crc.finalize()
     }
 }
 
 impl TryFrom<&[u8]> for Chunk {
-    type Error = anyhow::Error;
+    type Error = Error;
 
+    /// Parses a chunk from its bytes, which must hold exactly one chunk.
+    /// Offsets in errors are relative to the start of the bytes, and the chunk
+    /// is reported as index 0.
     fn try_from(bytes: &[u8]) -> result::Result<Self, Self::Error> {
         let chunk_len = bytes.len();
 
         // Should contain at least the length, chunk type, and CRC, which totals
         // to 12 bytes.
-        anyhow::ensure!(
-            chunk_len >= 12,
-            "invalid PNG chunk: input must be at least 12 bytes, but received: {}",
-            chunk_len
-        );
+        if chunk_len < 12 {
+            return Err(Error::Truncated {
+                offset: 0,
+                index: 0,
+            });
+        }
 
         let length = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
         let chunk_type = ChunkType::try_from([bytes[4], bytes[5], bytes[6], bytes[7]])?;
         let data = &bytes[8..chunk_len - 4];
 
-        anyhow::ensure!(
-            length as usize == data.len(),
-            "invalid PNG chunk: chunk length does not match length of chunk data: expected {}, but received {}",
-            length,
-            data.len()
-        );
+        if length as usize != data.len() {
+            return Err(Error::LengthMismatch {
+                expected: length,
+                actual: data.len(),
+            });
+        }
 
         let crc = u32::from_be_bytes([
             bytes[chunk_len - 4],
@@ -198,12 +242,14 @@ impl TryFrom<&[u8]> for Chunk {
 
         let computed_crc = Chunk::compute_crc(chunk_type.bytes(), data);
 
-        anyhow::ensure!(
-            crc == computed_crc,
-            "invalid PNG chunk: CRC verification fail: expected 0x{:x}, computed 0x{:x}",
-            crc,
-            computed_crc
-        );
+        if crc != computed_crc {
+            return Err(Error::CrcMismatch {
+                expected: crc,
+                computed: computed_crc,
+                offset: 0,
+                index: 0,
+            });
+        }
 
         Ok(Chunk {
             length,
@@ -338,7 +384,25 @@ mod tests {
 
         let chunk = Chunk::try_from(chunk_data.as_ref());
 
-        assert!(chunk.is_err());
+        assert!(matches!(
+            chunk,
+            Err(Error::CrcMismatch {
+                expected: 2882656333,
+                computed: 2882656334,
+                offset: 0,
+                index: 0,
+            })
+        ));
+    }
+
+    #[test]
+    fn test_truncated_chunk_from_bytes() {
+        let chunk_bytes = generate_chunk().as_bytes();
+
+        for len in 0..chunk_bytes.len() {
+            let chunk = Chunk::try_from(&chunk_bytes[..len]);
+            assert!(chunk.is_err(), "accepted chunk truncated to {len} bytes");
+        }
     }
 
     #[test]
@@ -20,55 +20,57 @@ use std::convert::TryFrom;
 use std::str::{self, FromStr};
 use std::{fmt, result};
 
+use crate::error::Error;
+
 /// Chunk type of a chunk within a PNG datastream.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub(crate) struct ChunkType {
+pub struct ChunkType {
     inner: [u8; 4],
 }
 
 impl ChunkType {
     /// Returns the raw bytes of the chunk type.
     #[inline]
-    pub(crate) const fn bytes(&self) -> [u8; 4] {
+    pub const fn bytes(&self) -> [u8; 4] {
         self.inner
     }
 
     /// Returns `true` if the chunk type is valid.
     #[inline]
-    pub(crate) fn is_valid(&self) -> bool {
+    pub fn is_valid(&self) -> bool {
         // Other validation occurs when constructing the `ChunkType`.
         self.is_reserved_bit_valid()
     }
 
     /// Returns `true` if the chunk type has the `critical` property bit set.
     #[inline]
-    pub(crate) fn is_critical(&self) -> bool {
+    pub fn is_critical(&self) -> bool {
         ((self.inner[0] >> 5) & 0x01) == 0
     }
 
     /// Returns `true` if the chunk type has the `public` property bit set.
     #[inline]
-    pub(crate) fn is_public(&self) -> bool {
+    pub fn is_public(&self) -> bool {
         ((self.inner[1] >> 5) & 0x01) == 0
     }
 
     /// Returns `true` if the chunk type has a valid reserved bit according to  
     /// version 3.0 of the PNG specification.
     #[inline]
-    pub(crate) fn is_reserved_bit_valid(&self) -> bool {
+    pub fn is_reserved_bit_valid(&self) -> bool {
         ((self.inner[2] >> 5) & 0x01) == 0
     }
 
     /// Returns `true` if the chunk type has the `safe-to-copy` property bit
     /// set.
     #[inline]
-    pub(crate) fn is_safe_to_copy(&self) -> bool {
+    pub fn is_safe_to_copy(&self) -> bool {
         ((self.inner[3] >> 5) & 0x01) == 1
     }
 }
 
 impl TryFrom<[u8; 4]> for ChunkType {
-    type Error = anyhow::Error;
+    type Error = Error;
 
     fn try_from(bytes: [u8; 4]) -> result::Result<Self, Self::Error> {
         // ASCII uppercase ('A' - 'Z') -> 0b01000001 - 0b01011010
@@ -79,7 +81,9 @@ impl TryFrom<[u8; 4]> for ChunkType {
             // Clear the 5th bit so the byte can be normalized.
             let upper = b & !0x20; // 0b1101_1111
             if !upper.is_ascii_uppercase() {
-                anyhow::bail!("invalid PNG chunk type: invalid byte '{}'", b as char)
+                return Err(Error::InvalidChunkType {
+                    bytes: bytes.to_vec(),
+                });
             }
         }
 
@@ -88,14 +92,14 @@ impl TryFrom<[u8; 4]> for ChunkType {
 }
 
 impl FromStr for ChunkType {
-    type Err = anyhow::Error;
+    type Err = Error;
 
     fn from_str(s: &str) -> result::Result<Self, Self::Err> {
-        anyhow::ensure!(
-            s.len() == 4,
-            "invalid PNG chunk type: must be exactly 4 bytes: \"{}\"",
-            s
-        );
+        if s.len() != 4 {
+            return Err(Error::InvalidChunkType {
+                bytes: s.as_bytes().to_vec(),
+            });
+        }
 
         let mut iter = s.bytes();
         ChunkType::try_from(std::array::from_fn(|_| {
@@ -201,6 +205,21 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_invalid_chunk_type_error() {
+        let err = ChunkType::from_str("Ru1t").unwrap_err();
+        assert!(matches!(&err, Error::InvalidChunkType { bytes } if bytes == b"Ru1t"));
+        assert_eq!(
+            err.to_string(),
+            "invalid PNG chunk type: \"Ru1t\": must be exactly 4 ASCII letters"
+        );
+
+        assert!(matches!(
+            ChunkType::from_str("RuStY"),
+            Err(Error::InvalidChunkType { .. })
+        ));
+    }
+
     #[test]
     fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -1,68 +1,575 @@
-//! Subcommands for the `pngme` program.
+//! Operations for embedding, retrieving, and removing messages in PNG files.
+//!
+//! [`encode`], [`decode`], and [`remove`] operate on an in-memory [`PNG`],
+//! storing messages in private chunks, while [`encode_text`], [`decode_text`],
+//! and [`remove_text`] store messages in textual chunks identified by keyword.
+//! [`encode_encrypted`] and [`decode_encrypted`] store messages encrypted with
+//! a passphrase or to X25519 recipients in private chunks. [`sign`] and
+//! [`verify`] attach and check Ed25519 signatures over message chunks.
+//! [`encode_payload`] and [`decode_payload`] store binary payloads along with
+//! their original filename and MIME type, fragmenting them across several
+//! chunks if they are large, while [`encode_fragmented`] fragments a message
+//! explicitly.
+//! [`lsb`] hides messages in the image data itself instead.
+//! The `invoke_*` functions read and write PNG files on disk and back
+//! the subcommands of the `pngme` program, where the path `-` stands for
+//! standard input or standard output. They refuse to overwrite existing files
+//! unless forced, and an [`Output`] can replace the input file in place
+//! atomically instead. Violations of the chunk ordering
+//! rules tolerated by [`Validation::Lenient`] are reported as warnings on
+//! standard error, and files which can not be read as a PNG are reported with
+//! an [`InvalidPngError`].
 
+use std::fmt;
 use std::fs;
-use std::io::{self, BufReader, Cursor, Read};
+use std::io::{self, BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
+use std::process;
 use std::str::FromStr;
 
+use crate::apng::{self, FrameControl, FrameOptions};
 use crate::chunk::Chunk;
 use crate::chunk_type::ChunkType;
+use crate::crypto::{self, Identity, PublicKey};
+use crate::error::Error;
+use crate::filter::FilterStrategy;
+use crate::fragment;
+use crate::idat::DeflateOptions;
+use crate::ihdr::InterlaceMethod;
+use crate::inspect::{self, ChunkInfo};
+use crate::lsb;
+use crate::ordering::{OrderingChecker, Validation, Violation, ViolationKind};
+use crate::payload::{self, Payload};
 use crate::png::PNG;
+use crate::reader::ChunkReader;
+use crate::signature::{SIGNATURE_CHUNK_TYPE, Signature, SigningKey};
+use crate::tail;
+use crate::text::{TextChunk, TextKind};
 
 use anyhow::{Context, Result};
 
 const DEFAULT_OUTPUT: &str = "out.png";
 
-fn png_parse(file_path: &Path) -> Result<PNG> {
+/// Path standing for standard input when reading, or standard output when
+/// writing.
+pub const STDIO_PATH: &str = "-";
+
+/// Maximum number of names tried for the temporary file written when
+/// replacing a file in place.
+const TEMP_ATTEMPTS: u32 = 100;
+
+/// Returns `true` if the path stands for standard input or output.
+pub fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == STDIO_PATH
+}
+
+/// Destination of a PNG file modified by a command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Output {
+    /// Path of the PNG file to write, or `out.png` if `None`.
+    /// [`STDIO_PATH`] writes to standard output.
+    pub path: Option<PathBuf>,
+    /// Replaces the input file instead of writing to a path.
+    pub in_place: bool,
+    /// Keeps the input file replaced in place, named after it with this
+    /// suffix appended.
+    pub backup: Option<String>,
+    /// Overwrites existing files, instead of refusing to.
+    pub force: bool,
+    /// Rewrites only the end of the input file in place, without reading the
+    /// rest of it, instead of replacing the whole file atomically. Only
+    /// supported when appending or removing message chunks. A backup is copied
+    /// from the file before it is edited.
+    pub tail: bool,
+}
+
+impl Output {
+    /// Returns an output writing to the path, or `out.png` if `None`,
+    /// which refuses to overwrite an existing file.
+    pub fn to_path(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            ..Self::default()
+        }
+    }
+}
+
+/// Error returned when a file is not a valid PNG file, either because its
+/// datastream is malformed or because it violates the chunk ordering rules
+/// under [`Validation::Strict`].
+///
+/// It is attached as context to the underlying error, so it can be found with
+/// [`anyhow::Error::downcast_ref`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidPngError {
+    path: PathBuf,
+}
+
+impl InvalidPngError {
+    fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl fmt::Display for InvalidPngError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to read PNG from '{}'", self.path.display())
+    }
+}
+
+impl std::error::Error for InvalidPngError {}
+
+/// Chunk type, length, and CRC of a chunk written to or read from a PNG file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkSummary {
+    /// Chunk type of the chunk.
+    pub chunk_type: ChunkType,
+    /// Length of the chunk data in bytes.
+    pub length: u32,
+    /// CRC of the chunk.
+    pub crc: u32,
+}
+
+impl From<&Chunk> for ChunkSummary {
+    fn from(chunk: &Chunk) -> Self {
+        Self {
+            chunk_type: chunk.chunk_type(),
+            length: chunk.length(),
+            crc: chunk.crc(),
+        }
+    }
+}
+
+/// Message encoded into a PNG file by [`invoke_encode`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encoded {
+    /// Chunks added to the PNG, in order, including signature chunks.
+    pub chunks: Vec<ChunkSummary>,
+    /// Path of the PNG file written.
+    pub out_path: PathBuf,
+}
+
+/// Message decoded from a PNG file by [`invoke_decode`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoded {
+    /// Payload of the message.
+    pub payload: Payload,
+    /// Chunks storing the message, in order.
+    pub chunks: Vec<ChunkSummary>,
+}
+
+/// Message removed from a PNG file by [`invoke_remove`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Removed {
+    /// Payload of the message.
+    pub payload: Payload,
+    /// Chunks which stored the message, in order.
+    pub chunks: Vec<ChunkSummary>,
+    /// Path of the PNG file written.
+    pub out_path: PathBuf,
+}
+
+/// Opens the file for buffered reading, or standard input for [`STDIO_PATH`].
+fn open_input(file_path: &Path) -> Result<Box<dyn BufRead>> {
+    if is_stdio(file_path) {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+
     let f = fs::File::open(file_path)
         .with_context(|| format!("failed to open '{}'", file_path.display()))?;
 
-    let metadata = f
-        .metadata()
-        .with_context(|| format!("failed to query metadata for '{}'", file_path.display()))?;
+    Ok(Box::new(BufReader::new(f)))
+}
+
+fn png_parse(file_path: &Path, validation: Validation) -> Result<PNG> {
+    let png = PNG::from_reader_with(open_input(file_path)?, validation)
+        .with_context(|| InvalidPngError::new(file_path))?;
+
+    for violation in png.violations() {
+        warn_violation(file_path, &violation);
+    }
+
+    Ok(png)
+}
+
+/// Returns the first chunk with a matching chunk type from the PNG file which
+/// satisfies the predicate, or `None` if it could not be found.
+fn png_find_chunk(
+    file_path: &Path,
+    chunk_type: &str,
+    validation: Validation,
+    predicate: impl FnMut(&Chunk) -> Result<bool>,
+) -> Result<Option<Chunk>> {
+    let found = png_find_chunks(file_path, chunk_type, validation, 1, predicate)?;
+
+    Ok(found.into_iter().next())
+}
+
+/// Returns up to `limit` chunks with a matching chunk type from the PNG file
+/// which satisfy the predicate, in the order they appear.
+///
+/// Only the data of chunks with a matching chunk type is read into memory; all
+/// other chunks are skipped as they are read. As when parsing the whole PNG, a
+/// missing `IHDR` or `IEND` chunk is an error even if `validation` is lenient.
+fn png_find_chunks(
+    file_path: &Path,
+    chunk_type: &str,
+    validation: Validation,
+    limit: usize,
+    mut predicate: impl FnMut(&Chunk) -> Result<bool>,
+) -> Result<Vec<Chunk>> {
+    let context = || InvalidPngError::new(file_path);
+
+    let mut reader = ChunkReader::new(open_input(file_path)?).with_context(context)?;
+    let mut checker = OrderingChecker::new();
+    let mut found = Vec::new();
+    let mut has_iend = false;
+
+    while let Some(header) = reader.next_header().with_context(context)? {
+        let violations = checker.push(header.chunk_type());
+
+        // As when parsing a PNG, a missing IHDR chunk is always an error.
+        if reader.chunk_index() == Some(0)
+            && let Some(violation) = violations
+                .iter()
+                .find(|v| v.kind() == ViolationKind::MissingIhdr)
+        {
+            return Err(Error::Ordering(*violation)).with_context(context);
+        }
 
-    let mut reader = BufReader::new(f);
-    let mut buf = Vec::with_capacity(metadata.len() as usize);
+        if let (Validation::Strict, Some(violation)) = (validation, violations.first()) {
+            return Err(anyhow::anyhow!("chunk ordering violation: {violation}"))
+                .with_context(context);
+        }
 
-    reader
-        .read_to_end(&mut buf)
-        .with_context(|| format!("failed to read from '{}'", file_path.display()))?;
+        has_iend |= header.chunk_type().bytes() == *b"IEND";
 
-    PNG::try_from(&buf[..])
+        // The remaining chunks are still read to check their CRCs and ordering.
+        if found.len() < limit && header.chunk_type().bytes() == chunk_type.as_bytes() {
+            let chunk = reader.read_data().with_context(context)?;
+
+            if predicate(&chunk)? {
+                found.push(chunk);
+            }
+        }
+    }
+
+    // A datastream ending on a chunk boundary without an IEND chunk has been
+    // truncated.
+    match reader.chunk_index() {
+        None => {
+            let violation = Violation::new(0, None, ViolationKind::MissingIhdr);
+            return Err(Error::Ordering(violation)).with_context(context);
+        }
+        Some(index) if !has_iend => {
+            return Err(Error::Truncated {
+                offset: reader.offset(),
+                index: index + 1,
+            })
+            .with_context(context);
+        }
+        Some(_) => {}
+    }
+
+    for violation in checker.finish() {
+        if validation == Validation::Strict {
+            return Err(anyhow::anyhow!("chunk ordering violation: {violation}"))
+                .with_context(context);
+        }
+
+        warn_violation(file_path, &violation);
+    }
+
+    Ok(found)
 }
 
-fn png_write_to_file(png: &PNG, output_path: Option<PathBuf>) -> Result<()> {
+/// Reports a violation of the chunk ordering rules tolerated when decoding.
+fn warn_violation(file_path: &Path, violation: &Violation) {
+    eprintln!("warning: '{}': {}", file_path.display(), violation);
+}
+
+/// Writes the PNG read from the input path to the output, returning the path
+/// written to.
+fn png_write_to_file(png: &PNG, input_path: &Path, output: Output) -> Result<PathBuf> {
+    anyhow::ensure!(
+        !output.tail,
+        "only message chunks can be appended or removed at the end of a file"
+    );
+
     let bytes = png.as_bytes();
-    let mut cursor = Cursor::new(&bytes);
 
-    let outfile = if let Some(outfile) = output_path {
-        outfile
+    if output.in_place {
+        anyhow::ensure!(
+            output.path.is_none(),
+            "an output file can not be given when editing in place"
+        );
+        anyhow::ensure!(
+            !is_stdio(input_path),
+            "standard input can not be edited in place"
+        );
+
+        replace_file(input_path, &bytes, output.backup.as_deref(), output.force)?;
+
+        return Ok(input_path.to_path_buf());
+    }
+
+    anyhow::ensure!(
+        output.backup.is_none(),
+        "a backup can only be kept when editing in place"
+    );
+
+    let outfile = output.path.unwrap_or_else(|| PathBuf::from(DEFAULT_OUTPUT));
+
+    if is_stdio(&outfile) {
+        write_stdout(&bytes).context("failed to write PNG datastream to standard output")?;
+
+        return Ok(outfile);
+    }
+
+    create_file(&outfile, output.force)?
+        .write_all(&bytes)
+        .with_context(|| format!("failed to write PNG datastream to '{}'", outfile.display()))?;
+
+    Ok(outfile)
+}
+
+/// Opens the PNG file for rewriting its end in place with [`tail`], checking
+/// the output is the input file itself.
+///
+/// As the file is edited directly, a backup is copied from it beforehand.
+fn open_tail(file_path: &Path, output: &Output) -> Result<fs::File> {
+    anyhow::ensure!(
+        output.in_place && output.path.is_none(),
+        "the end of a file can only be rewritten in place"
+    );
+    anyhow::ensure!(
+        !is_stdio(file_path),
+        "standard input can not be edited in place"
+    );
+
+    if let Some(suffix) = &output.backup {
+        copy_backup(file_path, suffix, output.force)?;
+    }
+
+    fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(file_path)
+        .with_context(|| format!("failed to open '{}'", file_path.display()))
+}
+
+/// Creates the file for writing, replacing an existing file only if `force`
+/// is set.
+fn create_file(path: &Path, force: bool) -> Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true);
+
+    if force {
+        options.create(true).truncate(true);
     } else {
-        PathBuf::from(DEFAULT_OUTPUT)
+        options.create_new(true);
+    }
+
+    options.open(path).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => {
+            anyhow::anyhow!("refusing to overwrite existing file '{}'", path.display())
+        }
+        _ => anyhow::Error::new(e).context(format!("failed to create '{}'", path.display())),
+    })
+}
+
+/// Atomically replaces the file with the bytes, by writing them to a temporary
+/// file in the same directory, flushing it to disk, and renaming it over the
+/// file, so the file is never left partially written.
+///
+/// If a backup suffix is given, the original file is kept under its name with
+/// the suffix appended, which is only overwritten if `force` is set.
+fn replace_file(path: &Path, bytes: &[u8], backup: Option<&str>, force: bool) -> Result<()> {
+    // Symbolic links are followed, so the file they point to is replaced.
+    let path = fs::canonicalize(path)
+        .with_context(|| format!("failed to resolve '{}'", path.display()))?;
+    let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+        anyhow::bail!("'{}' is not a file", path.display());
     };
 
-    let mut output = fs::File::create(&outfile)
-        .with_context(|| format!("failed to open/create '{}'", outfile.display()))?;
+    let backup_path = backup.map(|suffix| backup_path(&path, suffix));
 
-    io::copy(&mut cursor, &mut output)
-        .with_context(|| format!("failed to write PNG datastream to '{}'", outfile.display()))?;
+    if let Some(backup_path) = &backup_path {
+        anyhow::ensure!(
+            force || fs::symlink_metadata(backup_path).is_err(),
+            "refusing to overwrite existing backup '{}'",
+            backup_path.display()
+        );
+    }
+
+    let (temp_path, mut temp) = create_temp_file(dir, &file_name.to_string_lossy())?;
+
+    let replaced = (|| -> Result<()> {
+        let context = || format!("failed to write '{}'", temp_path.display());
+
+        temp.write_all(bytes).with_context(context)?;
+        temp.set_permissions(fs::metadata(&path).with_context(context)?.permissions())
+            .with_context(context)?;
+        temp.sync_all().with_context(context)?;
+
+        if let Some(backup_path) = &backup_path {
+            keep_backup(&path, backup_path, force)?;
+        }
+
+        fs::rename(&temp_path, &path).with_context(|| {
+            format!(
+                "failed to rename '{}' to '{}'",
+                temp_path.display(),
+                path.display()
+            )
+        })?;
+
+        sync_dir(dir).with_context(|| format!("failed to sync '{}'", dir.display()))
+    })();
+
+    if replaced.is_err() {
+        // The temporary file no longer exists if it was already renamed.
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    replaced
+}
+
+/// Creates a temporary file in the directory, named after the file it will
+/// replace, returning its path.
+fn create_temp_file(dir: &Path, file_name: &str) -> Result<(PathBuf, fs::File)> {
+    for attempt in 0..TEMP_ATTEMPTS {
+        let temp_path = dir.join(format!(".{file_name}.{}.{attempt}.tmp", process::id()));
+
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(temp) => return Ok((temp_path, temp)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to create '{}'", temp_path.display()));
+            }
+        }
+    }
+
+    anyhow::bail!(
+        "failed to create a temporary file in '{}': too many attempts",
+        dir.display()
+    )
+}
+
+/// Returns the path of the backup of the file, named after it with the suffix
+/// appended.
+fn backup_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut backup_path = path.as_os_str().to_os_string();
+    backup_path.push(suffix);
+
+    PathBuf::from(backup_path)
+}
+
+/// Copies the file to its backup path before it is edited in place, replacing
+/// an existing backup only if `force` is set.
+fn copy_backup(file_path: &Path, suffix: &str, force: bool) -> Result<()> {
+    // Symbolic links are followed, so the file they point to is backed up.
+    let path = fs::canonicalize(file_path)
+        .with_context(|| format!("failed to resolve '{}'", file_path.display()))?;
+    let backup_path = backup_path(&path, suffix);
+    let context = || format!("failed to back up '{}'", path.display());
+
+    anyhow::ensure!(
+        force || fs::symlink_metadata(&backup_path).is_err(),
+        "refusing to overwrite existing backup '{}'",
+        backup_path.display()
+    );
+
+    if force {
+        match fs::remove_file(&backup_path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(e).with_context(context);
+            }
+            _ => {}
+        }
+    }
+
+    // A hard link would share the file edited in place, so it is copied.
+    fs::copy(&path, &backup_path).with_context(context)?;
+    fs::File::open(&backup_path)
+        .and_then(|backup| backup.sync_all())
+        .with_context(context)
+}
+
+/// Keeps the file under the backup path, replacing an existing backup only if
+/// `force` is set.
+fn keep_backup(path: &Path, backup_path: &Path, force: bool) -> Result<()> {
+    let context = || format!("failed to back up '{}'", path.display());
+
+    if force {
+        match fs::remove_file(backup_path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(e).with_context(context);
+            }
+            _ => {}
+        }
+    }
+
+    // A hard link keeps the original file as the backup without copying it,
+    // falling back to a copy on file systems without hard links.
+    match fs::hard_link(path, backup_path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(anyhow::anyhow!(
+            "refusing to overwrite existing backup '{}'",
+            backup_path.display()
+        )),
+        Err(_) => fs::copy(path, backup_path)
+            .map(|_| ())
+            .with_context(context),
+    }
+}
+
+/// Flushes the directory entries of the directory to disk, so a file renamed
+/// within it is not lost on a crash.
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    fs::File::open(dir)?.sync_all()?;
+
+    #[cfg(not(unix))]
+    let _ = dir;
 
     Ok(())
 }
 
-/// Encodes a message into the PNG file given its chunk type.
+/// Encodes a message into the PNG given its chunk type.
 ///
-/// Writes the modifications to a new PNG file, or the output path if provided.
-pub fn invoke_encode(
-    png_path: PathBuf,
-    chunk_type: String,
-    message: String,
-    out_path: Option<PathBuf>,
-) -> Result<()> {
-    let file_path = png_path.as_path();
+/// The message chunk is inserted before the `IEND` chunk.
+///
+/// # Errors
+///
+/// Returns an error if the chunk type is not a valid ancillary, private, and
+/// safe-to-copy chunk type (e.g., "ruSt"), or if the message is too large to
+/// fit in a single chunk.
+pub fn encode(png: &mut PNG, chunk_type: &str, message: impl Into<Vec<u8>>) -> Result<()> {
+    let chunk_type = message_chunk_type(chunk_type)?;
+    let chunk = Chunk::new(chunk_type, message.into())?;
+
+    png.append_chunk(chunk);
 
-    let mut png = png_parse(file_path)?;
-    let chunk_type = ChunkType::from_str(&chunk_type)?;
+    Ok(())
+}
+
+/// Parses the chunk type of a message chunk, which must be ancillary, private,
+/// and safe-to-copy.
+fn message_chunk_type(chunk_type: &str) -> Result<ChunkType> {
+    let chunk_type = ChunkType::from_str(chunk_type)?;
 
     if chunk_type.is_critical()
         || chunk_type.is_public()
@@ -70,45 +577,1589 @@ pub fn invoke_encode(
         || !chunk_type.is_safe_to_copy()
     {
         anyhow::bail!(
-            "failed to append chunk with chunk type '{}' to '{}': must be exactly 4 ASCII letters with specific casing (e.g., \"ruSt\")",
-            chunk_type,
-            file_path.display()
+            "invalid message chunk type '{}': must be exactly 4 ASCII letters with specific casing (e.g., \"ruSt\")",
+            chunk_type
         );
     }
 
-    let chunk = Chunk::new(chunk_type, message.into())?;
+    Ok(chunk_type)
+}
 
-    png.append_chunk(chunk);
+/// Decodes a message from the PNG given its chunk type, returning the first
+/// matching message, or `None` if it could not be found.
+///
+/// A fragmented message is reassembled from all of its fragments, and the
+/// payload header of a message is stripped. The data of the first matching
+/// chunk is returned if the message could not be reassembled or decoded.
+pub fn decode(png: &PNG, chunk_type: &str) -> Option<String> {
+    let first = png.chunk_by_type(chunk_type)?;
+
+    let message = message_data(png.chunks_by_type(chunk_type))
+        .ok()
+        .flatten()
+        .and_then(|data| Payload::from_bytes(data).ok())
+        .map_or_else(|| first.data().to_vec(), Payload::into_data);
+
+    Some(String::from_utf8_lossy(&message).into_owned())
+}
 
-    png_write_to_file(&png, out_path)?;
+/// Encodes a message into the PNG given its chunk type, split into fragments
+/// of at most `fragment_size` bytes, each stored in its own chunk.
+///
+/// The fragment chunks are inserted before the `IEND` chunk.
+///
+/// # Errors
+///
+/// Returns an error if the chunk type is not a valid ancillary, private, and
+/// safe-to-copy chunk type (e.g., "ruSt"), or if the fragment size is invalid.
+pub fn encode_fragmented(
+    png: &mut PNG,
+    chunk_type: &str,
+    message: impl AsRef<[u8]>,
+    fragment_size: usize,
+) -> Result<()> {
+    let chunk_type = message_chunk_type(chunk_type)?;
+
+    append_message(png, chunk_type, message.as_ref(), Some(fragment_size))
+}
+
+/// Appends the message chunks storing a message to the PNG, fragmenting it if
+/// a fragment size is given, or if it is larger than
+/// [`fragment::DEFAULT_FRAGMENT_SIZE`].
+fn append_message(
+    png: &mut PNG,
+    chunk_type: ChunkType,
+    message: &[u8],
+    fragment_size: Option<usize>,
+) -> Result<()> {
+    for chunk in message_chunks(chunk_type, message, fragment_size)? {
+        png.append_chunk(chunk);
+    }
 
     Ok(())
 }
 
-/// Decodes a message from the PNG file given the chunk type, returning the
-/// message of the chunk, or `None` if it could not be found.
-pub fn invoke_decode(png_path: PathBuf, chunk_type: String) -> Result<Option<String>> {
-    let png = png_parse(png_path.as_path())?;
-    let chunk = png.chunk_by_type(&chunk_type);
+/// Returns the message chunks storing a message, fragmenting it if a fragment
+/// size is given, or if it is larger than [`fragment::DEFAULT_FRAGMENT_SIZE`].
+fn message_chunks(
+    chunk_type: ChunkType,
+    message: &[u8],
+    fragment_size: Option<usize>,
+) -> Result<Vec<Chunk>> {
+    let fragment_size = match fragment_size {
+        Some(fragment_size) => fragment_size,
+        // Messages which look like a fragment are fragmented so that they are
+        // decoded unchanged.
+        None if message.len() > fragment::DEFAULT_FRAGMENT_SIZE
+            || fragment::is_fragment(message) =>
+        {
+            fragment::DEFAULT_FRAGMENT_SIZE
+        }
+        None => return Ok(vec![Chunk::new(chunk_type, message.to_vec())?]),
+    };
 
-    Ok(chunk.map(|c| c.to_string()))
+    fragment::split(message, fragment_size)?
+        .into_iter()
+        .map(|fragment| Ok(Chunk::new(chunk_type, fragment.to_bytes())?))
+        .collect()
 }
 
-/// Removes a message from the PNG file given the chunk type, returning the
-/// message of the chunk, or `None` if it could not be found.
+/// Returns the first message stored in the message chunks, reassembling it if
+/// it is fragmented, or `None` if there are no message chunks.
+fn message_data<'a>(chunks: impl IntoIterator<Item = &'a Chunk>) -> Result<Option<Vec<u8>>> {
+    let mut chunks = chunks.into_iter().peekable();
+
+    let Some(first) = chunks.peek() else {
+        return Ok(None);
+    };
+
+    if !fragment::is_fragment(first.data()) {
+        return Ok(Some(first.data().to_vec()));
+    }
+
+    let fragments = chunks
+        .map(Chunk::data)
+        .filter(|data| fragment::is_fragment(data));
+
+    fragment::reassemble(fragments).map(Some)
+}
+
+/// Encodes a payload into the PNG given its chunk type.
 ///
-/// Writes the modifications to a new PNG file, or the output path if provided.
-pub fn invoke_remove(
-    png_path: PathBuf,
-    chunk_type: String,
-    out_path: Option<PathBuf>,
-) -> Result<Option<String>> {
-    let mut png = png_parse(png_path.as_path())?;
+/// The message chunk is inserted before the `IEND` chunk. Payloads larger
+/// than [`fragment::DEFAULT_FRAGMENT_SIZE`] are fragmented across several
+/// chunks.
+///
+/// # Errors
+///
+/// Returns an error if the chunk type is not a valid ancillary, private, and
+/// safe-to-copy chunk type (e.g., "ruSt").
+pub fn encode_payload(png: &mut PNG, chunk_type: &str, payload: &Payload) -> Result<()> {
+    let chunk_type = message_chunk_type(chunk_type)?;
+
+    append_message(png, chunk_type, &payload.to_bytes(), None)
+}
+
+/// Decodes a payload from the PNG given its chunk type, returning the payload
+/// of the first matching message byte-for-byte, or `None` if it could not be
+/// found.
+///
+/// A fragmented message is reassembled from all of its fragments.
+///
+/// # Errors
+///
+/// Returns an error if the message has a malformed payload header, or a
+/// [`fragment::ReassemblyError`] if fragments of the message are missing or
+/// corrupted.
+pub fn decode_payload(png: &PNG, chunk_type: &str) -> Result<Option<Payload>> {
+    message_data(png.chunks_by_type(chunk_type))?
+        .map(Payload::from_bytes)
+        .transpose()
+}
+
+/// Removes a message from the PNG given its chunk type, returning the first
+/// matching message, or `None` if it could not be found.
+///
+/// All fragments of a fragmented message are removed, along with the
+/// signature chunks signing the message chunks.
+pub fn remove(png: &mut PNG, chunk_type: &str) -> Option<String> {
+    let chunks = remove_message(png, chunk_type)?;
+
+    Some(String::from_utf8_lossy(&removed_message(&chunks)).into_owned())
+}
+
+/// Removes the first message with a matching chunk type from the PNG, along
+/// with the other fragments of a fragmented message, returning the removed
+/// message chunks in order.
+fn remove_message(png: &mut PNG, chunk_type: &str) -> Option<Vec<Chunk>> {
+    let is_message = |c: &Chunk| c.chunk_type().bytes() == chunk_type.as_bytes();
+
+    let idx = png.chunks().iter().position(is_message)?;
+    let Some(message_hash) = fragment::message_hash(png.chunks()[idx].data()).copied() else {
+        return remove_message_at(png, idx).map(|c| vec![c]);
+    };
+
+    let indices: Vec<_> = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| is_message(c) && fragment::message_hash(c.data()) == Some(&message_hash))
+        .map(|(idx, _)| idx)
+        .collect();
+
+    // Chunks are removed back to front, so the remaining indices stay valid.
+    let mut removed: Vec<_> = indices
+        .into_iter()
+        .rev()
+        .filter_map(|idx| remove_message_at(png, idx))
+        .collect();
+    removed.reverse();
+
+    Some(removed)
+}
+
+/// Returns the message stored in removed message chunks, or the data of the
+/// first chunk if the message could not be reassembled.
+fn removed_message(chunks: &[Chunk]) -> Vec<u8> {
+    message_data(chunks)
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| chunks[0].data().to_vec())
+}
+
+/// Removes the message chunk at the given index from the PNG and returns it,
+/// along with the signature chunk following it, if any.
+fn remove_message_at(png: &mut PNG, idx: usize) -> Option<Chunk> {
+    let message = png.remove_chunk_at(idx)?;
+
+    let signed = png
+        .chunks()
+        .get(idx)
+        .and_then(|chunk| Signature::try_from(chunk).ok())
+        .is_some_and(|signature| signature.chunk_type() == message.chunk_type());
+
+    if signed {
+        png.remove_chunk_at(idx);
+    }
+
+    Some(message)
+}
+
+/// Key a message is encrypted with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Encryption {
+    /// Encrypts the message with a key derived from the passphrase.
+    Passphrase(String),
+    /// Encrypts the message to one or more recipients.
+    Recipients(Vec<PublicKey>),
+}
+
+/// Key a message is decrypted with.
+#[derive(Debug, Clone)]
+pub enum Decryption {
+    /// Decrypts the message with a key derived from the passphrase.
+    Passphrase(String),
+    /// Decrypts the message with the identity of one of its recipients.
+    Identity(Identity),
+}
+
+/// Encodes a message into the PNG given its chunk type, encrypted with a key
+/// derived from a passphrase or to one or more recipients.
+///
+/// The message chunk is inserted before the `IEND` chunk. Encrypted messages
+/// larger than [`fragment::DEFAULT_FRAGMENT_SIZE`] are fragmented across
+/// several chunks.
+///
+/// # Errors
+///
+/// Returns an error if the chunk type is not a valid ancillary, private, and
+/// safe-to-copy chunk type (e.g., "ruSt"), or if the message could not be
+/// encrypted.
+pub fn encode_encrypted(
+    png: &mut PNG,
+    chunk_type: &str,
+    message: impl AsRef<[u8]>,
+    encryption: &Encryption,
+) -> Result<()> {
+    let chunk_type = message_chunk_type(chunk_type)?;
+    let envelope = encrypt_message(chunk_type, message.as_ref(), encryption)?;
+
+    append_message(png, chunk_type, &envelope, None)
+}
+
+/// Encrypts a message to be stored in a chunk with the chunk type.
+fn encrypt_message(
+    chunk_type: ChunkType,
+    message: &[u8],
+    encryption: &Encryption,
+) -> Result<Vec<u8>> {
+    match encryption {
+        Encryption::Passphrase(passphrase) => {
+            crypto::encrypt(chunk_type, passphrase.as_bytes(), message)
+        }
+        Encryption::Recipients(recipients) => {
+            crypto::encrypt_to_recipients(chunk_type, recipients, message)
+        }
+    }
+}
+
+/// Decodes an encrypted message from the PNG given its chunk type, returning
+/// the decrypted payload of the first matching message, or `None` if it could
+/// not be found.
+///
+/// A fragmented message is reassembled from all of its fragments before it is
+/// decrypted.
+///
+/// # Errors
+///
+/// Returns an error if the message is not encrypted or could not be
+/// decrypted, including a [`crypto::AuthenticationError`] if the passphrase
+/// or identity is wrong or the message was tampered with, a
+/// [`fragment::ReassemblyError`] if fragments of the message are missing or
+/// corrupted, or if the decrypted message has a malformed payload header.
+pub fn decode_encrypted(
+    png: &PNG,
+    chunk_type: &str,
+    decryption: &Decryption,
+) -> Result<Option<Payload>> {
+    let Some(envelope) = message_data(png.chunks_by_type(chunk_type))? else {
+        return Ok(None);
+    };
+
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    let message = decrypt_message(chunk_type, &envelope, decryption)?;
+
+    Payload::from_bytes(message).map(Some)
+}
 
-    let chunk = png.remove_chunk(&chunk_type);
-    let message = chunk.map(|c| c.to_string());
+/// Decrypts a message stored in a chunk with the chunk type.
+fn decrypt_message(
+    chunk_type: ChunkType,
+    envelope: &[u8],
+    decryption: &Decryption,
+) -> Result<Vec<u8>> {
+    anyhow::ensure!(
+        crypto::is_encrypted(envelope),
+        "message in chunk '{}' is not encrypted",
+        chunk_type
+    );
 
-    png_write_to_file(&png, out_path)?;
+    let message = match decryption {
+        Decryption::Passphrase(passphrase) => {
+            crypto::decrypt(chunk_type, passphrase.as_bytes(), envelope)?
+        }
+        Decryption::Identity(identity) => {
+            crypto::decrypt_with_identity(chunk_type, identity, envelope)?
+        }
+    };
 
     Ok(message)
 }
+
+/// Encodes a message into the PNG as a textual chunk.
+///
+/// The textual chunk is inserted before the `IEND` chunk.
+///
+/// # Errors
+///
+/// Returns an error if the encoded textual chunk is too large to fit in a
+/// single chunk.
+pub fn encode_text(png: &mut PNG, text: &TextChunk) -> Result<()> {
+    png.append_chunk(text.to_chunk()?);
+
+    Ok(())
+}
+
+/// Decodes a message from the PNG given the chunk type (`tEXt`, `zTXt`, or
+/// `iTXt`) and keyword of its textual chunk, returning the text of the first
+/// matching chunk, or `None` if it could not be found.
+///
+/// # Errors
+///
+/// Returns an error if the chunk type is not a textual chunk type, or if a
+/// textual chunk with the chunk type is malformed.
+pub fn decode_text(png: &PNG, chunk_type: &str, keyword: &str) -> Result<Option<String>> {
+    let found = find_text(png, chunk_type, keyword)?;
+
+    Ok(found.map(|(_, text)| text.text().to_owned()))
+}
+
+/// Removes a message from the PNG given the chunk type (`tEXt`, `zTXt`, or
+/// `iTXt`) and keyword of its textual chunk, returning the text of the first
+/// matching chunk, or `None` if it could not be found.
+///
+/// A signature chunk signing the textual chunk is removed along with it.
+///
+/// # Errors
+///
+/// Returns an error if the chunk type is not a textual chunk type, or if a
+/// textual chunk with the chunk type is malformed.
+pub fn remove_text(png: &mut PNG, chunk_type: &str, keyword: &str) -> Result<Option<String>> {
+    let Some((idx, text)) = find_text(png, chunk_type, keyword)? else {
+        return Ok(None);
+    };
+
+    remove_message_at(png, idx);
+
+    Ok(Some(text.text().to_owned()))
+}
+
+/// Signs the last message chunk in the PNG with a matching chunk type, which is
+/// the most recently encoded one, and the image data if `covers_image` is set.
+/// If the message is fragmented, every fragment of it is signed.
+///
+/// Each signature chunk is inserted immediately after the message chunk it
+/// signs.
+///
+/// # Errors
+///
+/// Returns an error if there is no chunk with a matching chunk type, or if the
+/// signature covers the image data and the PNG has no `IHDR` chunk.
+pub fn sign(png: &mut PNG, chunk_type: &str, key: &SigningKey, covers_image: bool) -> Result<()> {
+    let is_message = |c: &Chunk| c.chunk_type().bytes() == chunk_type.as_bytes();
+
+    let last = png
+        .chunks()
+        .iter()
+        .rposition(is_message)
+        .with_context(|| format!("no message chunk with chunk type '{chunk_type}' to sign"))?;
+
+    let indices: Vec<_> = match fragment::message_hash(png.chunks()[last].data()) {
+        Some(message_hash) => png
+            .chunks()
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| {
+                is_message(c) && fragment::message_hash(c.data()) == Some(message_hash)
+            })
+            .map(|(idx, _)| idx)
+            .collect(),
+        None => vec![last],
+    };
+
+    let mut signatures = Vec::with_capacity(indices.len());
+    for idx in indices {
+        signatures.push((
+            idx,
+            Signature::sign(key, &png.chunks()[idx], png, covers_image)?,
+        ));
+    }
+
+    // Signatures are inserted back to front, so the remaining indices stay
+    // valid.
+    for (idx, signature) in signatures.into_iter().rev() {
+        png.insert_chunk(idx + 1, signature.to_chunk());
+    }
+
+    Ok(())
+}
+
+/// Result of verifying a signature chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verification {
+    /// Index of the signature chunk within the PNG.
+    pub index: usize,
+    /// Decoded signature.
+    pub signature: Signature,
+    /// Whether the signature is valid for the message chunk preceding it, and
+    /// the image data if the signature covers it.
+    pub valid: bool,
+}
+
+/// Verifies the signature chunks in the PNG, or only those signing message
+/// chunks with a matching chunk type if provided.
+///
+/// A signature is valid if the chunk immediately preceding it is a message
+/// chunk with the signed chunk type, whose data, and the image data if the
+/// signature covers it, have not been altered since it was signed.
+///
+/// # Errors
+///
+/// Returns an error if a signature chunk is malformed or uses an unsupported
+/// version or algorithm.
+pub fn verify(png: &PNG, chunk_type: Option<&str>) -> Result<Vec<Verification>> {
+    let mut verifications = Vec::new();
+
+    for (idx, chunk) in png.chunks().iter().enumerate() {
+        if chunk.chunk_type().bytes() != SIGNATURE_CHUNK_TYPE.as_bytes() {
+            continue;
+        }
+
+        let signature = Signature::try_from(chunk)
+            .with_context(|| format!("failed to decode signature chunk {idx}"))?;
+
+        if chunk_type.is_some_and(|t| signature.chunk_type().bytes() != t.as_bytes()) {
+            continue;
+        }
+
+        let valid = idx
+            .checked_sub(1)
+            .is_some_and(|prev| signature.verify(&png.chunks()[prev], png));
+
+        verifications.push(Verification {
+            index: idx,
+            signature,
+            valid,
+        });
+    }
+
+    Ok(verifications)
+}
+
+/// Returns the index and contents of the first textual chunk in the PNG with
+/// a matching chunk type and keyword.
+fn find_text(png: &PNG, chunk_type: &str, keyword: &str) -> Result<Option<(usize, TextChunk)>> {
+    text_kind(chunk_type)?;
+
+    for (idx, chunk) in png.chunks().iter().enumerate() {
+        if chunk.chunk_type().bytes() != chunk_type.as_bytes() {
+            continue;
+        }
+
+        let text = TextChunk::try_from(chunk)?;
+        if text.keyword() == keyword {
+            return Ok(Some((idx, text)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Returns the kind of textual chunk with the given chunk type.
+fn text_kind(chunk_type: &str) -> Result<TextKind> {
+    TextKind::from_chunk_type(chunk_type).with_context(|| {
+        format!(
+            "invalid textual chunk type '{chunk_type}': must be one of \"tEXt\", \"zTXt\", or \"iTXt\""
+        )
+    })
+}
+
+/// Options for storing a message in a textual chunk identified by keyword,
+/// rather than in a private chunk.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextOptions {
+    /// Keyword identifying the message (e.g., "Comment").
+    pub keyword: String,
+    /// Language of an `iTXt` message (e.g., "en-US").
+    pub language_tag: String,
+    /// Keyword of an `iTXt` message translated into its language.
+    pub translated_keyword: String,
+    /// Whether an `iTXt` message is compressed.
+    pub compressed: bool,
+}
+
+impl TextOptions {
+    /// Creates the textual chunk storing the message, given its chunk type.
+    fn text_chunk(&self, chunk_type: &str, message: String) -> Result<TextChunk> {
+        let kind = match text_kind(chunk_type)? {
+            TextKind::International { .. } => TextKind::International {
+                compressed: self.compressed,
+                language_tag: self.language_tag.clone(),
+                translated_keyword: self.translated_keyword.clone(),
+            },
+            kind => kind,
+        };
+
+        TextChunk::new(kind, self.keyword.clone(), message)
+    }
+}
+
+/// Options for signing a message.
+#[derive(Debug, Clone)]
+pub struct SigningOptions {
+    /// Key the message is signed with.
+    pub key: SigningKey,
+    /// Whether the signature also covers the image data.
+    pub covers_image: bool,
+}
+
+/// Options for encoding a message into a PNG file.
+#[derive(Debug, Clone, Default)]
+pub struct EncodeOptions {
+    /// Stores the message in a textual chunk identified by keyword.
+    pub text: Option<TextOptions>,
+    /// Encrypts the message with a passphrase or to recipients.
+    pub encryption: Option<Encryption>,
+    /// Signs the message chunk.
+    pub signing: Option<SigningOptions>,
+    /// Splits the message into fragments of at most this many bytes, each
+    /// stored in its own chunk.
+    pub fragment_size: Option<usize>,
+}
+
+/// Options for decoding a message from a PNG file.
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    /// Decodes the message from the textual chunk with the keyword.
+    pub keyword: Option<String>,
+    /// Decrypts the message with a passphrase or identity.
+    pub decryption: Option<Decryption>,
+}
+
+/// Encodes a message into the PNG file given its chunk type.
+///
+/// Writes the modifications to the output, or replaces the PNG file in place.
+///
+/// If text options are provided, the message is stored in a textual chunk of
+/// the chunk type (`tEXt`, `zTXt`, or `iTXt`) instead. If encryption is
+/// requested, the message is encrypted. If a fragment size is provided, or the
+/// message is larger than [`fragment::DEFAULT_FRAGMENT_SIZE`], it is split
+/// into fragments stored in several chunks. If signing is requested, a
+/// signature chunk is inserted after each message chunk.
+///
+/// If the output rewrites only the end of the file, the message chunks are
+/// appended without reading the rest of it, which is then not validated.
+/// Messages stored in textual chunks or signed are not supported.
+///
+/// Returns the chunks added to the PNG and the path of the PNG file written.
+pub fn invoke_encode(
+    png_path: PathBuf,
+    chunk_type: String,
+    message: Payload,
+    output: Output,
+    opts: EncodeOptions,
+    validation: Validation,
+) -> Result<Encoded> {
+    let file_path = png_path.as_path();
+
+    if output.tail {
+        return encode_tail(file_path, &chunk_type, message, &output, opts);
+    }
+
+    let mut png = png_parse(file_path, validation)?;
+
+    // Chunks are only ever added before the `IEND` chunk, so the added chunks
+    // are those between the original position of `IEND` and its new one.
+    let chunk_count = png.chunks().len();
+    let start = png
+        .chunks()
+        .iter()
+        .position(|c| c.chunk_type().bytes() == *b"IEND")
+        .unwrap_or(chunk_count);
+
+    let encoded = match (opts.text, opts.encryption) {
+        (Some(_), Some(_)) => Err(anyhow::anyhow!(
+            "textual chunks can not store encrypted messages"
+        )),
+        (Some(_), None) if opts.fragment_size.is_some() => Err(anyhow::anyhow!(
+            "textual chunks can not store fragmented messages"
+        )),
+        (Some(text), None) => text_message(message)
+            .and_then(|message| text.text_chunk(&chunk_type, message))
+            .and_then(|text| encode_text(&mut png, &text)),
+        (None, encryption) => message_chunk_type(&chunk_type).and_then(|chunk_type| {
+            let message = match encryption {
+                Some(encryption) => encrypt_message(chunk_type, &message.to_bytes(), &encryption)?,
+                None => message.to_bytes(),
+            };
+
+            append_message(&mut png, chunk_type, &message, opts.fragment_size)
+        }),
+    };
+
+    encoded.with_context(|| {
+        format!(
+            "failed to append chunk with chunk type '{}' to '{}'",
+            chunk_type,
+            file_path.display()
+        )
+    })?;
+
+    if let Some(signing) = opts.signing {
+        sign(&mut png, &chunk_type, &signing.key, signing.covers_image).with_context(|| {
+            format!(
+                "failed to sign chunk with chunk type '{}' in '{}'",
+                chunk_type,
+                file_path.display()
+            )
+        })?;
+    }
+
+    let added = png.chunks().len() - chunk_count;
+    let chunks = png.chunks()[start..start + added]
+        .iter()
+        .map(ChunkSummary::from)
+        .collect();
+
+    let out_path = png_write_to_file(&png, file_path, output)?;
+
+    Ok(Encoded { chunks, out_path })
+}
+
+/// Appends the message chunks storing a message to the end of the PNG file in
+/// place, without reading the rest of it.
+fn encode_tail(
+    file_path: &Path,
+    chunk_type: &str,
+    message: Payload,
+    output: &Output,
+    opts: EncodeOptions,
+) -> Result<Encoded> {
+    anyhow::ensure!(
+        opts.text.is_none() && opts.signing.is_none(),
+        "textual chunks and signed messages can not be appended at the end of a file"
+    );
+
+    let mut file = open_tail(file_path, output)?;
+
+    let chunks = message_chunk_type(chunk_type)
+        .and_then(|message_type| {
+            let message = match &opts.encryption {
+                Some(encryption) => encrypt_message(message_type, &message.to_bytes(), encryption)?,
+                None => message.to_bytes(),
+            };
+
+            message_chunks(message_type, &message, opts.fragment_size)
+        })
+        .with_context(|| {
+            format!(
+                "failed to append chunk with chunk type '{}' to '{}'",
+                chunk_type,
+                file_path.display()
+            )
+        })?;
+
+    tail::append_chunks(&mut file, &chunks).with_context(|| InvalidPngError::new(file_path))?;
+    file.sync_all()
+        .with_context(|| format!("failed to sync '{}'", file_path.display()))?;
+
+    Ok(Encoded {
+        chunks: chunks.iter().map(ChunkSummary::from).collect(),
+        out_path: file_path.to_path_buf(),
+    })
+}
+
+/// Returns the text of a message to be stored in a textual chunk.
+fn text_message(message: Payload) -> Result<String> {
+    anyhow::ensure!(
+        !message.has_metadata(),
+        "textual chunks can not store a filename or MIME type"
+    );
+
+    String::from_utf8(message.into_data())
+        .map_err(|_| anyhow::anyhow!("textual chunks can not store binary data"))
+}
+
+/// Decodes a message from the PNG file given the chunk type, returning the
+/// payload of the chunk byte-for-byte along with the chunks storing it, or
+/// `None` if it could not be found.
+/// A fragmented message is reassembled from all of its fragments.
+///
+/// If a keyword is provided, the message is decoded from the textual chunk of
+/// the chunk type (`tEXt`, `zTXt`, or `iTXt`) with that keyword instead. If a
+/// passphrase or identity is provided, the message is decrypted.
+///
+/// # Errors
+///
+/// Returns an error if the message is encrypted but no passphrase or identity
+/// is provided, or if it could not be decrypted, including a
+/// [`crypto::AuthenticationError`] if the passphrase or identity is wrong or
+/// the message was tampered with, a [`fragment::ReassemblyError`] if fragments
+/// of the message are missing or corrupted, or if the message has a malformed
+/// payload header.
+pub fn invoke_decode(
+    png_path: PathBuf,
+    chunk_type: String,
+    opts: DecodeOptions,
+    validation: Validation,
+) -> Result<Option<Decoded>> {
+    let file_path = png_path.as_path();
+
+    let Some(keyword) = opts.keyword else {
+        let chunks = png_find_chunks(file_path, &chunk_type, validation, usize::MAX, |_| Ok(true))?;
+        let Some(data) = message_data(&chunks).with_context(|| {
+            format!(
+                "failed to reassemble message in chunk '{}' of '{}'",
+                chunk_type,
+                file_path.display()
+            )
+        })?
+        else {
+            return Ok(None);
+        };
+        let chunk_type = chunks[0].chunk_type();
+        // Only the fragments of the first message are part of it.
+        let chunks = match fragment::message_hash(chunks[0].data()) {
+            Some(hash) => chunks
+                .iter()
+                .filter(|c| fragment::message_hash(c.data()) == Some(hash))
+                .map(ChunkSummary::from)
+                .collect(),
+            None => vec![ChunkSummary::from(&chunks[0])],
+        };
+
+        let message = match opts.decryption {
+            Some(decryption) => {
+                decrypt_message(chunk_type, &data, &decryption).with_context(|| {
+                    format!(
+                        "failed to decrypt message in chunk '{}' of '{}'",
+                        chunk_type,
+                        file_path.display()
+                    )
+                })?
+            }
+            None if crypto::is_encrypted(&data) => anyhow::bail!(
+                "message in chunk '{}' of '{}' is encrypted: {} is required to decrypt it",
+                chunk_type,
+                file_path.display(),
+                match crypto::scheme(&data)? {
+                    crypto::Scheme::Passphrase => "a passphrase",
+                    crypto::Scheme::Recipients => "an identity",
+                }
+            ),
+            None => data,
+        };
+
+        let payload = Payload::from_bytes(message).with_context(|| {
+            format!(
+                "failed to decode message in chunk '{}' of '{}'",
+                chunk_type,
+                file_path.display()
+            )
+        })?;
+
+        return Ok(Some(Decoded { payload, chunks }));
+    };
+
+    anyhow::ensure!(
+        opts.decryption.is_none(),
+        "textual chunks can not store encrypted messages"
+    );
+
+    text_kind(&chunk_type)?;
+
+    let mut found = None;
+    png_find_chunk(file_path, &chunk_type, validation, |chunk| {
+        let text = TextChunk::try_from(chunk)?;
+        let matches = text.keyword() == keyword;
+
+        if matches {
+            found = Some(Decoded {
+                payload: Payload::new(text.text()),
+                chunks: vec![ChunkSummary::from(chunk)],
+            });
+        }
+
+        Ok(matches)
+    })?;
+
+    Ok(found)
+}
+
+/// Removes a message from the PNG file given the chunk type, returning the
+/// payload of the chunk along with the chunks which stored it and the path of
+/// the PNG file written, or `None` if it could not be found.
+///
+/// Writes the modifications to the output, or replaces the PNG file in place.
+/// Nothing is written if the message could not be found.
+///
+/// All fragments of a fragmented message are removed. If a keyword is
+/// provided, the message is removed from the textual chunk of the chunk type
+/// (`tEXt`, `zTXt`, or `iTXt`) with that keyword instead.
+///
+/// If the output rewrites only the end of the file, the message is only found
+/// in the last chunks before `IEND`, which are removed without reading the
+/// rest of the file. Messages stored in textual chunks are not supported.
+pub fn invoke_remove(
+    png_path: PathBuf,
+    chunk_type: String,
+    keyword: Option<String>,
+    output: Output,
+    validation: Validation,
+) -> Result<Option<Removed>> {
+    let file_path = png_path.as_path();
+
+    if output.tail {
+        anyhow::ensure!(
+            keyword.is_none(),
+            "textual chunks can not be removed at the end of a file"
+        );
+
+        return remove_tail(file_path, &chunk_type, &output);
+    }
+
+    let mut png = png_parse(file_path, validation)?;
+
+    let removed = match keyword {
+        Some(keyword) => find_text(&png, &chunk_type, &keyword)?.map(|(idx, text)| {
+            let chunk = ChunkSummary::from(&png.chunks()[idx]);
+            remove_message_at(&mut png, idx);
+
+            (Payload::new(text.text()), vec![chunk])
+        }),
+        None => remove_message(&mut png, &chunk_type).map(|chunks| {
+            // The message is removed regardless of whether it can be
+            // reassembled or its payload header can be decoded.
+            let message = removed_message(&chunks);
+            let payload =
+                Payload::from_bytes(message.clone()).unwrap_or_else(|_| Payload::new(message));
+
+            (payload, chunks.iter().map(ChunkSummary::from).collect())
+        }),
+    };
+
+    let Some((payload, chunks)) = removed else {
+        return Ok(None);
+    };
+
+    let out_path = png_write_to_file(&png, file_path, output)?;
+
+    Ok(Some(Removed {
+        payload,
+        chunks,
+        out_path,
+    }))
+}
+
+/// Removes the message stored in the last chunks before `IEND` from the end of
+/// the PNG file in place, without reading the rest of it, or returns `None` if
+/// the last chunk is not a message chunk of the chunk type.
+///
+/// # Errors
+///
+/// Returns an error if the message is signed, as its signature chunk would be
+/// left behind.
+fn remove_tail(file_path: &Path, chunk_type: &str, output: &Output) -> Result<Option<Removed>> {
+    let message_type = message_chunk_type(chunk_type)?;
+
+    let mut file = open_tail(file_path, output)?;
+    let mut chunks: Vec<Chunk> = Vec::new();
+    let mut signed = false;
+
+    // Fragments of a message are removed back to front, as long as they belong
+    // to the same message as the last chunk.
+    loop {
+        let removed = tail::remove_last_chunk_if(&mut file, |chunk| {
+            signed = chunks.is_empty()
+                && Signature::try_from(chunk).is_ok_and(|s| s.chunk_type() == message_type);
+
+            let message_hash = fragment::message_hash(chunk.data());
+            let same_message = match chunks.last() {
+                Some(last) => {
+                    message_hash.is_some() && message_hash == fragment::message_hash(last.data())
+                }
+                None => true,
+            };
+
+            chunk.chunk_type() == message_type && same_message
+        })
+        .with_context(|| InvalidPngError::new(file_path))?;
+
+        anyhow::ensure!(
+            !signed,
+            "the message with chunk type '{}' in '{}' is signed: signed messages can not be removed at the end of a file",
+            chunk_type,
+            file_path.display()
+        );
+
+        let Some(chunk) = removed else {
+            break;
+        };
+
+        let fragmented = fragment::is_fragment(chunk.data());
+        chunks.push(chunk);
+
+        if !fragmented {
+            break;
+        }
+    }
+
+    file.sync_all()
+        .with_context(|| format!("failed to sync '{}'", file_path.display()))?;
+
+    if chunks.is_empty() {
+        return Ok(None);
+    }
+
+    chunks.reverse();
+
+    let message = removed_message(&chunks);
+    let payload = Payload::from_bytes(message.clone()).unwrap_or_else(|_| Payload::new(message));
+
+    Ok(Some(Removed {
+        payload,
+        chunks: chunks.iter().map(ChunkSummary::from).collect(),
+        out_path: file_path.to_path_buf(),
+    }))
+}
+
+/// Hides a payload in the least significant bits of the image data of the PNG
+/// file.
+///
+/// Writes the modifications to the output, or replaces the PNG file in place,
+/// returning the path written to.
+pub fn invoke_hide(
+    png_path: PathBuf,
+    message: Payload,
+    output: Output,
+    validation: Validation,
+) -> Result<PathBuf> {
+    let file_path = png_path.as_path();
+
+    let mut png = png_parse(file_path, validation)?;
+
+    lsb::hide(&mut png, &message.to_bytes())
+        .with_context(|| format!("failed to hide message in '{}'", file_path.display()))?;
+
+    png_write_to_file(&png, file_path, output)
+}
+
+/// Reveals the payload hidden in the image data of the PNG file, or `None` if
+/// no message is hidden in it.
+pub fn invoke_reveal(png_path: PathBuf, validation: Validation) -> Result<Option<Payload>> {
+    let file_path = png_path.as_path();
+
+    let png = png_parse(file_path, validation)?;
+
+    let message = lsb::reveal(&png)
+        .with_context(|| format!("failed to reveal message in '{}'", file_path.display()))?;
+
+    message
+        .map(|message| {
+            Payload::from_bytes(message).with_context(|| {
+                format!(
+                    "failed to decode hidden message in '{}'",
+                    file_path.display()
+                )
+            })
+        })
+        .transpose()
+}
+
+/// Returns the maximum length in bytes of a message which can be hidden in the
+/// image data of the PNG file.
+pub fn invoke_capacity(png_path: PathBuf, validation: Validation) -> Result<usize> {
+    let file_path = png_path.as_path();
+
+    let png = png_parse(file_path, validation)?;
+
+    lsb::capacity(&png)
+        .with_context(|| format!("failed to compute capacity of '{}'", file_path.display()))
+}
+
+/// Describes each chunk of the PNG file in order, reading it one chunk at a
+/// time.
+pub fn invoke_inspect(png_path: PathBuf) -> Result<Vec<ChunkInfo>> {
+    let file_path = png_path.as_path();
+
+    inspect::inspect(open_input(file_path)?).with_context(|| InvalidPngError::new(file_path))
+}
+
+/// Converts the image data of the PNG file to the interlace method, keeping
+/// all other chunks, and returns the path of the PNG file written.
+pub fn invoke_interlace(
+    png_path: PathBuf,
+    interlace_method: InterlaceMethod,
+    output: Output,
+    validation: Validation,
+) -> Result<PathBuf> {
+    let file_path = png_path.as_path();
+
+    let mut png = png_parse(file_path, validation)?;
+
+    png.set_interlace_method(
+        interlace_method,
+        FilterStrategy::default(),
+        &DeflateOptions::default(),
+    )
+    .with_context(|| format!("failed to convert image data of '{}'", file_path.display()))?;
+
+    png_write_to_file(&png, file_path, output)
+}
+
+/// Assembles an animated PNG from the PNG files, one per frame in order with
+/// its options, and writes it to the output file.
+pub fn invoke_apng_build(
+    frame_paths: Vec<PathBuf>,
+    options: Vec<FrameOptions>,
+    num_plays: u32,
+    out_path: PathBuf,
+    force: bool,
+    validation: Validation,
+) -> Result<PathBuf> {
+    anyhow::ensure!(
+        frame_paths.len() == options.len(),
+        "{} frames given, but options for {}",
+        frame_paths.len(),
+        options.len()
+    );
+
+    let frames = frame_paths
+        .iter()
+        .zip(options)
+        .map(|(path, options)| Ok((png_parse(path, validation)?, options)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let png = apng::assemble(&frames, num_plays).context("failed to assemble APNG")?;
+
+    let output = Output {
+        force,
+        ..Output::to_path(Some(out_path.clone()))
+    };
+
+    // The animated PNG is never written in place, so it has no input file.
+    png_write_to_file(&png, &out_path, output)
+}
+
+/// Splits the animated PNG file into its frames, written as standalone PNG
+/// files named after it with their index into the output directory, or the
+/// current directory if none is given. Frames of an animated PNG read from
+/// standard input are named `frame` with their index.
+///
+/// Returns the path and frame control of each frame.
+pub fn invoke_apng_split(
+    png_path: PathBuf,
+    out_dir: Option<PathBuf>,
+    force: bool,
+    validation: Validation,
+) -> Result<Vec<(PathBuf, FrameControl)>> {
+    let file_path = png_path.as_path();
+
+    let png = png_parse(file_path, validation)?;
+
+    let frames =
+        apng::split(&png).with_context(|| format!("failed to split '{}'", file_path.display()))?;
+
+    let stem = frame_stem(file_path);
+    let out_dir = out_dir.unwrap_or_else(|| PathBuf::from("."));
+
+    frames
+        .into_iter()
+        .enumerate()
+        .map(|(index, (frame, control))| {
+            let out_path = out_dir.join(format!("{stem}-{index}.png"));
+            let output = Output {
+                force,
+                ..Output::to_path(Some(out_path.clone()))
+            };
+            png_write_to_file(&frame, file_path, output)?;
+
+            Ok((out_path, control))
+        })
+        .collect()
+}
+
+/// Returns the name of the frames split from the animated PNG file, before
+/// their index.
+fn frame_stem(file_path: &Path) -> &str {
+    if is_stdio(file_path) {
+        return "frame";
+    }
+
+    file_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("frame")
+}
+
+/// Reads a payload from the file, along with its filename and MIME type, which
+/// is guessed from its extension unless provided.
+pub fn read_payload(file_path: PathBuf, mime_type: Option<String>) -> Result<Payload> {
+    let data = fs::read(&file_path)
+        .with_context(|| format!("failed to read '{}'", file_path.display()))?;
+
+    let filename = file_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .with_context(|| format!("invalid filename '{}'", file_path.display()))?;
+    let mime_type = mime_type.unwrap_or_else(|| payload::guess_mime_type(filename).to_owned());
+
+    Payload::with_metadata(data, Some(filename.to_owned()), Some(mime_type))
+}
+
+/// Reads a payload from standard input, along with its MIME type if provided.
+pub fn read_payload_stdin(mime_type: Option<String>) -> Result<Payload> {
+    let mut data = Vec::new();
+    io::Read::read_to_end(&mut io::stdin().lock(), &mut data)
+        .context("failed to read payload from standard input")?;
+
+    let mime_type = mime_type.unwrap_or_else(|| payload::DEFAULT_MIME_TYPE.to_owned());
+
+    Payload::with_metadata(data, None, Some(mime_type))
+}
+
+/// Writes the payload data byte-for-byte to the output path, returning the
+/// path written to.
+///
+/// If the output path is an existing directory, the payload is written to a
+/// file within it named after its original filename. [`STDIO_PATH`] writes
+/// to standard output. An existing file is only overwritten if `force` is set.
+pub fn write_payload(payload: &Payload, out_path: PathBuf, force: bool) -> Result<PathBuf> {
+    if is_stdio(&out_path) {
+        write_stdout(payload.data()).context("failed to write payload to standard output")?;
+
+        return Ok(out_path);
+    }
+
+    let outfile = if out_path.is_dir() {
+        let filename = payload.filename().with_context(|| {
+            format!(
+                "failed to write payload to '{}': payload has no filename, so an output file must be given",
+                out_path.display()
+            )
+        })?;
+
+        // Filenames are checked not to contain directories when decoded.
+        out_path.join(filename)
+    } else {
+        out_path
+    };
+
+    create_file(&outfile, force)?
+        .write_all(payload.data())
+        .with_context(|| format!("failed to write payload to '{}'", outfile.display()))?;
+
+    Ok(outfile)
+}
+
+/// Verifies the signature chunks in the PNG file, or only those signing
+/// message chunks with a matching chunk type if provided.
+pub fn invoke_verify(
+    png_path: PathBuf,
+    chunk_type: Option<String>,
+    validation: Validation,
+) -> Result<Vec<Verification>> {
+    let file_path = png_path.as_path();
+
+    let png = png_parse(file_path, validation)?;
+
+    verify(&png, chunk_type.as_deref())
+        .with_context(|| format!("failed to verify signatures in '{}'", file_path.display()))
+}
+
+/// Generates a new identity for decrypting messages encrypted to its public
+/// key.
+///
+/// Writes the identity file to the output path if provided, which must not
+/// already exist. On Unix, the identity file is only readable and writable by
+/// its owner.
+pub fn invoke_keygen(out_path: Option<PathBuf>) -> Result<Identity> {
+    let identity = Identity::generate();
+
+    if let Some(outfile) = out_path {
+        write_key_file(&outfile, &identity.to_file_contents())?;
+    }
+
+    Ok(identity)
+}
+
+/// Generates a new key for signing messages.
+///
+/// Writes the key file to the output path if provided, which must not already
+/// exist. On Unix, the key file is only readable and writable by its owner.
+pub fn invoke_keygen_signing(out_path: Option<PathBuf>) -> Result<SigningKey> {
+    let key = SigningKey::generate();
+
+    if let Some(outfile) = out_path {
+        write_key_file(&outfile, &key.to_file_contents())?;
+    }
+
+    Ok(key)
+}
+
+/// Writes the bytes to standard output, flushing them so they are not lost if
+/// the program exits.
+fn write_stdout(bytes: &[u8]) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+
+    stdout.write_all(bytes)?;
+    stdout.flush()
+}
+
+fn write_key_file(outfile: &Path, contents: &str) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut output = options
+        .open(outfile)
+        .with_context(|| format!("failed to create '{}'", outfile.display()))?;
+
+    output
+        .write_all(contents.as_bytes())
+        .with_context(|| format!("failed to write key to '{}'", outfile.display()))
+}
+
+/// Reads an identity from the identity file.
+pub fn read_identity(identity_path: PathBuf) -> Result<Identity> {
+    read_key_file(&identity_path)
+}
+
+/// Reads a signing key from the key file.
+pub fn read_signing_key(key_path: PathBuf) -> Result<SigningKey> {
+    read_key_file(&key_path)
+}
+
+fn read_key_file<K: FromStr<Err = anyhow::Error>>(key_path: &Path) -> Result<K> {
+    let contents = zeroize::Zeroizing::new(
+        fs::read_to_string(key_path)
+            .with_context(|| format!("failed to read '{}'", key_path.display()))?,
+    );
+
+    K::from_str(&contents)
+        .with_context(|| format!("failed to read key from '{}'", key_path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an empty directory for the test, removing any left over from a
+    /// previous run.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pngme-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn test_create_file_refuses_existing_file() {
+        let dir = test_dir("create-file");
+        let path = dir.join("out.png");
+        fs::write(&path, b"original").unwrap();
+
+        let err = create_file(&path, false).unwrap_err();
+        assert!(err.to_string().contains("refusing to overwrite"));
+        assert_eq!(fs::read(&path).unwrap(), b"original");
+
+        create_file(&path, true).unwrap().write_all(b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_replace_file_with_backup() {
+        let dir = test_dir("replace-file");
+        let path = dir.join("image.png");
+        let backup_path = dir.join("image.png.bak");
+        fs::write(&path, b"original").unwrap();
+
+        replace_file(&path, b"replaced", Some(".bak"), false).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"replaced");
+        assert_eq!(fs::read(&backup_path).unwrap(), b"original");
+
+        // The backup is kept unless forced, leaving the file untouched.
+        let err = replace_file(&path, b"again", Some(".bak"), false).unwrap_err();
+        assert!(err.to_string().contains("refusing to overwrite"));
+        assert_eq!(fs::read(&path).unwrap(), b"replaced");
+
+        replace_file(&path, b"again", Some(".bak"), true).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"again");
+        assert_eq!(fs::read(&backup_path).unwrap(), b"replaced");
+
+        // No temporary files are left behind.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Writes a small PNG file into the directory, returning its path.
+    fn write_png(dir: &Path, name: &str) -> PathBuf {
+        let png = crate::builder::PngBuilder::new(
+            2,
+            2,
+            crate::ihdr::ColorType::Grayscale,
+            8,
+            vec![0, 64, 128, 255],
+        )
+        .build()
+        .unwrap();
+
+        let path = dir.join(name);
+        fs::write(&path, png.as_bytes()).unwrap();
+
+        path
+    }
+
+    /// Returns an output rewriting only the end of the input file.
+    fn tail_output() -> Output {
+        Output {
+            in_place: true,
+            tail: true,
+            ..Output::default()
+        }
+    }
+
+    #[test]
+    fn test_decode_and_remove_reject_missing_ihdr_or_iend() {
+        let dir = test_dir("missing-ihdr-iend");
+        let path = write_png(&dir, "image.png");
+        let mut png = PNG::try_from(fs::read(&path).unwrap().as_slice()).unwrap();
+        encode(&mut png, "ruSt", "message").unwrap();
+
+        let bytes = png.as_bytes();
+        let truncated = dir.join("truncated.png");
+        fs::write(&truncated, &bytes[..bytes.len() - 12]).unwrap();
+
+        png.remove_chunk_at(0).unwrap();
+        let headless = dir.join("headless.png");
+        fs::write(&headless, png.as_bytes()).unwrap();
+
+        let decode = |path: &Path| {
+            invoke_decode(
+                path.to_path_buf(),
+                "ruSt".to_string(),
+                DecodeOptions::default(),
+                Validation::Lenient,
+            )
+            .unwrap_err()
+        };
+        let remove = |path: &Path| {
+            invoke_remove(
+                path.to_path_buf(),
+                "ruSt".to_string(),
+                None,
+                Output::to_path(Some(dir.join("out.png"))),
+                Validation::Lenient,
+            )
+            .unwrap_err()
+        };
+
+        for err in [decode(&truncated), remove(&truncated)] {
+            assert!(
+                matches!(
+                    err.downcast_ref::<Error>(),
+                    Some(Error::Truncated { index: 3, .. })
+                ),
+                "{err:?}"
+            );
+        }
+        for err in [decode(&headless), remove(&headless)] {
+            assert!(
+                matches!(
+                    err.downcast_ref::<Error>(),
+                    Some(Error::Ordering(v)) if v.kind() == ViolationKind::MissingIhdr
+                ),
+                "{err:?}"
+            );
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_decode_fragmented_message() {
+        let mut png =
+            crate::builder::PngBuilder::new(2, 2, crate::ihdr::ColorType::Grayscale, 8, vec![0; 4])
+                .build()
+                .unwrap();
+
+        encode_fragmented(&mut png, "ruSt", "fragmented message", 4).unwrap();
+        assert!(png.chunks_by_type("ruSt").count() > 1);
+        assert_eq!(decode(&png, "ruSt").as_deref(), Some("fragmented message"));
+
+        encode_payload(&mut png, "ruSt", &Payload::new("later message")).unwrap();
+        assert_eq!(decode(&png, "ruSt").as_deref(), Some("fragmented message"));
+    }
+
+    #[test]
+    fn test_encode_tail_matches_rewrite() {
+        let dir = test_dir("encode-tail");
+        let path = write_png(&dir, "image.png");
+        let original = fs::read(&path).unwrap();
+        let rewritten = dir.join("rewritten.png");
+        let opts = || EncodeOptions {
+            fragment_size: Some(4),
+            ..EncodeOptions::default()
+        };
+
+        invoke_encode(
+            path.clone(),
+            "ruSt".to_string(),
+            Payload::new("fragmented message"),
+            Output::to_path(Some(rewritten.clone())),
+            opts(),
+            Validation::Lenient,
+        )
+        .unwrap();
+
+        let encoded = invoke_encode(
+            path.clone(),
+            "ruSt".to_string(),
+            Payload::new("fragmented message"),
+            tail_output(),
+            opts(),
+            Validation::Lenient,
+        )
+        .unwrap();
+        assert_eq!(encoded.chunks.len(), 5);
+        assert_eq!(fs::read(&path).unwrap(), fs::read(&rewritten).unwrap());
+
+        // All fragments are removed back to front, restoring the original.
+        let removed = invoke_remove(
+            path.clone(),
+            "ruSt".to_string(),
+            None,
+            tail_output(),
+            Validation::Lenient,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(removed.payload.data(), b"fragmented message");
+        assert_eq!(removed.chunks.len(), 5);
+        assert_eq!(fs::read(&path).unwrap(), original);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_encode_tail_with_backup() {
+        let dir = test_dir("encode-tail-backup");
+        let path = write_png(&dir, "image.png");
+        let original = fs::read(&path).unwrap();
+        let backup_path = dir.join("image.png.bak");
+        let output = || Output {
+            backup: Some(".bak".to_string()),
+            ..tail_output()
+        };
+        let encode = |output| {
+            invoke_encode(
+                path.clone(),
+                "ruSt".to_string(),
+                Payload::new("message"),
+                output,
+                EncodeOptions::default(),
+                Validation::Lenient,
+            )
+        };
+
+        encode(output()).unwrap();
+        assert_eq!(fs::read(&backup_path).unwrap(), original);
+        let encoded = fs::read(&path).unwrap();
+        assert_ne!(encoded, original);
+
+        // The backup is a copy, kept unless forced, leaving the file untouched.
+        let err = encode(output()).unwrap_err();
+        assert!(err.to_string().contains("refusing to overwrite"), "{err}");
+        assert_eq!(fs::read(&path).unwrap(), encoded);
+
+        encode(Output {
+            force: true,
+            ..output()
+        })
+        .unwrap();
+        assert_eq!(fs::read(&backup_path).unwrap(), encoded);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_remove_tail_not_found() {
+        let dir = test_dir("remove-tail-not-found");
+        let path = write_png(&dir, "image.png");
+
+        invoke_encode(
+            path.clone(),
+            "ruSt".to_string(),
+            Payload::new("message"),
+            tail_output(),
+            EncodeOptions::default(),
+            Validation::Lenient,
+        )
+        .unwrap();
+        let encoded = fs::read(&path).unwrap();
+
+        let removed = invoke_remove(
+            path.clone(),
+            "teSt".to_string(),
+            None,
+            tail_output(),
+            Validation::Lenient,
+        )
+        .unwrap();
+        assert!(removed.is_none());
+        assert_eq!(fs::read(&path).unwrap(), encoded);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_remove_tail_signed_message() {
+        let dir = test_dir("remove-tail-signed");
+        let path = write_png(&dir, "image.png");
+
+        invoke_encode(
+            path.clone(),
+            "ruSt".to_string(),
+            Payload::new("signed message"),
+            Output {
+                in_place: true,
+                ..Output::default()
+            },
+            EncodeOptions {
+                signing: Some(SigningOptions {
+                    key: SigningKey::generate(),
+                    covers_image: false,
+                }),
+                ..EncodeOptions::default()
+            },
+            Validation::Lenient,
+        )
+        .unwrap();
+        let signed = fs::read(&path).unwrap();
+
+        let err = invoke_remove(
+            path.clone(),
+            "ruSt".to_string(),
+            None,
+            tail_output(),
+            Validation::Lenient,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("is signed"));
+        assert_eq!(fs::read(&path).unwrap(), signed);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_frame_stem() {
+        assert_eq!(frame_stem(Path::new("dir/anim.png")), "anim");
+        assert_eq!(frame_stem(Path::new(STDIO_PATH)), "frame");
+    }
+}
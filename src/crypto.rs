@@ -0,0 +1,848 @@
+//! Authenticated encryption of messages.
+//!
+//! Messages are encrypted with ChaCha20-Poly1305, either under a key derived
+//! from a passphrase using Argon2id, or under a random file key which is
+//! wrapped for each of one or more X25519 recipients. The encrypted message is
+//! stored in the chunk data as an envelope, consisting of a versioned header
+//! followed by the ciphertext. Every header starts with:
+//!
+//! | Field      | Size     | Description                              |
+//! |------------|----------|------------------------------------------|
+//! | Magic      | 4 bytes  | `pmEn`                                   |
+//! | Version    | 1 byte   | Envelope version (1)                     |
+//! | Cipher     | 1 byte   | AEAD cipher (1: ChaCha20-Poly1305)       |
+//! | Scheme     | 1 byte   | Key scheme (1: passphrase, 2: recipients)|
+//!
+//! followed, for the passphrase scheme, by:
+//!
+//! | Field      | Size     | Description                              |
+//! |------------|----------|------------------------------------------|
+//! | KDF        | 1 byte   | Key derivation function (1: Argon2id)    |
+//! | Memory     | 4 bytes  | Argon2 memory cost in KiB                |
+//! | Iterations | 4 bytes  | Argon2 number of iterations              |
+//! | Lanes      | 4 bytes  | Argon2 degree of parallelism             |
+//! | Salt       | 16 bytes | Random salt                              |
+//! | Nonce      | 12 bytes | Random nonce                             |
+//!
+//! or, for the recipients scheme, by:
+//!
+//! | Field      | Size        | Description                           |
+//! |------------|-------------|---------------------------------------|
+//! | Ephemeral  | 32 bytes    | Ephemeral X25519 public key           |
+//! | Count      | 2 bytes     | Number of recipients                  |
+//! | Stanzas    | 48 bytes ea.| File key wrapped for each recipient   |
+//! | Nonce      | 12 bytes    | Random nonce                          |
+//!
+//! Each stanza is the file key encrypted with ChaCha20-Poly1305 under a key
+//! derived with HKDF-SHA256 from the X25519 shared secret of the ephemeral key
+//! and the recipient's key. Stanzas do not identify their recipient, so an
+//! identity is tried against each of them.
+//!
+//! All integers are big-endian. The header and the chunk type of the chunk
+//! storing the envelope are authenticated as associated data, so tampering
+//! with either, or moving the envelope to a chunk of a different chunk type,
+//! is detected when decrypting.
+
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{SharedSecret, StaticSecret};
+use zeroize::Zeroizing;
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::chunk_type::ChunkType;
+
+/// Magic bytes identifying an encrypted message.
+const MAGIC: [u8; 4] = *b"pmEn";
+
+/// Current version of the envelope format.
+const VERSION: u8 = 1;
+
+/// Identifier of the ChaCha20-Poly1305 cipher.
+const CIPHER_CHACHA20_POLY1305: u8 = 1;
+
+/// Identifier of the passphrase key scheme.
+const SCHEME_PASSPHRASE: u8 = 1;
+
+/// Identifier of the recipients key scheme.
+const SCHEME_RECIPIENTS: u8 = 2;
+
+/// Identifier of the Argon2id key derivation function.
+const KDF_ARGON2ID: u8 = 1;
+
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// Length in bytes of the header fields shared by all key schemes.
+const PREFIX_LEN: usize = MAGIC.len() + 3;
+
+/// Length in bytes of the header of the passphrase scheme.
+const PASSPHRASE_HEADER_LEN: usize = PREFIX_LEN + 1 + 3 * 4 + SALT_LEN + NONCE_LEN;
+
+/// Length in bytes of a file key wrapped for a recipient.
+const STANZA_LEN: usize = KEY_LEN + TAG_LEN;
+
+/// Domain separation string used when deriving the key wrapping a file key.
+const RECIPIENT_INFO: &[u8] = b"pngme-x25519";
+
+/// Prefix of an encoded public key.
+const PUBLIC_KEY_PREFIX: &str = "pngme-pk-";
+
+/// Prefix of an encoded secret key.
+const SECRET_KEY_PREFIX: &str = "PNGME-SK-";
+
+/// Maximum Argon2 memory cost accepted when decrypting (1 GiB), so a crafted
+/// header cannot exhaust memory.
+const MAX_MEMORY_COST: u32 = 1024 * 1024;
+
+/// Maximum Argon2 number of iterations accepted when decrypting.
+const MAX_ITERATIONS: u32 = 64;
+
+/// Maximum Argon2 degree of parallelism accepted when decrypting.
+const MAX_LANES: u32 = 16;
+
+/// Error returned when an encrypted message fails authentication, either
+/// because the passphrase or identity is wrong or because the message was
+/// tampered with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuthenticationError;
+
+impl fmt::Display for AuthenticationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "authentication failed: wrong passphrase or identity, or the message was tampered with"
+        )
+    }
+}
+
+impl error::Error for AuthenticationError {}
+
+/// Key scheme of an encrypted message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    /// Encrypted under a key derived from a passphrase.
+    Passphrase,
+    /// Encrypted to one or more X25519 recipients.
+    Recipients,
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scheme::Passphrase => write!(f, "passphrase"),
+            Scheme::Recipients => write!(f, "recipients"),
+        }
+    }
+}
+
+/// X25519 public key a message can be encrypted to, encoded as `pngme-pk-`
+/// followed by 64 hexadecimal digits.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PublicKey(x25519_dalek::PublicKey);
+
+impl PublicKey {
+    /// Returns the raw bytes of the public key.
+    pub fn as_bytes(&self) -> &[u8; KEY_LEN] {
+        self.0.as_bytes()
+    }
+}
+
+impl FromStr for PublicKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = s
+            .strip_prefix(PUBLIC_KEY_PREFIX)
+            .and_then(|hex| decode_key(hex).ok())
+            .with_context(|| {
+                format!(
+                    "invalid public key '{s}': must be \"{PUBLIC_KEY_PREFIX}\" followed by {} hexadecimal digits",
+                    KEY_LEN * 2
+                )
+            })?;
+
+        Ok(PublicKey(x25519_dalek::PublicKey::from(*bytes)))
+    }
+}
+
+impl fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{PUBLIC_KEY_PREFIX}{}", hex::encode(self.as_bytes()))
+    }
+}
+
+impl fmt::Debug for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PublicKey({self})")
+    }
+}
+
+/// X25519 secret key which can decrypt messages encrypted to its public key.
+///
+/// Identities are stored in identity files, which contain the secret key
+/// encoded as `PNGME-SK-` followed by 64 hexadecimal digits. Empty lines and
+/// lines starting with `#` are ignored.
+#[derive(Clone)]
+pub struct Identity(StaticSecret);
+
+impl Identity {
+    /// Generates a new random identity.
+    pub fn generate() -> Self {
+        Identity(StaticSecret::random_from_rng(OsRng))
+    }
+
+    /// Returns the public key of the identity.
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(x25519_dalek::PublicKey::from(&self.0))
+    }
+
+    /// Returns the contents of an identity file storing the identity.
+    pub fn to_file_contents(&self) -> Zeroizing<String> {
+        Zeroizing::new(format!(
+            "# public key: {}\n{SECRET_KEY_PREFIX}{}\n",
+            self.public_key(),
+            hex::encode_upper(self.0.as_bytes())
+        ))
+    }
+}
+
+impl FromStr for Identity {
+    type Err = anyhow::Error;
+
+    /// Parses the contents of an identity file.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut keys = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+        let (Some(key), None) = (keys.next(), keys.next()) else {
+            anyhow::bail!("invalid identity: expected exactly one secret key");
+        };
+
+        let bytes = key
+            .strip_prefix(SECRET_KEY_PREFIX)
+            .and_then(|hex| decode_key(hex).ok())
+            .with_context(|| {
+                format!(
+                    "invalid identity: secret key must be \"{SECRET_KEY_PREFIX}\" followed by {} hexadecimal digits",
+                    KEY_LEN * 2
+                )
+            })?;
+
+        Ok(Identity(StaticSecret::from(*bytes)))
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Identity({})", self.public_key())
+    }
+}
+
+/// Decodes a key from hexadecimal digits.
+fn decode_key(hex: &str) -> Result<Zeroizing<[u8; KEY_LEN]>> {
+    let mut key = Zeroizing::new([0; KEY_LEN]);
+    hex::decode_to_slice(hex, key.as_mut())?;
+
+    Ok(key)
+}
+
+/// Argon2id parameters used to derive a key from a passphrase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct KdfParams {
+    memory_cost: u32,
+    iterations: u32,
+    lanes: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            memory_cost: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            lanes: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl KdfParams {
+    /// Derives a key from the passphrase and salt.
+    fn derive_key(&self, passphrase: &[u8], salt: &[u8]) -> Result<Zeroizing<[u8; KEY_LEN]>> {
+        let params = Params::new(self.memory_cost, self.iterations, self.lanes, Some(KEY_LEN))
+            .map_err(|e| anyhow!("invalid key derivation parameters: {e}"))?;
+
+        let mut key = Zeroizing::new([0; KEY_LEN]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase, salt, key.as_mut())
+            .map_err(|e| anyhow!("failed to derive key from passphrase: {e}"))?;
+
+        Ok(key)
+    }
+}
+
+/// Returns `true` if the data is an encrypted message.
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Returns the key scheme of an encrypted message.
+///
+/// # Errors
+///
+/// Returns an error if the data is not an encrypted message, or uses an
+/// unsupported version, cipher, or key scheme.
+pub fn scheme(envelope: &[u8]) -> Result<Scheme> {
+    anyhow::ensure!(
+        is_encrypted(envelope),
+        "invalid encrypted message: missing magic bytes"
+    );
+    anyhow::ensure!(
+        envelope.len() >= PREFIX_LEN,
+        "invalid encrypted message: expected at least {} bytes, found {}",
+        PREFIX_LEN,
+        envelope.len()
+    );
+
+    let [version, cipher, scheme] = [envelope[4], envelope[5], envelope[6]];
+    anyhow::ensure!(
+        version == VERSION,
+        "unsupported encrypted message version: {version}"
+    );
+    anyhow::ensure!(
+        cipher == CIPHER_CHACHA20_POLY1305,
+        "unsupported encrypted message cipher: {cipher}"
+    );
+
+    match scheme {
+        SCHEME_PASSPHRASE => Ok(Scheme::Passphrase),
+        SCHEME_RECIPIENTS => Ok(Scheme::Recipients),
+        _ => anyhow::bail!("unsupported encrypted message key scheme: {scheme}"),
+    }
+}
+
+/// Checks that the encrypted message uses the expected key scheme.
+fn expect_scheme(envelope: &[u8], expected: Scheme) -> Result<()> {
+    let scheme = scheme(envelope)?;
+
+    anyhow::ensure!(
+        scheme == expected,
+        "message is encrypted with the {scheme} key scheme, not the {expected} key scheme"
+    );
+
+    Ok(())
+}
+
+/// Encrypts a message with a key derived from the passphrase, returning the
+/// envelope to be stored in a chunk of the given chunk type.
+///
+/// # Errors
+///
+/// Returns an error if the key could not be derived or the message could not
+/// be encrypted.
+pub fn encrypt(chunk_type: ChunkType, passphrase: &[u8], message: &[u8]) -> Result<Vec<u8>> {
+    encrypt_with(KdfParams::default(), chunk_type, passphrase, message)
+}
+
+fn encrypt_with(
+    params: KdfParams,
+    chunk_type: ChunkType,
+    passphrase: &[u8],
+    message: &[u8],
+) -> Result<Vec<u8>> {
+    let mut salt = [0; SALT_LEN];
+    let mut nonce = [0; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut envelope = Vec::with_capacity(PASSPHRASE_HEADER_LEN + message.len() + TAG_LEN);
+    envelope.extend(MAGIC);
+    envelope.extend([
+        VERSION,
+        CIPHER_CHACHA20_POLY1305,
+        SCHEME_PASSPHRASE,
+        KDF_ARGON2ID,
+    ]);
+    envelope.extend(params.memory_cost.to_be_bytes());
+    envelope.extend(params.iterations.to_be_bytes());
+    envelope.extend(params.lanes.to_be_bytes());
+    envelope.extend(salt);
+    envelope.extend(nonce);
+
+    let key = params.derive_key(passphrase, &salt)?;
+    let ciphertext = seal(&key, &nonce, chunk_type, &envelope, message)?;
+
+    envelope.extend(ciphertext);
+
+    Ok(envelope)
+}
+
+/// Decrypts an encrypted message stored in a chunk of the given chunk type,
+/// using a key derived from the passphrase.
+///
+/// # Errors
+///
+/// Returns an error if the envelope is malformed, uses an unsupported version
+/// or algorithm, or is not encrypted with a passphrase. Returns an
+/// [`AuthenticationError`] if the passphrase is wrong, or if the envelope or
+/// chunk type was tampered with.
+pub fn decrypt(chunk_type: ChunkType, passphrase: &[u8], envelope: &[u8]) -> Result<Vec<u8>> {
+    expect_scheme(envelope, Scheme::Passphrase)?;
+    anyhow::ensure!(
+        envelope.len() >= PASSPHRASE_HEADER_LEN + TAG_LEN,
+        "invalid encrypted message: expected at least {} bytes, found {}",
+        PASSPHRASE_HEADER_LEN + TAG_LEN,
+        envelope.len()
+    );
+
+    let (header, ciphertext) = envelope.split_at(PASSPHRASE_HEADER_LEN);
+    let fields = &header[PREFIX_LEN..];
+
+    let kdf = fields[0];
+    anyhow::ensure!(
+        kdf == KDF_ARGON2ID,
+        "unsupported encrypted message key derivation function: {kdf}"
+    );
+
+    let fields = &fields[1..];
+    let u32_at = |i: usize| u32::from_be_bytes(fields[i..i + 4].try_into().unwrap());
+    let params = KdfParams {
+        memory_cost: u32_at(0),
+        iterations: u32_at(4),
+        lanes: u32_at(8),
+    };
+
+    anyhow::ensure!(
+        params.memory_cost <= MAX_MEMORY_COST
+            && params.iterations <= MAX_ITERATIONS
+            && params.lanes <= MAX_LANES,
+        "invalid encrypted message: key derivation parameters exceed limits (memory: {} KiB, iterations: {}, lanes: {})",
+        params.memory_cost,
+        params.iterations,
+        params.lanes
+    );
+
+    let (salt, nonce) = fields[12..].split_at(SALT_LEN);
+
+    let key = params.derive_key(passphrase, salt)?;
+
+    open(&key, nonce, chunk_type, header, ciphertext)
+}
+
+/// Encrypts a message to one or more recipients, returning the envelope to be
+/// stored in a chunk of the given chunk type.
+///
+/// # Errors
+///
+/// Returns an error if there are no recipients or more than 65535, or if the
+/// message could not be encrypted.
+pub fn encrypt_to_recipients(
+    chunk_type: ChunkType,
+    recipients: &[PublicKey],
+    message: &[u8],
+) -> Result<Vec<u8>> {
+    anyhow::ensure!(!recipients.is_empty(), "at least one recipient is required");
+    let count = u16::try_from(recipients.len())
+        .map_err(|_| anyhow!("too many recipients: {}", recipients.len()))?;
+
+    let mut file_key = Zeroizing::new([0; KEY_LEN]);
+    let mut nonce = [0; NONCE_LEN];
+    OsRng.fill_bytes(file_key.as_mut());
+    OsRng.fill_bytes(&mut nonce);
+
+    // A single ephemeral key is shared by all recipients, so it must be usable
+    // for more than one key agreement.
+    let ephemeral = StaticSecret::random_from_rng(OsRng);
+    let ephemeral_public = PublicKey(x25519_dalek::PublicKey::from(&ephemeral));
+
+    let mut envelope =
+        Vec::with_capacity(recipients_header_len(recipients.len()) + message.len() + TAG_LEN);
+    envelope.extend(MAGIC);
+    envelope.extend([VERSION, CIPHER_CHACHA20_POLY1305, SCHEME_RECIPIENTS]);
+    envelope.extend(ephemeral_public.as_bytes());
+    envelope.extend(count.to_be_bytes());
+
+    for recipient in recipients {
+        let shared = ephemeral.diffie_hellman(&recipient.0);
+        let wrapping_key = wrapping_key(&shared, &ephemeral_public, recipient)
+            .with_context(|| format!("invalid recipient {recipient}"))?;
+
+        let stanza = ChaCha20Poly1305::new(Key::from_slice(wrapping_key.as_ref()))
+            .encrypt(&Nonce::default(), file_key.as_ref())
+            .map_err(|_| anyhow!("failed to wrap file key for recipient {recipient}"))?;
+
+        envelope.extend(stanza);
+    }
+
+    envelope.extend(nonce);
+
+    let ciphertext = seal(&file_key, &nonce, chunk_type, &envelope, message)?;
+
+    envelope.extend(ciphertext);
+
+    Ok(envelope)
+}
+
+/// Decrypts an encrypted message stored in a chunk of the given chunk type,
+/// using the identity of one of its recipients.
+///
+/// # Errors
+///
+/// Returns an error if the envelope is malformed, uses an unsupported version
+/// or algorithm, or is not encrypted to recipients. Returns an
+/// [`AuthenticationError`] if the identity is not a recipient of the message,
+/// or if the envelope or chunk type was tampered with.
+pub fn decrypt_with_identity(
+    chunk_type: ChunkType,
+    identity: &Identity,
+    envelope: &[u8],
+) -> Result<Vec<u8>> {
+    expect_scheme(envelope, Scheme::Recipients)?;
+
+    let fields = &envelope[PREFIX_LEN..];
+    anyhow::ensure!(
+        fields.len() >= KEY_LEN + 2,
+        "invalid encrypted message: expected at least {} bytes, found {}",
+        PREFIX_LEN + KEY_LEN + 2,
+        envelope.len()
+    );
+
+    let count = u16::from_be_bytes([fields[KEY_LEN], fields[KEY_LEN + 1]]) as usize;
+    let header_len = recipients_header_len(count);
+    anyhow::ensure!(
+        envelope.len() >= header_len + TAG_LEN,
+        "invalid encrypted message: expected at least {} bytes for {} recipients, found {}",
+        header_len + TAG_LEN,
+        count,
+        envelope.len()
+    );
+
+    let (header, ciphertext) = envelope.split_at(header_len);
+    let ephemeral_public = PublicKey(x25519_dalek::PublicKey::from(
+        <[u8; KEY_LEN]>::try_from(&fields[..KEY_LEN]).unwrap(),
+    ));
+    let (stanzas, nonce) = header[PREFIX_LEN + KEY_LEN + 2..].split_at(count * STANZA_LEN);
+
+    // A low order ephemeral key yields the same shared secret for every
+    // identity, so it can not have been used to encrypt to this identity.
+    let shared = identity.0.diffie_hellman(&ephemeral_public.0);
+    let wrapping_key = wrapping_key(&shared, &ephemeral_public, &identity.public_key())
+        .map_err(|_| AuthenticationError)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(wrapping_key.as_ref()));
+
+    let file_key = stanzas
+        .chunks_exact(STANZA_LEN)
+        .find_map(|stanza| cipher.decrypt(&Nonce::default(), stanza).ok())
+        .map(Zeroizing::new)
+        .ok_or(AuthenticationError)?;
+
+    let file_key = <&[u8; KEY_LEN]>::try_from(file_key.as_slice())
+        .expect("stanzas contain a wrapped file key");
+
+    open(file_key, nonce, chunk_type, header, ciphertext)
+}
+
+/// Returns the length in bytes of the header of the recipients scheme.
+const fn recipients_header_len(count: usize) -> usize {
+    PREFIX_LEN + KEY_LEN + 2 + count * STANZA_LEN + NONCE_LEN
+}
+
+/// Derives the key wrapping the file key for a recipient from the shared
+/// secret of the ephemeral key and the recipient's key.
+fn wrapping_key(
+    shared: &SharedSecret,
+    ephemeral_public: &PublicKey,
+    recipient: &PublicKey,
+) -> Result<Zeroizing<[u8; KEY_LEN]>> {
+    anyhow::ensure!(shared.was_contributory(), "low order public key");
+
+    let salt: Vec<u8> = ephemeral_public
+        .as_bytes()
+        .iter()
+        .chain(recipient.as_bytes())
+        .copied()
+        .collect();
+
+    let mut key = Zeroizing::new([0; KEY_LEN]);
+    Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes())
+        .expand(RECIPIENT_INFO, key.as_mut())
+        .map_err(|_| anyhow!("failed to derive wrapping key"))?;
+
+    Ok(key)
+}
+
+/// Encrypts a message, authenticating the header and chunk type.
+fn seal(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8],
+    chunk_type: ChunkType,
+    header: &[u8],
+    message: &[u8],
+) -> Result<Vec<u8>> {
+    let aad = associated_data(chunk_type, header);
+
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: message,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| anyhow!("failed to encrypt message"))
+}
+
+/// Decrypts a message, verifying the header and chunk type.
+fn open(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8],
+    chunk_type: ChunkType,
+    header: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>> {
+    let aad = associated_data(chunk_type, header);
+
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| AuthenticationError.into())
+}
+
+/// Returns the associated data authenticated along with the message.
+fn associated_data(chunk_type: ChunkType, header: &[u8]) -> Vec<u8> {
+    chunk_type.bytes().iter().chain(header).copied().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // Minimal parameters, so tests do not spend time deriving keys.
+    const PARAMS: KdfParams = KdfParams {
+        memory_cost: 8,
+        iterations: 1,
+        lanes: 1,
+    };
+
+    fn chunk_type() -> ChunkType {
+        ChunkType::from_str("ruSt").unwrap()
+    }
+
+    fn envelope_with_passphrase(message: &[u8]) -> Vec<u8> {
+        encrypt_with(PARAMS, chunk_type(), b"hunter2", message).unwrap()
+    }
+
+    fn is_authentication_error(err: &anyhow::Error) -> bool {
+        err.downcast_ref::<AuthenticationError>().is_some()
+    }
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let envelope = envelope_with_passphrase(b"This is a secret message!");
+
+        assert!(is_encrypted(&envelope));
+        assert_eq!(envelope.len(), PASSPHRASE_HEADER_LEN + 25 + TAG_LEN);
+
+        let message = decrypt(chunk_type(), b"hunter2", &envelope).unwrap();
+        assert_eq!(message, b"This is a secret message!");
+    }
+
+    #[test]
+    fn test_encrypt_is_randomized() {
+        assert_ne!(
+            envelope_with_passphrase(b"message"),
+            envelope_with_passphrase(b"message")
+        );
+    }
+
+    #[test]
+    fn test_decrypt_wrong_passphrase() {
+        let envelope = envelope_with_passphrase(b"message");
+
+        let err = decrypt(chunk_type(), b"hunter3", &envelope).unwrap_err();
+        assert!(is_authentication_error(&err));
+    }
+
+    #[test]
+    fn test_decrypt_tampered() {
+        let envelope = envelope_with_passphrase(b"message");
+
+        // Tampering with the salt, nonce, or ciphertext must be detected.
+        for i in PASSPHRASE_HEADER_LEN - SALT_LEN - NONCE_LEN..envelope.len() {
+            let mut tampered = envelope.clone();
+            tampered[i] ^= 0x01;
+
+            let err = decrypt(chunk_type(), b"hunter2", &tampered).unwrap_err();
+            assert!(is_authentication_error(&err), "byte {i}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_decrypt_wrong_chunk_type() {
+        let envelope = envelope_with_passphrase(b"message");
+        let other = ChunkType::from_str("ruSs").unwrap();
+
+        let err = decrypt(other, b"hunter2", &envelope).unwrap_err();
+        assert!(is_authentication_error(&err));
+    }
+
+    #[test]
+    fn test_decrypt_unsupported_version() {
+        let mut envelope = envelope_with_passphrase(b"message");
+        envelope[MAGIC.len()] = 2;
+
+        let err = decrypt(chunk_type(), b"hunter2", &envelope).unwrap_err();
+        assert!(!is_authentication_error(&err));
+        assert!(err.to_string().contains("version: 2"));
+    }
+
+    #[test]
+    fn test_decrypt_truncated() {
+        let envelope = envelope_with_passphrase(b"message");
+
+        for len in 0..PASSPHRASE_HEADER_LEN + TAG_LEN {
+            assert!(decrypt(chunk_type(), b"hunter2", &envelope[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_decrypt_excessive_memory_cost() {
+        let mut envelope = envelope_with_passphrase(b"message");
+        envelope[MAGIC.len() + 4..MAGIC.len() + 8].copy_from_slice(&u32::MAX.to_be_bytes());
+
+        let err = decrypt(chunk_type(), b"hunter2", &envelope).unwrap_err();
+        assert!(err.to_string().contains("exceed limits"));
+    }
+
+    #[test]
+    fn test_encrypt_to_recipients() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let recipients = [alice.public_key(), bob.public_key()];
+
+        let envelope = encrypt_to_recipients(chunk_type(), &recipients, b"message").unwrap();
+
+        assert_eq!(scheme(&envelope).unwrap(), Scheme::Recipients);
+        assert_eq!(envelope.len(), recipients_header_len(2) + 7 + TAG_LEN);
+
+        for identity in [&alice, &bob] {
+            let message = decrypt_with_identity(chunk_type(), identity, &envelope).unwrap();
+            assert_eq!(message, b"message");
+        }
+    }
+
+    #[test]
+    fn test_decrypt_with_identity_not_recipient() {
+        let alice = Identity::generate();
+        let envelope =
+            encrypt_to_recipients(chunk_type(), &[alice.public_key()], b"message").unwrap();
+
+        let err =
+            decrypt_with_identity(chunk_type(), &Identity::generate(), &envelope).unwrap_err();
+        assert!(is_authentication_error(&err));
+    }
+
+    #[test]
+    fn test_decrypt_with_identity_tampered() {
+        let alice = Identity::generate();
+        let envelope =
+            encrypt_to_recipients(chunk_type(), &[alice.public_key()], b"message").unwrap();
+
+        // Tampering with the ephemeral key, stanza, nonce, or ciphertext must be
+        // detected.
+        for i in (PREFIX_LEN..PREFIX_LEN + KEY_LEN).chain(PREFIX_LEN + KEY_LEN + 2..envelope.len())
+        {
+            let mut tampered = envelope.clone();
+            tampered[i] ^= 0x01;
+
+            let err = decrypt_with_identity(chunk_type(), &alice, &tampered).unwrap_err();
+            assert!(is_authentication_error(&err), "byte {i}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_decrypt_with_identity_low_order_key() {
+        let alice = Identity::generate();
+        let mut envelope =
+            encrypt_to_recipients(chunk_type(), &[alice.public_key()], b"message").unwrap();
+        envelope[PREFIX_LEN..PREFIX_LEN + KEY_LEN].fill(0);
+
+        let err = decrypt_with_identity(chunk_type(), &alice, &envelope).unwrap_err();
+        assert!(is_authentication_error(&err));
+    }
+
+    #[test]
+    fn test_decrypt_with_identity_truncated() {
+        let alice = Identity::generate();
+        let envelope =
+            encrypt_to_recipients(chunk_type(), &[alice.public_key()], b"message").unwrap();
+
+        for len in 0..recipients_header_len(1) + TAG_LEN {
+            let err = decrypt_with_identity(chunk_type(), &alice, &envelope[..len]).unwrap_err();
+            assert!(!is_authentication_error(&err));
+        }
+    }
+
+    #[test]
+    fn test_decrypt_wrong_scheme() {
+        let alice = Identity::generate();
+        let envelope =
+            encrypt_to_recipients(chunk_type(), &[alice.public_key()], b"message").unwrap();
+
+        let err = decrypt(chunk_type(), b"hunter2", &envelope).unwrap_err();
+        assert!(!is_authentication_error(&err));
+        assert!(err.to_string().contains("recipients key scheme"));
+
+        let envelope = envelope_with_passphrase(b"message");
+        let err = decrypt_with_identity(chunk_type(), &alice, &envelope).unwrap_err();
+        assert!(err.to_string().contains("passphrase key scheme"));
+    }
+
+    #[test]
+    fn test_encrypt_to_no_recipients() {
+        assert!(encrypt_to_recipients(chunk_type(), &[], b"message").is_err());
+    }
+
+    #[test]
+    fn test_identity_roundtrip() {
+        let identity = Identity::generate();
+        let contents = identity.to_file_contents();
+
+        let parsed = Identity::from_str(&contents).unwrap();
+        assert_eq!(parsed.public_key(), identity.public_key());
+
+        let public_key = identity.public_key().to_string();
+        assert!(public_key.starts_with("pngme-pk-"));
+        assert_eq!(
+            PublicKey::from_str(&public_key).unwrap(),
+            identity.public_key()
+        );
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        assert!(PublicKey::from_str("pngme-pk-00").is_err());
+        assert!(PublicKey::from_str(&"0".repeat(64)).is_err());
+        assert!(Identity::from_str("# no key").is_err());
+
+        let identity = Identity::generate().to_file_contents();
+        assert!(Identity::from_str(&format!("{}{}", *identity, *identity)).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+//! Errors returned when parsing PNG datastreams, chunks, and chunk types.
+//!
+//! Each [`Error`] variant carries the context needed to act on it, such as the
+//! byte offset of the offending chunk within the datastream or the rule of the
+//! chunk ordering table that was violated, so callers can match on failures
+//! instead of inspecting error messages.
+
+use std::{error, fmt, io, result};
+
+use crate::chunk_type::ChunkType;
+use crate::ordering::Violation;
+
+/// Result type of the core PNG operations, defaulting to [`Error`].
+pub type Result<T, E = Error> = result::Result<T, E>;
+
+/// Error returned when a PNG datastream, chunk, or chunk type is malformed, or
+/// when reading the datastream fails.
+#[derive(Debug)]
+pub enum Error {
+    /// The datastream does not begin with the PNG signature.
+    InvalidSignature {
+        /// First 8 bytes of the datastream.
+        found: [u8; 8],
+    },
+    /// A chunk type is not a sequence of 4 ASCII letters.
+    InvalidChunkType {
+        /// Bytes of the chunk type.
+        bytes: Vec<u8>,
+    },
+    /// The length field of a chunk exceeds [`i32::MAX`].
+    InvalidLength {
+        /// Length field of the chunk.
+        length: u32,
+        /// Byte offset of the start of the chunk.
+        offset: u64,
+        /// Index of the chunk within the datastream.
+        index: usize,
+    },
+    /// The data of a chunk being created exceeds [`i32::MAX`] bytes.
+    DataTooLong {
+        /// Length of the data in bytes.
+        length: usize,
+    },
+    /// The length field of a chunk does not match the length of its data.
+    LengthMismatch {
+        /// Length field of the chunk.
+        expected: u32,
+        /// Length of the chunk data in bytes.
+        actual: usize,
+    },
+    /// The CRC of a chunk does not match the CRC computed over its chunk type
+    /// and data.
+    CrcMismatch {
+        /// CRC stored in the chunk.
+        expected: u32,
+        /// CRC computed over the chunk type and data.
+        computed: u32,
+        /// Byte offset of the start of the chunk.
+        offset: u64,
+        /// Index of the chunk within the datastream.
+        index: usize,
+    },
+    /// The datastream ends within the PNG signature or a chunk, or before the
+    /// `IEND` chunk.
+    Truncated {
+        /// Byte offset of the start of the incomplete signature or chunk, or
+        /// of the end of the datastream if the `IEND` chunk is missing.
+        offset: u64,
+        /// Index of the incomplete chunk, or of the chunk expected next if the
+        /// `IEND` chunk is missing. The signature is reported as index 0.
+        index: usize,
+    },
+    /// Data follows the `IEND` chunk where the datastream must end, such as
+    /// when editing its end in place.
+    TrailingData {
+        /// Byte offset of the end of the `IEND` chunk.
+        offset: u64,
+    },
+    /// The chunks violate the chunk ordering rules, including a missing
+    /// `IHDR` chunk.
+    Ordering(Violation),
+    /// The contents of a chunk required to interpret the datastream, such as
+    /// `IHDR` or `PLTE`, are invalid.
+    InvalidChunk {
+        /// Chunk type of the chunk.
+        chunk_type: ChunkType,
+        /// Reason the chunk is invalid.
+        source: Box<dyn error::Error + Send + Sync>,
+    },
+    /// The data of a chunk was requested from a chunk reader before reading
+    /// its header.
+    NoChunkHeader,
+    /// Reading the datastream failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidSignature { found } => write!(
+                f,
+                "invalid PNG datastream: malformed PNG signature: {found:?}"
+            ),
+            Error::InvalidChunkType { bytes } => write!(
+                f,
+                "invalid PNG chunk type: \"{}\": must be exactly 4 ASCII letters",
+                bytes.escape_ascii()
+            ),
+            Error::InvalidLength {
+                length,
+                offset,
+                index,
+            } => write!(
+                f,
+                "invalid PNG datastream: failed to parse chunk {index} at byte offset {offset}: data length must not exceed i32::MAX bytes, but received: {length}"
+            ),
+            Error::DataTooLong { length } => write!(
+                f,
+                "invalid PNG chunk: data length must not exceed i32::MAX bytes, but received: {length}"
+            ),
+            Error::LengthMismatch { expected, actual } => write!(
+                f,
+                "invalid PNG chunk: chunk length does not match length of chunk data: expected {expected}, but received {actual}"
+            ),
+            Error::CrcMismatch {
+                expected,
+                computed,
+                offset,
+                index,
+            } => write!(
+                f,
+                "invalid PNG datastream: failed to parse chunk {index} at byte offset {offset}: CRC verification fail: expected 0x{expected:x}, computed 0x{computed:x}"
+            ),
+            Error::Truncated { offset, index } => write!(
+                f,
+                "invalid PNG datastream: failed to parse chunk {index} at byte offset {offset}: datastream is truncated"
+            ),
+            Error::TrailingData { offset } => write!(
+                f,
+                "invalid PNG datastream: unexpected data after the IEND chunk at byte offset {offset}"
+            ),
+            Error::Ordering(violation) => write!(
+                f,
+                "invalid PNG datastream: chunk ordering violation: {violation}"
+            ),
+            Error::InvalidChunk { chunk_type, .. } => {
+                write!(f, "invalid PNG datastream: invalid {chunk_type} chunk")
+            }
+            Error::NoChunkHeader => write!(
+                f,
+                "invalid chunk reader state: no chunk header has been read"
+            ),
+            Error::Io(_) => write!(f, "failed to read PNG datastream"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::InvalidChunk { source, .. } => Some(source.as_ref()),
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -0,0 +1,511 @@
+//! Scanline filtering of PNG image data.
+//!
+//! Before compression, each scanline of the image is transformed by one of
+//! five filter types, recorded in a byte preceding the scanline. Filters
+//! operate on bytes, not pixels: each byte is predicted from the corresponding
+//! byte of the pixel to its left (`a`), the byte above it (`b`), and the byte
+//! above and to the left (`c`), where "corresponding" is determined by the
+//! number of bytes per complete pixel, rounded up to one.
+//!
+//! [`unfilter_image`] reconstructs the scanlines of every pass of the image
+//! from the decompressed image data, and [`filter_image`] filters them again,
+//! choosing the filter type of each scanline with a [`FilterStrategy`].
+
+use std::fmt;
+
+use anyhow::{Context, Result};
+
+use crate::ihdr::Ihdr;
+
+/// Compression level used to compare filter types by brute force.
+const BRUTE_FORCE_LEVEL: u8 = 6;
+
+/// Filter type applied to a scanline.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FilterType {
+    /// The scanline is unmodified.
+    None = 0,
+    /// Each byte is predicted from the byte to its left.
+    Sub = 1,
+    /// Each byte is predicted from the byte above it.
+    Up = 2,
+    /// Each byte is predicted from the mean of the bytes to its left and above.
+    Average = 3,
+    /// Each byte is predicted from whichever of the bytes to its left, above,
+    /// or above and to the left is closest to `a + b - c`.
+    Paeth = 4,
+}
+
+impl FilterType {
+    /// All filter types, in order of their values.
+    pub const ALL: [FilterType; 5] = [
+        FilterType::None,
+        FilterType::Sub,
+        FilterType::Up,
+        FilterType::Average,
+        FilterType::Paeth,
+    ];
+}
+
+impl TryFrom<u8> for FilterType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(FilterType::None),
+            1 => Ok(FilterType::Sub),
+            2 => Ok(FilterType::Up),
+            3 => Ok(FilterType::Average),
+            4 => Ok(FilterType::Paeth),
+            _ => anyhow::bail!("invalid filter type: {value}"),
+        }
+    }
+}
+
+impl fmt::Display for FilterType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FilterType::None => "None",
+            FilterType::Sub => "Sub",
+            FilterType::Up => "Up",
+            FilterType::Average => "Average",
+            FilterType::Paeth => "Paeth",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// Strategy for choosing the filter type of each scanline when filtering.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum FilterStrategy {
+    /// Filters every scanline with the same filter type.
+    Fixed(FilterType),
+    /// Filters each scanline with the filter type minimizing the sum of the
+    /// absolute values of the filtered bytes, taken as signed. This is the
+    /// heuristic recommended by the PNG specification.
+    #[default]
+    MinSumAbs,
+    /// Filters each scanline with every filter type, keeping the one which
+    /// compresses the scanline to the fewest bytes on its own. Slowest, but
+    /// usually produces the smallest image data.
+    BruteForce,
+}
+
+/// Paeth predictor, choosing whichever of `a`, `b`, or `c` is closest to
+/// `a + b - c`, breaking ties in that order.
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Returns the prediction of byte `i` of a scanline from the reconstructed
+/// bytes to its left in `row` and the previous scanline `prev`.
+#[inline]
+fn predict(filter_type: FilterType, row: &[u8], prev: &[u8], i: usize, bpp: usize) -> u8 {
+    let a = if i >= bpp { row[i - bpp] } else { 0 };
+    let b = prev[i];
+    let c = if i >= bpp { prev[i - bpp] } else { 0 };
+
+    match filter_type {
+        FilterType::None => 0,
+        FilterType::Sub => a,
+        FilterType::Up => b,
+        FilterType::Average => ((a as u16 + b as u16) / 2) as u8,
+        FilterType::Paeth => paeth(a, b, c),
+    }
+}
+
+/// Reverses a filter in place, reconstructing a scanline given the
+/// reconstructed previous scanline, which is all zeros for the first one, and
+/// the number of bytes per complete pixel, rounded up to one.
+///
+/// # Panics
+///
+/// Panics if `prev` is shorter than `row`.
+pub fn unfilter_row(filter_type: FilterType, row: &mut [u8], prev: &[u8], bpp: usize) {
+    for i in 0..row.len() {
+        let prediction = predict(filter_type, row, prev, i, bpp);
+        row[i] = row[i].wrapping_add(prediction);
+    }
+}
+
+/// Applies a filter to a scanline given the previous scanline, which is all
+/// zeros for the first one, and the number of bytes per complete pixel,
+/// rounded up to one, appending the filtered bytes to `out`.
+///
+/// # Panics
+///
+/// Panics if `prev` is shorter than `row`.
+pub fn filter_row(filter_type: FilterType, row: &[u8], prev: &[u8], bpp: usize, out: &mut Vec<u8>) {
+    out.extend(
+        (0..row.len()).map(|i| row[i].wrapping_sub(predict(filter_type, row, prev, i, bpp))),
+    );
+}
+
+/// Reconstructs the scanlines of a single pass from its filtered scanlines,
+/// each preceded by its filter type byte, given the length of a scanline in
+/// bytes and the number of bytes per complete pixel, rounded up to one.
+///
+/// # Errors
+///
+/// Returns an error if the length of the filtered data is not a multiple of
+/// the length of a scanline plus its filter type byte, or if a scanline has
+/// an invalid filter type.
+pub fn unfilter(filtered: &[u8], scanline_len: usize, bpp: usize) -> Result<Vec<u8>> {
+    anyhow::ensure!(
+        filtered.len().is_multiple_of(scanline_len + 1),
+        "invalid image data: {} bytes do not form whole scanlines of {} bytes",
+        filtered.len(),
+        scanline_len + 1
+    );
+
+    let rows = filtered.len() / (scanline_len + 1);
+    let mut raw = vec![0; rows * scanline_len];
+    let zeros = vec![0; scanline_len];
+
+    for (y, line) in filtered.chunks_exact(scanline_len + 1).enumerate() {
+        let filter_type = FilterType::try_from(line[0])
+            .with_context(|| format!("invalid image data in scanline {y}"))?;
+
+        let (prev, rest) = raw.split_at_mut(y * scanline_len);
+        let prev = match y {
+            0 => zeros.as_slice(),
+            _ => &prev[prev.len() - scanline_len..],
+        };
+        let row = &mut rest[..scanline_len];
+
+        row.copy_from_slice(&line[1..]);
+        unfilter_row(filter_type, row, prev, bpp);
+    }
+
+    Ok(raw)
+}
+
+/// Filters the scanlines of a single pass, preceding each with its filter
+/// type byte, given the length of a scanline in bytes and the number of bytes
+/// per complete pixel, rounded up to one.
+///
+/// # Panics
+///
+/// Panics if `scanline_len` is zero, or if the length of the scanlines is not
+/// a multiple of it.
+pub fn filter(raw: &[u8], scanline_len: usize, bpp: usize, strategy: FilterStrategy) -> Vec<u8> {
+    assert!(
+        scanline_len > 0 && raw.len().is_multiple_of(scanline_len),
+        "scanlines must be whole"
+    );
+
+    let rows = raw.len() / scanline_len;
+    let zeros = vec![0; scanline_len];
+
+    let mut filtered = Vec::with_capacity(raw.len() + rows);
+    let mut candidate = Vec::with_capacity(scanline_len);
+    let mut prev = zeros.as_slice();
+
+    for row in raw.chunks_exact(scanline_len) {
+        let filter_type = match strategy {
+            FilterStrategy::Fixed(filter_type) => filter_type,
+            FilterStrategy::MinSumAbs => min_by_key(row, prev, bpp, &mut candidate, |filtered| {
+                filtered
+                    .iter()
+                    .map(|&b| (b as i8).unsigned_abs() as u64)
+                    .sum()
+            }),
+            FilterStrategy::BruteForce => min_by_key(row, prev, bpp, &mut candidate, |filtered| {
+                miniz_oxide::deflate::compress_to_vec(filtered, BRUTE_FORCE_LEVEL).len() as u64
+            }),
+        };
+
+        filtered.push(filter_type as u8);
+        filter_row(filter_type, row, prev, bpp, &mut filtered);
+        prev = row;
+    }
+
+    filtered
+}
+
+/// Returns the filter type whose filtered scanline has the lowest cost,
+/// preferring earlier filter types on ties.
+fn min_by_key(
+    row: &[u8],
+    prev: &[u8],
+    bpp: usize,
+    candidate: &mut Vec<u8>,
+    mut cost: impl FnMut(&[u8]) -> u64,
+) -> FilterType {
+    let mut best = (u64::MAX, FilterType::None);
+
+    for filter_type in FilterType::ALL {
+        candidate.clear();
+        filter_row(filter_type, row, prev, bpp, candidate);
+
+        let cost = cost(candidate);
+        if cost < best.0 {
+            best = (cost, filter_type);
+        }
+    }
+
+    best.1
+}
+
+/// Reconstructs the scanlines of every pass of the image from the
+/// decompressed image data.
+///
+/// The scanlines of a non-interlaced image are returned as is, while those of
+/// an interlaced image are returned pass after pass, each pass forming a
+/// reduced image with scanlines of its own width.
+///
+/// # Errors
+///
+/// Returns an error if the length of the image data does not match the one
+/// required by the image header, or if a scanline has an invalid filter type.
+pub fn unfilter_image(ihdr: &Ihdr, filtered: &[u8]) -> Result<Vec<u8>> {
+    let expected = ihdr
+        .image_data_len()
+        .context("invalid IHDR chunk: image is too large")?;
+    anyhow::ensure!(
+        filtered.len() == expected,
+        "invalid image data: {} bytes given, but the IHDR chunk requires {}",
+        filtered.len(),
+        expected
+    );
+
+    let mut raw = Vec::with_capacity(filtered.len());
+    let mut rest = filtered;
+
+    for (pass, (width, height)) in pass_sizes(ihdr).enumerate() {
+        let scanline_len = ihdr.scanline_len(width);
+        let (data, tail) = rest.split_at((scanline_len + 1) * height as usize);
+
+        let pass_raw = unfilter(data, scanline_len, ihdr.bytes_per_pixel())
+            .with_context(|| format!("failed to unfilter pass {}", pass + 1))?;
+        raw.extend(pass_raw);
+        rest = tail;
+    }
+
+    Ok(raw)
+}
+
+/// Filters the scanlines of every pass of the image, laid out as returned by
+/// [`unfilter_image`], into image data ready to be compressed.
+///
+/// # Errors
+///
+/// Returns an error if the length of the scanlines does not match the one
+/// required by the image header.
+pub fn filter_image(ihdr: &Ihdr, raw: &[u8], strategy: FilterStrategy) -> Result<Vec<u8>> {
+    let expected: usize = pass_sizes(ihdr)
+        .map(|(width, height)| ihdr.scanline_len(width) * height as usize)
+        .sum();
+    anyhow::ensure!(
+        raw.len() == expected,
+        "invalid scanlines: {} bytes given, but the IHDR chunk requires {}",
+        raw.len(),
+        expected
+    );
+
+    let mut filtered = Vec::with_capacity(raw.len() + ihdr.height() as usize);
+    let mut rest = raw;
+
+    for (width, height) in pass_sizes(ihdr) {
+        let scanline_len = ihdr.scanline_len(width);
+        let (data, tail) = rest.split_at(scanline_len * height as usize);
+
+        filtered.extend(filter(data, scanline_len, ihdr.bytes_per_pixel(), strategy));
+        rest = tail;
+    }
+
+    Ok(filtered)
+}
+
+/// Returns the dimensions of the non-empty passes over the image.
+fn pass_sizes(ihdr: &Ihdr) -> impl Iterator<Item = (u32, u32)> {
+    ihdr.pass_dimensions()
+        .into_iter()
+        .filter(|&(width, height)| width > 0 && height > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::{ColorType, InterlaceMethod};
+
+    const STRATEGIES: [FilterStrategy; 4] = [
+        FilterStrategy::Fixed(FilterType::None),
+        FilterStrategy::Fixed(FilterType::Paeth),
+        FilterStrategy::MinSumAbs,
+        FilterStrategy::BruteForce,
+    ];
+
+    /// Returns scanlines of pseudo-random bytes with some repetition.
+    fn scanlines(len: usize) -> Vec<u8> {
+        (0..len).map(|i| ((i * i / 7) % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_filter_roundtrip() {
+        let prev: Vec<u8> = (0..24).map(|i| (i * 37) as u8).collect();
+        let row: Vec<u8> = (0..24).map(|i| (i * 91 + 13) as u8).collect();
+
+        for filter_type in FilterType::ALL {
+            for bpp in [1, 2, 3, 4, 6, 8] {
+                let mut filtered = Vec::new();
+                filter_row(filter_type, &row, &prev, bpp, &mut filtered);
+                unfilter_row(filter_type, &mut filtered, &prev, bpp);
+
+                assert_eq!(filtered, row, "{filter_type} with {bpp} bytes per pixel");
+            }
+        }
+    }
+
+    #[test]
+    fn test_filter_known_values() {
+        let prev = [10, 20, 30, 40];
+        let row = [15, 25, 35, 45];
+
+        let filtered = |filter_type| {
+            let mut out = Vec::new();
+            filter_row(filter_type, &row, &prev, 1, &mut out);
+            out
+        };
+
+        assert_eq!(filtered(FilterType::None), [15, 25, 35, 45]);
+        assert_eq!(filtered(FilterType::Sub), [15, 10, 10, 10]);
+        assert_eq!(filtered(FilterType::Up), [5, 5, 5, 5]);
+        assert_eq!(filtered(FilterType::Average), [10, 8, 8, 8]);
+        assert_eq!(filtered(FilterType::Paeth), [5, 5, 5, 5]);
+    }
+
+    #[test]
+    fn test_paeth_tie_breaking() {
+        assert_eq!(paeth(1, 1, 1), 1);
+        assert_eq!(paeth(3, 5, 4), 4);
+        assert_eq!(paeth(0, 0, 255), 0);
+        assert_eq!(paeth(10, 20, 5), 20);
+        assert_eq!(paeth(20, 10, 5), 20);
+    }
+
+    #[test]
+    fn test_invalid_filter_type() {
+        assert_eq!(FilterType::try_from(4).unwrap(), FilterType::Paeth);
+        assert!(FilterType::try_from(5).is_err());
+    }
+
+    #[test]
+    fn test_filter_strategies_roundtrip() {
+        let raw = scanlines(12 * 9);
+
+        for strategy in STRATEGIES {
+            for bpp in [1, 3, 4] {
+                let filtered = filter(&raw, 12, bpp, strategy);
+                assert_eq!(filtered.len(), raw.len() + 9);
+                assert_eq!(unfilter(&filtered, 12, bpp).unwrap(), raw, "{strategy:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_filter_fixed() {
+        let filtered = filter(
+            &scanlines(8 * 4),
+            8,
+            1,
+            FilterStrategy::Fixed(FilterType::Up),
+        );
+
+        assert!(
+            filtered
+                .chunks_exact(9)
+                .all(|line| line[0] == FilterType::Up as u8)
+        );
+    }
+
+    #[test]
+    fn test_filter_min_sum_abs() {
+        // Identical scanlines are best predicted from the scanline above, and
+        // a linear ramp from the byte to the left.
+        let mut raw = vec![10, 200, 30, 140, 50, 60];
+        raw.extend_from_slice(&raw.clone());
+        raw.extend([1, 2, 3, 4, 5, 6]);
+
+        let filtered = filter(&raw, 6, 1, FilterStrategy::MinSumAbs);
+        let filter_types: Vec<_> = filtered.chunks_exact(7).map(|line| line[0]).collect();
+
+        assert_eq!(filter_types[1], FilterType::Up as u8);
+        assert_eq!(filter_types[2], FilterType::Sub as u8);
+    }
+
+    #[test]
+    fn test_filter_brute_force_is_smallest() {
+        let raw = scanlines(64 * 16);
+        let compressed_len =
+            |filtered: &[u8]| miniz_oxide::deflate::compress_to_vec(filtered, 6).len();
+
+        let brute_force = filter(&raw, 64, 3, FilterStrategy::BruteForce);
+        let first = &brute_force[1..65];
+
+        for filter_type in FilterType::ALL {
+            let mut candidate = Vec::new();
+            filter_row(filter_type, &raw[..64], &[0; 64], 3, &mut candidate);
+            assert!(compressed_len(first) <= compressed_len(&candidate));
+        }
+    }
+
+    #[test]
+    fn test_unfilter_invalid_data() {
+        let mut filtered = filter(&scanlines(8 * 2), 8, 1, FilterStrategy::MinSumAbs);
+        assert!(unfilter(&filtered[1..], 8, 1).is_err());
+
+        filtered[9] = 5;
+        assert!(unfilter(&filtered, 8, 1).is_err());
+    }
+
+    #[test]
+    fn test_filter_image_every_format() {
+        for (bit_depth, color_type) in [
+            (1, ColorType::Grayscale),
+            (2, ColorType::Grayscale),
+            (4, ColorType::Indexed),
+            (8, ColorType::Indexed),
+            (16, ColorType::Grayscale),
+            (8, ColorType::Truecolor),
+            (16, ColorType::Truecolor),
+            (8, ColorType::GrayscaleAlpha),
+            (16, ColorType::GrayscaleAlpha),
+            (8, ColorType::TruecolorAlpha),
+            (16, ColorType::TruecolorAlpha),
+        ] {
+            for interlace_method in [InterlaceMethod::None, InterlaceMethod::Adam7] {
+                let ihdr = Ihdr::new(13, 7, bit_depth, color_type, interlace_method).unwrap();
+                let raw_len = ihdr.image_data_len().unwrap()
+                    - pass_sizes(&ihdr)
+                        .map(|(_, height)| height as usize)
+                        .sum::<usize>();
+                let raw = scanlines(raw_len);
+
+                for strategy in STRATEGIES {
+                    let filtered = filter_image(&ihdr, &raw, strategy).unwrap();
+                    assert_eq!(filtered.len(), ihdr.image_data_len().unwrap());
+                    assert_eq!(
+                        unfilter_image(&ihdr, &filtered).unwrap(),
+                        raw,
+                        "{bit_depth}-bit {color_type}, {interlace_method:?}, {strategy:?}"
+                    );
+                }
+
+                assert!(filter_image(&ihdr, &raw[1..], FilterStrategy::MinSumAbs).is_err());
+            }
+        }
+    }
+}
@@ -0,0 +1,515 @@
+//! Messages fragmented across several chunks.
+//!
+//! Messages too large to fit comfortably in a single chunk are split into
+//! numbered fragments, each stored in its own chunk with the same chunk type.
+//! Every fragment is prefixed with a header:
+//!
+//! | Field        | Size     | Description                                  |
+//! |--------------|----------|----------------------------------------------|
+//! | Magic        | 4 bytes  | `pmFR`                                       |
+//! | Version      | 1 byte   | Fragment version (1)                         |
+//! | Index        | 4 bytes  | Sequence index of the fragment, from 0       |
+//! | Total        | 4 bytes  | Total number of fragments of the message     |
+//! | Message hash | 32 bytes | SHA-256 digest of the whole message          |
+//! | CRC          | 4 bytes  | CRC-32 of the header fields above and data   |
+//!
+//! followed by the fragment data. The message hash ties the fragments of a
+//! message together and is checked once they are reassembled, while the CRC
+//! of each fragment identifies which ones were corrupted.
+
+use std::fmt;
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+
+use crate::chunk::Crc;
+
+/// Magic bytes identifying a fragment header.
+const MAGIC: [u8; 4] = *b"pmFR";
+
+/// Current version of the fragment header.
+const VERSION: u8 = 1;
+
+/// Length of a message hash in bytes.
+const HASH_LEN: usize = 32;
+
+/// Length of the fragment header in bytes.
+pub const HEADER_LEN: usize = MAGIC.len() + 1 + 4 + 4 + HASH_LEN + 4;
+
+/// Fragment size used when a message is too large to store in a single chunk.
+pub const DEFAULT_FRAGMENT_SIZE: usize = 1 << 20;
+
+/// Maximum fragment size, so that each fragment fits in a single chunk.
+pub const MAX_FRAGMENT_SIZE: usize = i32::MAX as usize - HEADER_LEN;
+
+/// Single fragment of a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+    index: u32,
+    total: u32,
+    message_hash: [u8; HASH_LEN],
+    data: Vec<u8>,
+}
+
+impl Fragment {
+    /// Returns the sequence index of the fragment, counting from 0.
+    #[inline]
+    pub const fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Returns the total number of fragments of the message.
+    #[inline]
+    pub const fn total(&self) -> u32 {
+        self.total
+    }
+
+    /// Returns the SHA-256 digest of the whole message.
+    #[inline]
+    pub const fn message_hash(&self) -> &[u8; HASH_LEN] {
+        &self.message_hash
+    }
+
+    /// Returns a shared reference to the fragment data.
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Encodes the fragment, along with its header, as chunk data.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.data.len());
+        bytes.extend(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend(self.index.to_be_bytes());
+        bytes.extend(self.total.to_be_bytes());
+        bytes.extend(self.message_hash);
+        bytes.extend(self.crc().to_be_bytes());
+        bytes.extend(&self.data);
+
+        bytes
+    }
+
+    /// Decodes a fragment from chunk data, returning it along with whether its
+    /// CRC matches.
+    fn parse(bytes: &[u8]) -> Result<(Self, bool)> {
+        anyhow::ensure!(is_fragment(bytes), "not a fragment");
+        anyhow::ensure!(
+            bytes.len() >= HEADER_LEN,
+            "invalid fragment header: header is truncated"
+        );
+
+        let version = bytes[MAGIC.len()];
+        anyhow::ensure!(
+            version == VERSION,
+            "unsupported fragment header version: {version}"
+        );
+
+        let be_u32 = |at: usize| u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap());
+        let crc_at = HEADER_LEN - 4;
+
+        let fragment = Fragment {
+            index: be_u32(5),
+            total: be_u32(9),
+            message_hash: bytes[13..crc_at].try_into().unwrap(),
+            data: bytes[HEADER_LEN..].to_vec(),
+        };
+        let intact = fragment.crc() == be_u32(crc_at);
+
+        // The CRC does not authenticate the header, so an intact header may
+        // still be crafted.
+        anyhow::ensure!(
+            !intact || fragment.index < fragment.total,
+            "invalid fragment header: index {} is out of range for {} fragments",
+            fragment.index,
+            fragment.total
+        );
+
+        Ok((fragment, intact))
+    }
+
+    /// Returns the CRC of the header fields and data of the fragment.
+    fn crc(&self) -> u32 {
+        let mut crc = Crc::new();
+        crc.update(&self.index.to_be_bytes());
+        crc.update(&self.total.to_be_bytes());
+        crc.update(&self.message_hash);
+        crc.update(&self.data);
+        crc.finalize()
+    }
+}
+
+impl TryFrom<&[u8]> for Fragment {
+    type Error = anyhow::Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        let (fragment, intact) = Fragment::parse(bytes)?;
+
+        anyhow::ensure!(
+            intact,
+            "fragment {} of {} is corrupted: CRC mismatch",
+            fragment.index,
+            fragment.total
+        );
+
+        Ok(fragment)
+    }
+}
+
+/// Returns `true` if the chunk data starts with a fragment header.
+pub fn is_fragment(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Returns the message hash from the header of a fragment's chunk data,
+/// without checking its CRC, or `None` if the header is truncated.
+pub fn message_hash(data: &[u8]) -> Option<&[u8; HASH_LEN]> {
+    if !is_fragment(data) {
+        return None;
+    }
+
+    data.get(13..13 + HASH_LEN)?.try_into().ok()
+}
+
+/// Splits a message into fragments of at most `fragment_size` bytes each.
+///
+/// An empty message is split into a single, empty fragment.
+///
+/// # Errors
+///
+/// Returns an error if the fragment size is 0 or exceeds
+/// [`MAX_FRAGMENT_SIZE`], or if the message would need more than
+/// [`u32::MAX`] fragments.
+pub fn split(message: &[u8], fragment_size: usize) -> Result<Vec<Fragment>> {
+    anyhow::ensure!(
+        (1..=MAX_FRAGMENT_SIZE).contains(&fragment_size),
+        "invalid fragment size {fragment_size}: must be 1-{MAX_FRAGMENT_SIZE} bytes"
+    );
+
+    let total = message.len().div_ceil(fragment_size).max(1);
+    let total = u32::try_from(total).map_err(|_| {
+        anyhow::anyhow!(
+            "message of {} bytes needs too many fragments",
+            message.len()
+        )
+    })?;
+    let message_hash: [u8; HASH_LEN] = Sha256::digest(message).into();
+
+    let mut fragments: Vec<_> = message
+        .chunks(fragment_size)
+        .zip(0..)
+        .map(|(data, index)| Fragment {
+            index,
+            total,
+            message_hash,
+            data: data.to_vec(),
+        })
+        .collect();
+
+    if fragments.is_empty() {
+        fragments.push(Fragment {
+            index: 0,
+            total,
+            message_hash,
+            data: Vec::new(),
+        });
+    }
+
+    Ok(fragments)
+}
+
+/// Reassembles a message from the data of its fragment chunks, in any order.
+///
+/// The message is the one of the first intact fragment; fragments of other
+/// messages are ignored, as are duplicates of a fragment.
+///
+/// # Errors
+///
+/// Returns a [`ReassemblyError`] listing the missing and corrupted fragments
+/// if any are, or if the reassembled message does not match its hash, or an
+/// error if a fragment header is malformed, including an intact header whose
+/// index is not less than its total.
+pub fn reassemble<'a>(fragments: impl IntoIterator<Item = &'a [u8]>) -> Result<Vec<u8>> {
+    let mut parsed = Vec::new();
+    for bytes in fragments {
+        parsed.push(Fragment::parse(bytes)?);
+    }
+
+    let Some((first, _)) = parsed.iter().find(|(_, intact)| *intact) else {
+        return Err(ReassemblyError::AllCorrupted {
+            count: parsed.len(),
+        }
+        .into());
+    };
+    let (total, message_hash) = (first.total, first.message_hash);
+
+    // The total is only trusted as far as the fragments found can back it, so
+    // a crafted header can not force a huge allocation.
+    if total as usize > parsed.len().saturating_mul(2) {
+        return Err(ReassemblyError::TooFewFragments {
+            total,
+            found: parsed.len(),
+        }
+        .into());
+    }
+
+    let mut slots: Vec<Option<&[u8]>> = vec![None; total as usize];
+    let mut corrupted = Vec::new();
+
+    for (fragment, intact) in &parsed {
+        if !intact {
+            // The index of a corrupted fragment may be corrupted too, so it
+            // is only reported if it is plausible.
+            if fragment.index < total {
+                corrupted.push(fragment.index);
+            }
+        } else if fragment.total == total && fragment.message_hash == message_hash {
+            slots[fragment.index as usize].get_or_insert(&fragment.data);
+        }
+    }
+
+    corrupted.retain(|&index| slots[index as usize].is_none());
+    corrupted.sort_unstable();
+    corrupted.dedup();
+
+    let missing: Vec<u32> = (0..total)
+        .filter(|&index| {
+            slots[index as usize].is_none() && corrupted.binary_search(&index).is_err()
+        })
+        .collect();
+
+    if !missing.is_empty() || !corrupted.is_empty() {
+        return Err(ReassemblyError::Incomplete {
+            total,
+            missing,
+            corrupted,
+        }
+        .into());
+    }
+
+    let message: Vec<u8> = slots.into_iter().flatten().flatten().copied().collect();
+
+    if Sha256::digest(&message).as_slice() != message_hash {
+        return Err(ReassemblyError::HashMismatch.into());
+    }
+
+    Ok(message)
+}
+
+/// Error reassembling a message from its fragments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReassemblyError {
+    /// Some fragments of the message are missing or corrupted.
+    Incomplete {
+        /// Total number of fragments of the message.
+        total: u32,
+        /// Indices of the fragments which could not be found.
+        missing: Vec<u32>,
+        /// Indices of the fragments whose CRC does not match.
+        corrupted: Vec<u32>,
+    },
+    /// Fewer than half of the fragments of the message were found, so they
+    /// are not listed individually.
+    TooFewFragments {
+        /// Total number of fragments of the message.
+        total: u32,
+        /// Number of fragments found.
+        found: usize,
+    },
+    /// Every fragment is corrupted, so the message can not be identified.
+    AllCorrupted {
+        /// Number of corrupted fragments.
+        count: usize,
+    },
+    /// All fragments are intact, but the reassembled message does not match
+    /// its hash.
+    HashMismatch,
+}
+
+impl fmt::Display for ReassemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |indices: &[u32]| {
+            indices
+                .iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        match self {
+            ReassemblyError::Incomplete {
+                total,
+                missing,
+                corrupted,
+            } => {
+                write!(f, "message is incomplete:")?;
+                if !missing.is_empty() {
+                    write!(f, " missing fragments [{}]", list(missing))?;
+                }
+                if !missing.is_empty() && !corrupted.is_empty() {
+                    write!(f, ",")?;
+                }
+                if !corrupted.is_empty() {
+                    write!(f, " corrupted fragments [{}]", list(corrupted))?;
+                }
+                write!(f, " of {total} (indexed from 0)")
+            }
+            ReassemblyError::TooFewFragments { total, found } => write!(
+                f,
+                "message is incomplete: only {found} of {total} fragments were found"
+            ),
+            ReassemblyError::AllCorrupted { count } => {
+                write!(f, "all {count} fragments of the message are corrupted")
+            }
+            ReassemblyError::HashMismatch => write!(
+                f,
+                "reassembled message does not match its hash: fragments of different messages were mixed"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReassemblyError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragment_bytes(message: &[u8], fragment_size: usize) -> Vec<Vec<u8>> {
+        split(message, fragment_size)
+            .unwrap()
+            .iter()
+            .map(Fragment::to_bytes)
+            .collect()
+    }
+
+    fn reassemble_error(fragments: &[Vec<u8>]) -> ReassemblyError {
+        reassemble(fragments.iter().map(Vec::as_slice))
+            .unwrap_err()
+            .downcast()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_split_sizes() {
+        let fragments = split(&[7; 10], 4).unwrap();
+
+        let sizes: Vec<_> = fragments.iter().map(|f| f.data().len()).collect();
+        assert_eq!(sizes, [4, 4, 2]);
+        assert!(fragments.iter().all(|f| f.total() == 3));
+        assert!(fragments.iter().zip(0..).all(|(f, i)| f.index() == i));
+
+        assert_eq!(split(&[], 4).unwrap().len(), 1);
+        assert!(split(&[7; 10], 0).is_err());
+    }
+
+    #[test]
+    fn test_fragment_roundtrip() {
+        let fragment = &split(b"hidden message", 5).unwrap()[1];
+        let bytes = fragment.to_bytes();
+
+        assert!(is_fragment(&bytes));
+        assert_eq!(message_hash(&bytes), Some(fragment.message_hash()));
+        assert_eq!(bytes.len(), HEADER_LEN + 5);
+        assert_eq!(Fragment::try_from(bytes.as_slice()).unwrap(), *fragment);
+    }
+
+    #[test]
+    fn test_reassemble_out_of_order() {
+        let message = b"a message spread over several fragments";
+        let mut fragments = fragment_bytes(message, 8);
+        fragments.reverse();
+        fragments.push(fragments[1].clone());
+
+        let reassembled = reassemble(fragments.iter().map(Vec::as_slice)).unwrap();
+        assert_eq!(reassembled, message);
+    }
+
+    #[test]
+    fn test_reassemble_reports_missing_and_corrupted() {
+        let mut fragments = fragment_bytes(&[1; 100], 10);
+
+        // Corrupt fragments 3 and 7, and drop fragments 5 and 9.
+        fragments[3][HEADER_LEN] ^= 1;
+        *fragments[7].last_mut().unwrap() ^= 1;
+        fragments.remove(9);
+        fragments.remove(5);
+
+        let error = reassemble_error(&fragments);
+        assert_eq!(
+            error,
+            ReassemblyError::Incomplete {
+                total: 10,
+                missing: vec![5, 9],
+                corrupted: vec![3, 7],
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "message is incomplete: missing fragments [5, 9], corrupted fragments [3, 7] of 10 (indexed from 0)"
+        );
+    }
+
+    #[test]
+    fn test_reassemble_corrupted_duplicate_is_ignored() {
+        let mut fragments = fragment_bytes(b"hidden message", 4);
+        let mut corrupted = fragments[2].clone();
+        corrupted[HEADER_LEN] ^= 1;
+        fragments.push(corrupted);
+
+        let reassembled = reassemble(fragments.iter().map(Vec::as_slice)).unwrap();
+        assert_eq!(reassembled, b"hidden message");
+    }
+
+    #[test]
+    fn test_reassemble_ignores_other_messages() {
+        let mut fragments = fragment_bytes(b"first message", 4);
+        fragments.extend(fragment_bytes(b"second message", 4));
+
+        let reassembled = reassemble(fragments.iter().map(Vec::as_slice)).unwrap();
+        assert_eq!(reassembled, b"first message");
+    }
+
+    #[test]
+    fn test_reassemble_all_corrupted() {
+        let mut fragments = fragment_bytes(b"hidden message", 100);
+        fragments[0][HEADER_LEN] ^= 1;
+
+        assert_eq!(
+            reassemble_error(&fragments),
+            ReassemblyError::AllCorrupted { count: 1 }
+        );
+    }
+
+    #[test]
+    fn test_reassemble_malformed_header() {
+        let fragments = fragment_bytes(b"hidden message", 100);
+
+        assert!(reassemble([&fragments[0][..HEADER_LEN - 1]]).is_err());
+        assert!(reassemble([&b"pmFR\x02"[..]]).is_err());
+        assert!(reassemble([&b"not a fragment"[..]]).is_err());
+    }
+
+    #[test]
+    fn test_reassemble_rejects_index_out_of_range() {
+        let mut fragment = split(b"hidden message", 100).unwrap().remove(0);
+        fragment.index = 5;
+
+        let error = reassemble([fragment.to_bytes().as_slice()]).unwrap_err();
+        assert!(error.to_string().contains("index 5 is out of range"));
+    }
+
+    #[test]
+    fn test_reassemble_rejects_huge_total() {
+        let mut fragment = split(b"hidden message", 100).unwrap().remove(0);
+        fragment.total = u32::MAX;
+
+        assert_eq!(
+            reassemble_error(&[fragment.to_bytes()]),
+            ReassemblyError::TooFewFragments {
+                total: u32::MAX,
+                found: 1,
+            }
+        );
+    }
+}
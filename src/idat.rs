@@ -0,0 +1,449 @@
+//! Compression of the image data stored in `IDAT` chunks.
+//!
+//! The image data of a PNG is a single zlib datastream, split across one or
+//! more consecutive `IDAT` chunks at arbitrary boundaries. Decompressed, it
+//! holds the filtered scanlines of the image, each preceded by its filter type
+//! byte, whose total length is determined by the `IHDR` chunk.
+//!
+//! [`PNG::inflate_image_data`] joins and decompresses the image data, and
+//! [`PNG::deflate_image_data`] compresses filtered scanlines back into `IDAT`
+//! chunks.
+
+use std::str::FromStr;
+use std::{fmt, result};
+
+use anyhow::{Context, Result};
+use miniz_oxide::inflate::TINFLStatus;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::PNG;
+
+/// Compression level used by default, balancing size and speed.
+pub const DEFAULT_COMPRESSION_LEVEL: u8 = 6;
+
+/// Maximum compression level.
+pub const MAX_COMPRESSION_LEVEL: u8 = 9;
+
+/// Length in bytes of the data of each `IDAT` chunk used by default.
+pub const DEFAULT_CHUNK_SIZE: usize = 8192;
+
+/// Options for compressing image data into `IDAT` chunks.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DeflateOptions {
+    /// Compression level, from 0 (no compression) to 9 (smallest output).
+    pub level: u8,
+    /// Maximum length in bytes of the data of each `IDAT` chunk.
+    pub chunk_size: usize,
+}
+
+impl Default for DeflateOptions {
+    fn default() -> Self {
+        DeflateOptions {
+            level: DEFAULT_COMPRESSION_LEVEL,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+}
+
+/// Error decompressing the image data of a PNG.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageDataError {
+    /// The PNG has no `IDAT` chunks.
+    Missing,
+    /// The zlib header is malformed or uses features PNG does not allow.
+    InvalidZlibHeader(&'static str),
+    /// The Adler-32 checksum of the decompressed data does not match the one
+    /// stored at the end of the zlib datastream.
+    ChecksumMismatch,
+    /// The compressed data is malformed or ends prematurely.
+    Corrupt(&'static str),
+    /// The decompressed data is longer than the image requires.
+    TooLong {
+        /// Length in bytes required by the image header.
+        expected: usize,
+    },
+    /// The decompressed data is shorter than the image requires.
+    TooShort {
+        /// Length in bytes required by the image header.
+        expected: usize,
+        /// Length in bytes of the decompressed data.
+        actual: usize,
+    },
+}
+
+impl fmt::Display for ImageDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageDataError::Missing => write!(f, "invalid PNG datastream: missing IDAT chunk"),
+            ImageDataError::InvalidZlibHeader(reason) => {
+                write!(f, "invalid image data: corrupt zlib header: {reason}")
+            }
+            ImageDataError::ChecksumMismatch => write!(
+                f,
+                "invalid image data: Adler-32 checksum of the decompressed data does not match"
+            ),
+            ImageDataError::Corrupt(reason) => write!(f, "invalid image data: {reason}"),
+            ImageDataError::TooLong { expected } => write!(
+                f,
+                "invalid image data: decompressed data exceeds the {expected} bytes required by the IHDR chunk"
+            ),
+            ImageDataError::TooShort { expected, actual } => write!(
+                f,
+                "invalid image data: decompressed data is {actual} bytes, but the IHDR chunk requires {expected}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ImageDataError {}
+
+impl PNG {
+    /// Returns the compressed image data, joined from all `IDAT` chunks.
+    pub fn compressed_image_data(&self) -> Vec<u8> {
+        self.chunks_by_type("IDAT")
+            .flat_map(|c| c.data())
+            .copied()
+            .collect()
+    }
+
+    /// Returns the decompressed image data: the filtered scanlines of the
+    /// image, each preceded by its filter type byte.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `IHDR` chunk is invalid, or an
+    /// [`ImageDataError`] if the image data is missing or corrupt, or if its
+    /// length does not match the one required by the `IHDR` chunk.
+    pub fn inflate_image_data(&self) -> Result<Vec<u8>> {
+        let ihdr = self.ihdr()?;
+        let expected = ihdr
+            .image_data_len()
+            .context("invalid IHDR chunk: image is too large")?;
+
+        let compressed = self.compressed_image_data();
+        if compressed.is_empty() {
+            return Err(ImageDataError::Missing.into());
+        }
+
+        Ok(inflate(&compressed, expected)?)
+    }
+
+    /// Compresses the filtered scanlines of the image, each preceded by its
+    /// filter type byte, replacing the `IDAT` chunks of the PNG.
+    ///
+    /// The new `IDAT` chunks take the place of the first existing one, or are
+    /// inserted before the `IEND` chunk if there are none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `IHDR` chunk is invalid, if the length of the
+    /// image data does not match the one required by the `IHDR` chunk, or if
+    /// the options are invalid.
+    pub fn deflate_image_data(&mut self, filtered: &[u8], options: &DeflateOptions) -> Result<()> {
+        let expected = self
+            .ihdr()?
+            .image_data_len()
+            .context("invalid IHDR chunk: image is too large")?;
+
+        anyhow::ensure!(
+            filtered.len() == expected,
+            "invalid image data: {} bytes given, but the IHDR chunk requires {}",
+            filtered.len(),
+            expected
+        );
+        anyhow::ensure!(
+            options.level <= MAX_COMPRESSION_LEVEL,
+            "invalid compression level {}: must be 0-{}",
+            options.level,
+            MAX_COMPRESSION_LEVEL
+        );
+        anyhow::ensure!(
+            (1..=i32::MAX as usize).contains(&options.chunk_size),
+            "invalid IDAT chunk size {}: must be 1-{} bytes",
+            options.chunk_size,
+            i32::MAX
+        );
+
+        let compressed = miniz_oxide::deflate::compress_to_vec_zlib(filtered, options.level);
+
+        let chunk_type = ChunkType::from_str("IDAT")?;
+        let chunks = compressed
+            .chunks(options.chunk_size)
+            .map(|data| Chunk::new(chunk_type, data.to_vec()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let is_idat = |c: &Chunk| c.chunk_type().bytes() == *b"IDAT";
+        let idx = match self.chunks().iter().position(is_idat) {
+            Some(idx) => idx,
+            None => self
+                .chunks()
+                .iter()
+                .position(|c| c.chunk_type().bytes() == *b"IEND")
+                .unwrap_or(self.chunks().len()),
+        };
+
+        while let Some(idx) = self.chunks().iter().rposition(is_idat) {
+            self.remove_chunk_at(idx);
+        }
+
+        for (offset, chunk) in chunks.into_iter().enumerate() {
+            self.insert_chunk(idx + offset, chunk);
+        }
+
+        Ok(())
+    }
+}
+
+/// Decompresses a zlib datastream which must hold exactly `expected` bytes.
+fn inflate(compressed: &[u8], expected: usize) -> result::Result<Vec<u8>, ImageDataError> {
+    check_zlib_header(compressed)?;
+
+    let decompressed = miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(
+        compressed, expected,
+    )
+    .map_err(|e| match e.status {
+        TINFLStatus::Adler32Mismatch => ImageDataError::ChecksumMismatch,
+        TINFLStatus::HasMoreOutput => ImageDataError::TooLong { expected },
+        TINFLStatus::FailedCannotMakeProgress | TINFLStatus::NeedsMoreInput => {
+            ImageDataError::Corrupt("compressed data ends prematurely")
+        }
+        _ => ImageDataError::Corrupt("malformed deflate stream"),
+    })?;
+
+    if decompressed.len() != expected {
+        return Err(ImageDataError::TooShort {
+            expected,
+            actual: decompressed.len(),
+        });
+    }
+
+    Ok(decompressed)
+}
+
+/// Checks the two-byte zlib header, which for PNG must use deflate with a
+/// window of at most 32 KiB and no preset dictionary.
+fn check_zlib_header(compressed: &[u8]) -> result::Result<(), ImageDataError> {
+    let [cmf, flg, ..] = *compressed else {
+        return Err(ImageDataError::InvalidZlibHeader("header is truncated"));
+    };
+
+    if cmf & 0x0F != 8 {
+        return Err(ImageDataError::InvalidZlibHeader(
+            "compression method is not deflate",
+        ));
+    }
+    if cmf >> 4 > 7 {
+        return Err(ImageDataError::InvalidZlibHeader(
+            "window size exceeds 32 KiB",
+        ));
+    }
+    if (u16::from(cmf) << 8 | u16::from(flg)) % 31 != 0 {
+        return Err(ImageDataError::InvalidZlibHeader(
+            "header check bits are invalid",
+        ));
+    }
+    if flg & 0x20 != 0 {
+        return Err(ImageDataError::InvalidZlibHeader(
+            "preset dictionaries are not allowed",
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::{ColorType, Ihdr, InterlaceMethod};
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data).unwrap()
+    }
+
+    /// Returns a 16x16 8-bit greyscale PNG without image data, and filtered
+    /// scanlines for it.
+    fn empty_png() -> (PNG, Vec<u8>) {
+        let ihdr = Ihdr::new(16, 16, 8, ColorType::Grayscale, InterlaceMethod::None).unwrap();
+        let png = PNG::from_chunks(vec![
+            chunk("IHDR", ihdr.as_bytes().to_vec()),
+            chunk("tEXt", b"Comment\0before".to_vec()),
+            chunk("tEXt", b"Comment\0after".to_vec()),
+            chunk("IEND", Vec::new()),
+        ]);
+
+        let filtered = (0..16 * 17)
+            .map(|i| if i % 17 == 0 { 0 } else { i as u8 })
+            .collect();
+
+        (png, filtered)
+    }
+
+    fn inflate_error(png: &PNG) -> ImageDataError {
+        png.inflate_image_data().unwrap_err().downcast().unwrap()
+    }
+
+    #[test]
+    fn test_deflate_and_inflate() {
+        let (mut png, filtered) = empty_png();
+        let options = DeflateOptions {
+            level: 9,
+            chunk_size: 16,
+        };
+
+        png.deflate_image_data(&filtered, &options).unwrap();
+        assert_eq!(png.inflate_image_data().unwrap(), filtered);
+
+        let idats: Vec<_> = png.chunks_by_type("IDAT").collect();
+        assert!(idats.len() > 1);
+        assert!(idats.iter().all(|c| c.length() <= 16));
+
+        // Image data is inserted before IEND, after all other chunks.
+        let types: Vec<_> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types[..3], ["IHDR", "tEXt", "tEXt"]);
+        assert_eq!(types.last().unwrap(), "IEND");
+    }
+
+    #[test]
+    fn test_deflate_replaces_image_data_in_place() {
+        let (mut png, filtered) = empty_png();
+        png.deflate_image_data(&filtered, &DeflateOptions::default())
+            .unwrap();
+        // Move the trailing comment after the image data.
+        let comment = png.remove_chunk_at(2).unwrap();
+        png.insert_chunk(3, comment);
+
+        png.deflate_image_data(
+            &filtered,
+            &DeflateOptions {
+                level: 0,
+                chunk_size: 100,
+            },
+        )
+        .unwrap();
+
+        let types: Vec<_> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        let idats = types.iter().filter(|t| *t == "IDAT").count();
+        assert!(idats >= 3);
+        assert_eq!(types[2..2 + idats], vec!["IDAT"; idats]);
+        assert_eq!(types[2 + idats..], ["tEXt", "IEND"]);
+        assert_eq!(png.inflate_image_data().unwrap(), filtered);
+    }
+
+    #[test]
+    fn test_deflate_invalid_options() {
+        let (mut png, filtered) = empty_png();
+
+        let level = DeflateOptions {
+            level: 10,
+            ..Default::default()
+        };
+        assert!(png.deflate_image_data(&filtered, &level).is_err());
+
+        let chunk_size = DeflateOptions {
+            chunk_size: 0,
+            ..Default::default()
+        };
+        assert!(png.deflate_image_data(&filtered, &chunk_size).is_err());
+
+        assert!(
+            png.deflate_image_data(&filtered[1..], &DeflateOptions::default())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_inflate_missing_image_data() {
+        let (png, _) = empty_png();
+
+        assert_eq!(inflate_error(&png), ImageDataError::Missing);
+    }
+
+    #[test]
+    fn test_inflate_corrupt_zlib_header() {
+        let (mut png, filtered) = empty_png();
+        png.deflate_image_data(&filtered, &DeflateOptions::default())
+            .unwrap();
+
+        for (header, reason) in [
+            ([0x78, 0x9D], "header check bits are invalid"),
+            ([0x79, 0x9C], "compression method is not deflate"),
+            ([0x88, 0x98], "window size exceeds 32 KiB"),
+            ([0x78, 0xBB], "preset dictionaries are not allowed"),
+        ] {
+            let mut compressed = png.compressed_image_data();
+            compressed[..2].copy_from_slice(&header);
+            let png = with_image_data(&png, compressed);
+
+            assert_eq!(
+                inflate_error(&png),
+                ImageDataError::InvalidZlibHeader(reason)
+            );
+        }
+    }
+
+    #[test]
+    fn test_inflate_checksum_mismatch() {
+        let (mut png, filtered) = empty_png();
+        png.deflate_image_data(&filtered, &DeflateOptions::default())
+            .unwrap();
+
+        let mut compressed = png.compressed_image_data();
+        *compressed.last_mut().unwrap() ^= 1;
+        let png = with_image_data(&png, compressed);
+
+        assert_eq!(inflate_error(&png), ImageDataError::ChecksumMismatch);
+    }
+
+    #[test]
+    fn test_inflate_size_mismatch() {
+        let (png, filtered) = empty_png();
+
+        let short = miniz_oxide::deflate::compress_to_vec_zlib(&filtered[..100], 6);
+        assert_eq!(
+            inflate_error(&with_image_data(&png, short)),
+            ImageDataError::TooShort {
+                expected: filtered.len(),
+                actual: 100
+            }
+        );
+
+        let long =
+            miniz_oxide::deflate::compress_to_vec_zlib(&[filtered.clone(), vec![0]].concat(), 6);
+        assert_eq!(
+            inflate_error(&with_image_data(&png, long)),
+            ImageDataError::TooLong {
+                expected: filtered.len()
+            }
+        );
+
+        let mut truncated = miniz_oxide::deflate::compress_to_vec_zlib(&filtered, 6);
+        truncated.truncate(truncated.len() / 2);
+        assert!(matches!(
+            inflate_error(&with_image_data(&png, truncated)),
+            ImageDataError::Corrupt(_)
+        ));
+    }
+
+    /// Returns a copy of the PNG with a single `IDAT` chunk holding the
+    /// compressed image data.
+    fn with_image_data(png: &PNG, compressed: Vec<u8>) -> PNG {
+        let mut chunks: Vec<_> = png
+            .chunks()
+            .iter()
+            .filter(|c| c.chunk_type().bytes() != *b"IDAT")
+            .cloned()
+            .collect();
+        chunks.insert(1, chunk("IDAT", compressed));
+
+        PNG::from_chunks(chunks)
+    }
+}
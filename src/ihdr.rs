@@ -0,0 +1,540 @@
+//! The `IHDR` chunk is the first chunk of every PNG datastream, describing the
+//! image it contains. Its 13 bytes of data consist of:
+//!
+//! - Width: four-byte unsigned integer, in pixels.
+//! - Height: four-byte unsigned integer, in pixels.
+//! - Bit depth: one byte, giving the number of bits per sample or per palette
+//!   index (not per pixel).
+//! - Color type: one byte, defining the PNG image type.
+//! - Compression method: one byte, which must be 0 (deflate).
+//! - Filter method: one byte, which must be 0 (adaptive filtering).
+//! - Interlace method: one byte, either 0 (no interlace) or 1 (Adam7).
+//!
+//! Width and height must both be non-zero and must not exceed 2^31-1. Only
+//! certain combinations of bit depth and color type are allowed:
+//!
+//! | Color type               | Value | Allowed bit depths |
+//! |--------------------------|-------|--------------------|
+//! | Greyscale                | 0     | 1, 2, 4, 8, 16     |
+//! | Truecolor                | 2     | 8, 16              |
+//! | Indexed-color            | 3     | 1, 2, 4, 8         |
+//! | Greyscale with alpha     | 4     | 8, 16              |
+//! | Truecolor with alpha     | 6     | 8, 16              |
+
+use std::convert::TryFrom;
+use std::{fmt, result};
+
+use crate::chunk::Chunk;
+
+use anyhow::Result;
+
+/// PNG image type, defining how the samples of each pixel are interpreted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorType {
+    /// Each pixel is a greyscale sample.
+    Grayscale = 0,
+    /// Each pixel is an R,G,B triple.
+    Truecolor = 2,
+    /// Each pixel is a palette index; a `PLTE` chunk shall appear.
+    Indexed = 3,
+    /// Each pixel is a greyscale sample followed by an alpha sample.
+    GrayscaleAlpha = 4,
+    /// Each pixel is an R,G,B triple followed by an alpha sample.
+    TruecolorAlpha = 6,
+}
+
+impl ColorType {
+    /// Returns the bit depths allowed for the color type.
+    pub const fn allowed_bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Truecolor | ColorType::GrayscaleAlpha | ColorType::TruecolorAlpha => {
+                &[8, 16]
+            }
+        }
+    }
+
+    /// Returns the number of samples (channels) per pixel.
+    pub const fn channels(&self) -> usize {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Truecolor => 3,
+            ColorType::TruecolorAlpha => 4,
+        }
+    }
+
+    /// Returns whether a `PLTE` chunk is required, optional, or forbidden for
+    /// the color type.
+    pub const fn palette_requirement(&self) -> PaletteRequirement {
+        match self {
+            ColorType::Indexed => PaletteRequirement::Required,
+            ColorType::Truecolor | ColorType::TruecolorAlpha => PaletteRequirement::Optional,
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => PaletteRequirement::Forbidden,
+        }
+    }
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> result::Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Truecolor),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::TruecolorAlpha),
+            _ => anyhow::bail!("invalid IHDR chunk: unknown color type: {}", value),
+        }
+    }
+}
+
+impl fmt::Display for ColorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "greyscale",
+            ColorType::Truecolor => "truecolor",
+            ColorType::Indexed => "indexed-color",
+            ColorType::GrayscaleAlpha => "greyscale with alpha",
+            ColorType::TruecolorAlpha => "truecolor with alpha",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// Whether a `PLTE` chunk may appear for a given color type.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PaletteRequirement {
+    /// A `PLTE` chunk shall appear.
+    Required,
+    /// A `PLTE` chunk may appear, as a suggested palette.
+    Optional,
+    /// A `PLTE` chunk shall not appear.
+    Forbidden,
+}
+
+/// Method used to order the transmission of the image data.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InterlaceMethod {
+    /// Pixels are transmitted sequentially, scanline by scanline.
+    None = 0,
+    /// Pixels are transmitted in seven passes over the image (Adam7).
+    Adam7 = 1,
+}
+
+impl TryFrom<u8> for InterlaceMethod {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> result::Result<Self, Self::Error> {
+        match value {
+            0 => Ok(InterlaceMethod::None),
+            1 => Ok(InterlaceMethod::Adam7),
+            _ => anyhow::bail!("invalid IHDR chunk: unknown interlace method: {}", value),
+        }
+    }
+}
+
+/// Starting column, starting row, column increment, and row increment of the
+/// pixels in each of the seven Adam7 passes.
+pub(crate) const ADAM7_PASSES: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// Decoded contents of an `IHDR` chunk.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Ihdr {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: ColorType,
+    compression_method: u8,
+    filter_method: u8,
+    interlace_method: InterlaceMethod,
+}
+
+impl Ihdr {
+    /// Length of the `IHDR` chunk data in bytes.
+    pub const LENGTH: usize = 13;
+
+    /// Creates a new image header, using the only defined compression method
+    /// and filter method.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either dimension is zero or exceeds [`i32::MAX`],
+    /// or if the bit depth is not allowed for the color type.
+    pub fn new(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: ColorType,
+        interlace_method: InterlaceMethod,
+    ) -> Result<Self> {
+        anyhow::ensure!(
+            width > 0 && height > 0,
+            "invalid IHDR chunk: image dimensions must be non-zero, but received: {}x{}",
+            width,
+            height
+        );
+        anyhow::ensure!(
+            width <= i32::MAX as u32 && height <= i32::MAX as u32,
+            "invalid IHDR chunk: image dimensions must not exceed i32::MAX, but received: {}x{}",
+            width,
+            height
+        );
+        anyhow::ensure!(
+            color_type.allowed_bit_depths().contains(&bit_depth),
+            "invalid IHDR chunk: bit depth {} is not allowed for color type {} ({}), expected one of {:?}",
+            bit_depth,
+            color_type as u8,
+            color_type,
+            color_type.allowed_bit_depths()
+        );
+
+        Ok(Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method,
+        })
+    }
+
+    /// Returns the width of the image in pixels.
+    #[inline]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the image in pixels.
+    #[inline]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the number of bits per sample or per palette index.
+    #[inline]
+    pub const fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    /// Returns the color type of the image.
+    #[inline]
+    pub const fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    /// Returns the compression method of the image data.
+    #[inline]
+    pub const fn compression_method(&self) -> u8 {
+        self.compression_method
+    }
+
+    /// Returns the filter method of the image data.
+    #[inline]
+    pub const fn filter_method(&self) -> u8 {
+        self.filter_method
+    }
+
+    /// Returns the interlace method of the image data.
+    #[inline]
+    pub const fn interlace_method(&self) -> InterlaceMethod {
+        self.interlace_method
+    }
+
+    /// Returns the number of bits per pixel.
+    #[inline]
+    pub const fn bits_per_pixel(&self) -> usize {
+        self.color_type.channels() * self.bit_depth as usize
+    }
+
+    /// Returns the number of bytes per complete pixel, rounded up to one, used
+    /// to find corresponding bytes when filtering.
+    #[inline]
+    pub const fn bytes_per_pixel(&self) -> usize {
+        self.bits_per_pixel().div_ceil(8)
+    }
+
+    /// Returns the length in bytes of a scanline of the given width in pixels,
+    /// excluding its filter type byte.
+    #[inline]
+    pub const fn scanline_len(&self, width: u32) -> usize {
+        (width as usize * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    /// Returns the width and height in pixels of each pass over the image:
+    /// the full image if it is not interlaced, or the seven Adam7 passes,
+    /// some of which may be empty for small images.
+    pub fn pass_dimensions(&self) -> Vec<(u32, u32)> {
+        match self.interlace_method {
+            InterlaceMethod::None => vec![(self.width, self.height)],
+            InterlaceMethod::Adam7 => ADAM7_PASSES
+                .iter()
+                .map(|&(x0, y0, dx, dy)| {
+                    (
+                        self.width.saturating_sub(x0).div_ceil(dx),
+                        self.height.saturating_sub(y0).div_ceil(dy),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the length in bytes of the decompressed image data: the
+    /// scanlines of every pass, each preceded by its filter type byte.
+    ///
+    /// Returns `None` if the length does not fit in a `usize`.
+    pub fn image_data_len(&self) -> Option<usize> {
+        self.pass_dimensions()
+            .into_iter()
+            .filter(|&(width, height)| width > 0 && height > 0)
+            .try_fold(0usize, |len, (width, height)| {
+                (self.scanline_len(width) + 1)
+                    .checked_mul(height as usize)
+                    .and_then(|pass| len.checked_add(pass))
+            })
+    }
+
+    /// Checks the `PLTE` chunk of the image, if any, against the color type
+    /// and bit depth.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a `PLTE` chunk is required but missing, present but
+    /// forbidden, or has a length that is not a multiple of 3, holds no
+    /// entries, or holds more entries than the bit depth can index.
+    pub fn check_palette(&self, plte: Option<&Chunk>) -> Result<()> {
+        let plte = match (self.color_type.palette_requirement(), plte) {
+            (PaletteRequirement::Required, None) => anyhow::bail!(
+                "invalid PNG datastream: missing PLTE chunk required for color type {} ({})",
+                self.color_type as u8,
+                self.color_type
+            ),
+            (PaletteRequirement::Forbidden, Some(_)) => anyhow::bail!(
+                "invalid PNG datastream: PLTE chunk must not appear for color type {} ({})",
+                self.color_type as u8,
+                self.color_type
+            ),
+            (_, None) => return Ok(()),
+            (_, Some(plte)) => plte,
+        };
+
+        let len = plte.data().len();
+        anyhow::ensure!(
+            len % 3 == 0,
+            "invalid PLTE chunk: length must be a multiple of 3, but received: {}",
+            len
+        );
+
+        // Indexed-color images can only reference 2^bit_depth entries, while
+        // suggested palettes for truecolor images can hold up to 256.
+        let max_entries = match self.color_type {
+            ColorType::Indexed => 1 << self.bit_depth,
+            _ => 256,
+        };
+        let entries = len / 3;
+
+        anyhow::ensure!(
+            (1..=max_entries).contains(&entries),
+            "invalid PLTE chunk: must hold between 1 and {} entries for bit depth {}, but received: {}",
+            max_entries,
+            self.bit_depth,
+            entries
+        );
+
+        Ok(())
+    }
+
+    /// Returns the memory representation of the image header as a byte array
+    /// in big-endian (network) byte order.
+    pub fn as_bytes(&self) -> [u8; Self::LENGTH] {
+        let mut bytes = [0u8; Self::LENGTH];
+
+        bytes[0..4].copy_from_slice(&self.width.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.height.to_be_bytes());
+        bytes[8] = self.bit_depth;
+        bytes[9] = self.color_type as u8;
+        bytes[10] = self.compression_method;
+        bytes[11] = self.filter_method;
+        bytes[12] = self.interlace_method as u8;
+
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for Ihdr {
+    type Error = anyhow::Error;
+
+    fn try_from(bytes: &[u8]) -> result::Result<Self, Self::Error> {
+        anyhow::ensure!(
+            bytes.len() == Self::LENGTH,
+            "invalid IHDR chunk: data must be exactly {} bytes, but received: {}",
+            Self::LENGTH,
+            bytes.len()
+        );
+
+        let width = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let height = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let color_type = ColorType::try_from(bytes[9])?;
+        let interlace_method = InterlaceMethod::try_from(bytes[12])?;
+
+        anyhow::ensure!(
+            bytes[10] == 0,
+            "invalid IHDR chunk: unknown compression method: {}",
+            bytes[10]
+        );
+        anyhow::ensure!(
+            bytes[11] == 0,
+            "invalid IHDR chunk: unknown filter method: {}",
+            bytes[11]
+        );
+
+        Ihdr::new(width, height, bytes[8], color_type, interlace_method)
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = anyhow::Error;
+
+    fn try_from(chunk: &Chunk) -> result::Result<Self, Self::Error> {
+        anyhow::ensure!(
+            chunk.chunk_type().bytes() == *b"IHDR",
+            "invalid IHDR chunk: unexpected chunk type '{}'",
+            chunk.chunk_type()
+        );
+
+        Ihdr::try_from(chunk.data())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk_type::ChunkType;
+
+    fn ihdr_bytes(width: u32, height: u32, bit_depth: u8, color_type: u8) -> Vec<u8> {
+        width
+            .to_be_bytes()
+            .iter()
+            .chain(height.to_be_bytes().iter())
+            .chain([bit_depth, color_type, 0, 0, 0].iter())
+            .copied()
+            .collect()
+    }
+
+    fn plte_chunk(entries: usize) -> Chunk {
+        Chunk::new(ChunkType::from_str("PLTE").unwrap(), vec![0; entries * 3]).unwrap()
+    }
+
+    #[test]
+    fn test_ihdr_from_bytes() {
+        let ihdr = Ihdr::try_from(&ihdr_bytes(50, 40, 8, 6)[..]).unwrap();
+
+        assert_eq!(ihdr.width(), 50);
+        assert_eq!(ihdr.height(), 40);
+        assert_eq!(ihdr.bit_depth(), 8);
+        assert_eq!(ihdr.color_type(), ColorType::TruecolorAlpha);
+        assert_eq!(ihdr.interlace_method(), InterlaceMethod::None);
+        assert_eq!(ihdr.bits_per_pixel(), 32);
+    }
+
+    #[test]
+    fn test_ihdr_as_bytes() {
+        let bytes = ihdr_bytes(1, 1, 4, 3);
+        let ihdr = Ihdr::try_from(&bytes[..]).unwrap();
+
+        assert_eq!(ihdr.as_bytes().to_vec(), bytes);
+    }
+
+    #[test]
+    fn test_ihdr_invalid_length() {
+        let bytes = ihdr_bytes(1, 1, 8, 2);
+        assert!(Ihdr::try_from(&bytes[..12]).is_err());
+    }
+
+    #[test]
+    fn test_ihdr_zero_dimensions() {
+        assert!(Ihdr::try_from(&ihdr_bytes(0, 1, 8, 2)[..]).is_err());
+        assert!(Ihdr::try_from(&ihdr_bytes(1, 0, 8, 2)[..]).is_err());
+    }
+
+    #[test]
+    fn test_ihdr_illegal_combinations() {
+        // Truecolor images require 8 or 16 bits per sample.
+        assert!(Ihdr::try_from(&ihdr_bytes(1, 1, 4, 2)[..]).is_err());
+        // Indexed-color images can not exceed 8 bits per index.
+        assert!(Ihdr::try_from(&ihdr_bytes(1, 1, 16, 3)[..]).is_err());
+        // Color type 1 is undefined.
+        assert!(Ihdr::try_from(&ihdr_bytes(1, 1, 8, 1)[..]).is_err());
+    }
+
+    #[test]
+    fn test_ihdr_invalid_methods() {
+        let mut bytes = ihdr_bytes(1, 1, 8, 2);
+        bytes[12] = 2;
+        assert!(Ihdr::try_from(&bytes[..]).is_err());
+
+        bytes[12] = 0;
+        bytes[10] = 1;
+        assert!(Ihdr::try_from(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn test_ihdr_palette_requirements() {
+        let indexed = Ihdr::try_from(&ihdr_bytes(1, 1, 2, 3)[..]).unwrap();
+        assert!(indexed.check_palette(None).is_err());
+        assert!(indexed.check_palette(Some(&plte_chunk(4))).is_ok());
+        // A bit depth of 2 can only index 4 entries.
+        assert!(indexed.check_palette(Some(&plte_chunk(5))).is_err());
+
+        let grayscale = Ihdr::try_from(&ihdr_bytes(1, 1, 8, 0)[..]).unwrap();
+        assert!(grayscale.check_palette(None).is_ok());
+        assert!(grayscale.check_palette(Some(&plte_chunk(1))).is_err());
+
+        let truecolor = Ihdr::try_from(&ihdr_bytes(1, 1, 8, 2)[..]).unwrap();
+        assert!(truecolor.check_palette(None).is_ok());
+        assert!(truecolor.check_palette(Some(&plte_chunk(256))).is_ok());
+        assert!(truecolor.check_palette(Some(&plte_chunk(0))).is_err());
+    }
+
+    #[test]
+    fn test_ihdr_palette_invalid_length() {
+        let indexed = Ihdr::try_from(&ihdr_bytes(1, 1, 8, 3)[..]).unwrap();
+        let plte = Chunk::new(ChunkType::from_str("PLTE").unwrap(), vec![0; 4]).unwrap();
+
+        assert!(indexed.check_palette(Some(&plte)).is_err());
+    }
+
+    #[test]
+    fn test_ihdr_image_data_len() {
+        // 10 pixels at 2 bits per pixel fill 3 bytes, plus the filter byte.
+        let ihdr = Ihdr::new(10, 4, 2, ColorType::Grayscale, InterlaceMethod::None).unwrap();
+        assert_eq!(ihdr.scanline_len(10), 3);
+        assert_eq!(ihdr.image_data_len(), Some(16));
+
+        let ihdr = Ihdr::new(8, 8, 8, ColorType::Truecolor, InterlaceMethod::Adam7).unwrap();
+        assert_eq!(
+            ihdr.pass_dimensions(),
+            [(1, 1), (1, 1), (2, 1), (2, 2), (4, 2), (4, 4), (8, 4)]
+        );
+        // Passes hold 64 pixels of 3 bytes, plus 15 filter bytes.
+        assert_eq!(ihdr.image_data_len(), Some(64 * 3 + 15));
+
+        // Passes starting beyond the edge of a small image are empty.
+        let ihdr = Ihdr::new(1, 1, 8, ColorType::Grayscale, InterlaceMethod::Adam7).unwrap();
+        assert_eq!(ihdr.image_data_len(), Some(2));
+    }
+}
@@ -0,0 +1,408 @@
+//! Inspection of the chunks of a PNG datastream.
+//!
+//! [`inspect`] reads a datastream one chunk at a time, recording where each
+//! chunk starts, its chunk type, length, and CRC, and a short summary of its
+//! contents for the chunk types this crate knows how to decode: image header,
+//! palette, transparency, color space, physical dimensions, modification time,
+//! text, animation, signatures, encrypted messages, and message fragments.
+
+use std::io::Read;
+
+use anyhow::Result;
+
+use crate::apng::{AnimationControl, FrameControl};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::crypto;
+use crate::fragment::{self, Fragment};
+use crate::ihdr::{ColorType, Ihdr, InterlaceMethod};
+use crate::reader::ChunkReader;
+use crate::signature::{SIGNATURE_CHUNK_TYPE, Signature};
+use crate::text::{TextChunk, TextKind};
+
+/// Description of a chunk within a PNG datastream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkInfo {
+    index: usize,
+    offset: u64,
+    chunk_type: ChunkType,
+    length: u32,
+    crc: u32,
+    summary: Option<String>,
+    error: Option<String>,
+}
+
+impl ChunkInfo {
+    /// Returns the position of the chunk in the datastream, starting from 0.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the byte offset of the start of the chunk in the datastream,
+    /// including the PNG signature.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the chunk type of the chunk.
+    pub fn chunk_type(&self) -> ChunkType {
+        self.chunk_type
+    }
+
+    /// Returns the length of the chunk data in bytes.
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    /// Returns the CRC of the chunk.
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// Returns a short summary of the decoded contents of the chunk, or
+    /// `None` if its chunk type is not known or has nothing to summarize.
+    pub fn summary(&self) -> Option<&str> {
+        self.summary.as_deref()
+    }
+
+    /// Returns the reason the contents of the chunk could not be decoded, or
+    /// `None` if they could.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+/// Reads the PNG datastream from the reader, describing each of its chunks in
+/// order.
+///
+/// # Errors
+///
+/// Returns an error if the datastream could not be read, if it does not start
+/// with the PNG signature, or if a chunk is malformed or fails its CRC check.
+/// Chunks whose contents can not be decoded are reported through
+/// [`ChunkInfo::error`] instead.
+pub fn inspect<R: Read>(reader: R) -> Result<Vec<ChunkInfo>> {
+    let mut reader = ChunkReader::new(reader)?;
+    let mut chunks = Vec::new();
+    let mut ihdr = None;
+
+    while let Some(chunk) = reader.next_chunk()? {
+        if chunks.is_empty() {
+            ihdr = Ihdr::try_from(&chunk).ok();
+        }
+
+        let (summary, error) = match summarize(&chunk, ihdr.as_ref()) {
+            Ok(summary) => (summary, None),
+            Err(e) => (None, Some(format!("{e:#}"))),
+        };
+
+        chunks.push(ChunkInfo {
+            index: chunks.len(),
+            offset: reader.chunk_offset(),
+            chunk_type: chunk.chunk_type(),
+            length: chunk.length(),
+            crc: chunk.crc(),
+            summary,
+            error,
+        });
+    }
+
+    Ok(chunks)
+}
+
+/// Returns a short summary of the contents of the chunk, given the image
+/// header of its datastream if known, or `None` if there is nothing to
+/// summarize.
+///
+/// # Errors
+///
+/// Returns an error if the contents of a known chunk type are invalid.
+pub fn summarize(chunk: &Chunk, ihdr: Option<&Ihdr>) -> Result<Option<String>> {
+    let data = chunk.data();
+
+    let summary = match &chunk.chunk_type().bytes() {
+        b"IHDR" => {
+            let ihdr = Ihdr::try_from(chunk)?;
+            let interlace = match ihdr.interlace_method() {
+                InterlaceMethod::None => "non-interlaced",
+                InterlaceMethod::Adam7 => "Adam7 interlaced",
+            };
+
+            format!(
+                "{}x{}, {}-bit {}, {}",
+                ihdr.width(),
+                ihdr.height(),
+                ihdr.bit_depth(),
+                ihdr.color_type(),
+                interlace
+            )
+        }
+        b"PLTE" => format!("{} entries", data.len() / 3),
+        b"tRNS" => match ihdr.map(Ihdr::color_type) {
+            Some(ColorType::Indexed) => format!("{} palette alpha values", data.len()),
+            Some(ColorType::Grayscale | ColorType::Truecolor) => {
+                let samples: Vec<_> = data
+                    .chunks_exact(2)
+                    .map(|s| u16::from_be_bytes([s[0], s[1]]).to_string())
+                    .collect();
+
+                format!("transparent color {}", samples.join(","))
+            }
+            _ => return Ok(None),
+        },
+        b"gAMA" => {
+            let gamma = read_u32(chunk, 0, 4)?;
+            format!("gamma {:.5}", gamma as f64 / 100_000.0)
+        }
+        b"sRGB" => {
+            expect_len(chunk, 1)?;
+            let intent = match data[0] {
+                0 => "perceptual",
+                1 => "relative colorimetric",
+                2 => "saturation",
+                3 => "absolute colorimetric",
+                intent => anyhow::bail!("invalid sRGB chunk: unknown rendering intent {intent}"),
+            };
+
+            format!("{intent} rendering intent")
+        }
+        b"pHYs" => {
+            expect_len(chunk, 9)?;
+            let unit = match data[8] {
+                0 => "unit (aspect ratio)",
+                1 => "meter",
+                unit => anyhow::bail!("invalid pHYs chunk: unknown unit {unit}"),
+            };
+
+            format!(
+                "{}x{} pixels per {}",
+                read_u32(chunk, 0, 9)?,
+                read_u32(chunk, 4, 9)?,
+                unit
+            )
+        }
+        b"tIME" => {
+            expect_len(chunk, 7)?;
+            let year = u16::from_be_bytes([data[0], data[1]]);
+
+            format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+                year, data[2], data[3], data[4], data[5], data[6]
+            )
+        }
+        b"tEXt" | b"zTXt" | b"iTXt" => {
+            let text = TextChunk::try_from(chunk)?;
+
+            match text.kind() {
+                TextKind::International { language_tag, .. } if !language_tag.is_empty() => {
+                    format!("keyword \"{}\" ({})", text.keyword(), language_tag)
+                }
+                _ => format!("keyword \"{}\"", text.keyword()),
+            }
+        }
+        b"acTL" => {
+            let control = AnimationControl::try_from(chunk)?;
+            let plays = match control.num_plays {
+                0 => "loops forever".to_owned(),
+                1 => "plays once".to_owned(),
+                n => format!("plays {n} times"),
+            };
+
+            format!("{} frames, {}", control.num_frames, plays)
+        }
+        b"fcTL" => {
+            let control = FrameControl::try_from(chunk)?;
+
+            format!(
+                "sequence {}, {}x{} at {},{}, delay {}/{} s, dispose {}, blend {}",
+                control.sequence_number,
+                control.width,
+                control.height,
+                control.x_offset,
+                control.y_offset,
+                control.delay_num,
+                control.delay_den,
+                control.dispose_op,
+                control.blend_op
+            )
+        }
+        b"fdAT" => {
+            anyhow::ensure!(
+                data.len() >= 4,
+                "invalid fdAT chunk: expected at least 4 bytes, but received {}",
+                data.len()
+            );
+
+            format!("sequence {}", u32::from_be_bytes(data[..4].try_into()?))
+        }
+        bytes if bytes == SIGNATURE_CHUNK_TYPE.as_bytes() => {
+            let signature = Signature::try_from(chunk)?;
+
+            format!(
+                "signature of {} by {}{}",
+                signature.chunk_type(),
+                signature.signer(),
+                if signature.covers_image() {
+                    ", covers image data"
+                } else {
+                    ""
+                }
+            )
+        }
+        // Only private chunks can hold messages.
+        _ if !chunk.chunk_type().is_public() => {
+            if fragment::is_fragment(data) {
+                let fragment = Fragment::try_from(data)?;
+
+                format!(
+                    "message fragment {} of {} (indexed from 0)",
+                    fragment.index(),
+                    fragment.total()
+                )
+            } else if crypto::is_encrypted(data) {
+                format!("encrypted message ({})", crypto::scheme(data)?)
+            } else {
+                return Ok(None);
+            }
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(summary))
+}
+
+/// Checks that the chunk data has the expected length.
+fn expect_len(chunk: &Chunk, len: usize) -> Result<()> {
+    anyhow::ensure!(
+        chunk.data().len() == len,
+        "invalid {} chunk: expected {} bytes, but received {}",
+        chunk.chunk_type(),
+        len,
+        chunk.data().len()
+    );
+
+    Ok(())
+}
+
+/// Reads a four-byte unsigned integer at the offset of the chunk data, after
+/// checking that the data has the expected length.
+fn read_u32(chunk: &Chunk, offset: usize, len: usize) -> Result<u32> {
+    expect_len(chunk, len)?;
+
+    Ok(u32::from_be_bytes(
+        chunk.data()[offset..offset + 4].try_into().unwrap(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::builder::PngBuilder;
+    use crate::commands;
+    use crate::png::PNG;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_inspect() {
+        let mut png = PngBuilder::new(2, 3, ColorType::Truecolor, 8, vec![0; 18])
+            .build()
+            .unwrap();
+        png.insert_chunk(1, chunk("tIME", &[7, 233, 1, 2, 3, 4, 5]));
+        png.append_chunk(chunk("tEXt", b"Comment\0hello"));
+        commands::encode(&mut png, "ruSt", "message").unwrap();
+
+        let bytes = png.as_bytes();
+        let chunks = inspect(bytes.as_slice()).unwrap();
+
+        let types: Vec<_> = chunks.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "tIME", "IDAT", "tEXt", "ruSt", "IEND"]);
+
+        let mut offset = PNG::MAGIC.len() as u64;
+        for (i, (info, chunk)) in chunks.iter().zip(png.chunks()).enumerate() {
+            assert_eq!(info.index(), i);
+            assert_eq!(info.offset(), offset);
+            assert_eq!(info.length(), chunk.length());
+            assert_eq!(info.crc(), chunk.crc());
+            assert_eq!(info.error(), None);
+            offset += chunk.size() as u64;
+        }
+
+        let summaries: Vec<_> = chunks.iter().map(ChunkInfo::summary).collect();
+        assert_eq!(
+            summaries,
+            [
+                Some("2x3, 8-bit truecolor, non-interlaced"),
+                Some("2025-01-02 03:04:05 UTC"),
+                None,
+                Some("keyword \"Comment\""),
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inspect_invalid_contents() {
+        let mut png = PngBuilder::new(1, 1, ColorType::Grayscale, 8, vec![0])
+            .build()
+            .unwrap();
+        png.insert_chunk(1, chunk("tIME", &[7, 233]));
+
+        let bytes = png.as_bytes();
+        let chunks = inspect(bytes.as_slice()).unwrap();
+
+        assert_eq!(chunks[1].summary(), None);
+        assert!(chunks[1].error().unwrap().contains("tIME"));
+
+        assert!(inspect(&bytes[..bytes.len() - 1]).is_err());
+        assert!(inspect(&bytes[1..]).is_err());
+    }
+
+    #[test]
+    fn test_summarize() {
+        let ihdr = Ihdr::new(1, 1, 8, ColorType::Indexed, InterlaceMethod::Adam7).unwrap();
+        let summary = |chunk: Chunk| summarize(&chunk, Some(&ihdr)).unwrap().unwrap();
+
+        assert_eq!(
+            summary(chunk("IHDR", &ihdr.as_bytes())),
+            "1x1, 8-bit indexed-color, Adam7 interlaced"
+        );
+        assert_eq!(summary(chunk("PLTE", &[0; 9])), "3 entries");
+        assert_eq!(summary(chunk("tRNS", &[0; 2])), "2 palette alpha values");
+        assert_eq!(summary(chunk("gAMA", &[0, 0, 177, 143])), "gamma 0.45455");
+        assert_eq!(summary(chunk("sRGB", &[0])), "perceptual rendering intent");
+        assert_eq!(
+            summary(chunk("pHYs", &[0, 0, 11, 19, 0, 0, 11, 19, 1])),
+            "2835x2835 pixels per meter"
+        );
+        assert_eq!(
+            summary(chunk("acTL", &[0, 0, 0, 2, 0, 0, 0, 0])),
+            "2 frames, loops forever"
+        );
+
+        let truecolor = Ihdr::new(1, 1, 8, ColorType::Truecolor, InterlaceMethod::None).unwrap();
+        let trns = chunk("tRNS", &[0, 1, 0, 2, 0, 3]);
+        assert_eq!(
+            summarize(&trns, Some(&truecolor)).unwrap().as_deref(),
+            Some("transparent color 1,2,3")
+        );
+
+        let mut png = PngBuilder::new(1, 1, ColorType::Grayscale, 8, vec![0])
+            .build()
+            .unwrap();
+        commands::encode_fragmented(&mut png, "ruSt", "fragmented", 4).unwrap();
+        let fragment = png.chunk_by_type("ruSt").unwrap();
+        assert_eq!(
+            summarize(fragment, None).unwrap().as_deref(),
+            Some("message fragment 0 of 3 (indexed from 0)")
+        );
+
+        assert!(summarize(&chunk("sRGB", &[4]), None).is_err());
+        assert_eq!(summarize(&chunk("IDAT", &[1, 2, 3]), None).unwrap(), None);
+    }
+}
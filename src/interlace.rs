@@ -0,0 +1,488 @@
+//! Adam7 interlacing of the image data.
+//!
+//! An interlaced image is transmitted in seven passes, each a reduced image
+//! holding every 8th, 4th, or 2nd pixel of certain rows and columns, so that
+//! a coarse version of the image can be displayed early. The pixels of pass
+//! `p` start at column and row `(x0, y0)` and advance by `(dx, dy)`:
+//!
+//! | Pass | x0 | y0 | dx | dy |
+//! |------|----|----|----|----|
+//! | 1    | 0  | 0  | 8  | 8  |
+//! | 2    | 4  | 0  | 8  | 8  |
+//! | 3    | 0  | 4  | 4  | 8  |
+//! | 4    | 2  | 0  | 4  | 4  |
+//! | 5    | 0  | 2  | 2  | 4  |
+//! | 6    | 1  | 0  | 2  | 2  |
+//! | 7    | 0  | 1  | 1  | 2  |
+//!
+//! Passes are laid out one after the other, as returned by
+//! [`filter::unfilter_image`], with scanlines of their own width. Empty passes
+//! of small images take no space at all. A raster holds the scanlines of the
+//! full image instead, as a non-interlaced image does.
+
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::filter::{self, FilterStrategy};
+use crate::idat::DeflateOptions;
+use crate::ihdr::{ADAM7_PASSES, Ihdr, InterlaceMethod};
+use crate::png::PNG;
+
+/// Number of Adam7 passes.
+pub const PASSES: usize = ADAM7_PASSES.len();
+
+/// Returns the scanlines of a single Adam7 pass over the raster, numbered from
+/// 0, which are empty if the image is too small to have pixels in that pass.
+///
+/// # Errors
+///
+/// Returns an error if the pass does not exist, or if the length of the
+/// raster does not match the image header.
+pub fn extract_pass(ihdr: &Ihdr, raster: &[u8], pass: usize) -> Result<Vec<u8>> {
+    anyhow::ensure!(
+        pass < PASSES,
+        "invalid pass {pass}: must be 0-{}",
+        PASSES - 1
+    );
+    check_raster_len(ihdr, raster)?;
+
+    Ok(copy_pass(ihdr, raster, pass))
+}
+
+/// Splits the raster into the seven Adam7 passes, laid out one after the
+/// other.
+///
+/// # Errors
+///
+/// Returns an error if the length of the raster does not match the image
+/// header.
+pub fn interlace(ihdr: &Ihdr, raster: &[u8]) -> Result<Vec<u8>> {
+    check_raster_len(ihdr, raster)?;
+
+    let mut passes = Vec::with_capacity(raster.len());
+    for pass in 0..PASSES {
+        passes.extend(copy_pass(ihdr, raster, pass));
+    }
+
+    Ok(passes)
+}
+
+/// Reconstructs the raster from the seven Adam7 passes, laid out one after
+/// the other.
+///
+/// # Errors
+///
+/// Returns an error if the length of the passes does not match the image
+/// header.
+pub fn deinterlace(ihdr: &Ihdr, passes: &[u8]) -> Result<Vec<u8>> {
+    let adam7 = adam7_header(ihdr)?;
+    let expected: usize = adam7
+        .pass_dimensions()
+        .iter()
+        .map(|&(width, height)| ihdr.scanline_len(width) * height as usize)
+        .sum();
+    anyhow::ensure!(
+        passes.len() == expected,
+        "invalid interlaced scanlines: {} bytes given, but the IHDR chunk requires {}",
+        passes.len(),
+        expected
+    );
+
+    let bits = ihdr.bits_per_pixel();
+    let row_len = ihdr.scanline_len(ihdr.width());
+    let mut raster = vec![0; row_len * ihdr.height() as usize];
+    let mut rest = passes;
+
+    for (&(x0, y0, dx, dy), (width, height)) in ADAM7_PASSES.iter().zip(adam7.pass_dimensions()) {
+        let pass_len = ihdr.scanline_len(width);
+        let (pass, tail) = rest.split_at(pass_len * height as usize);
+
+        for (j, src) in pass
+            .chunks_exact(pass_len.max(1))
+            .take(height as usize)
+            .enumerate()
+        {
+            let y = (y0 + j as u32 * dy) as usize;
+            let dst = &mut raster[y * row_len..(y + 1) * row_len];
+
+            for i in 0..width as usize {
+                let x = (x0 + i as u32 * dx) as usize;
+                copy_pixel(src, i * bits, dst, x * bits, bits);
+            }
+        }
+
+        rest = tail;
+    }
+
+    Ok(raster)
+}
+
+/// Copies the pixels of a pass out of a raster of the correct length.
+fn copy_pass(ihdr: &Ihdr, raster: &[u8], pass: usize) -> Vec<u8> {
+    let (x0, y0, dx, dy) = ADAM7_PASSES[pass];
+    let width = ihdr.width().saturating_sub(x0).div_ceil(dx);
+    let height = ihdr.height().saturating_sub(y0).div_ceil(dy);
+
+    let bits = ihdr.bits_per_pixel();
+    let row_len = ihdr.scanline_len(ihdr.width());
+    let pass_len = ihdr.scanline_len(width);
+
+    let mut scanlines = vec![0; pass_len * height as usize];
+
+    for (j, dst) in scanlines
+        .chunks_exact_mut(pass_len.max(1))
+        .take(height as usize)
+        .enumerate()
+    {
+        let y = (y0 + j as u32 * dy) as usize;
+        let src = &raster[y * row_len..(y + 1) * row_len];
+
+        for i in 0..width as usize {
+            let x = (x0 + i as u32 * dx) as usize;
+            copy_pixel(src, x * bits, dst, i * bits, bits);
+        }
+    }
+
+    scanlines
+}
+
+/// Copies a pixel of `bits` bits at bit offset `from` of `src` to bit offset
+/// `to` of `dst`. Pixels of less than 8 bits never straddle a byte boundary.
+fn copy_pixel(src: &[u8], from: usize, dst: &mut [u8], to: usize, bits: usize) {
+    if bits.is_multiple_of(8) {
+        let len = bits / 8;
+        dst[to / 8..to / 8 + len].copy_from_slice(&src[from / 8..from / 8 + len]);
+        return;
+    }
+
+    let mask = ((1u16 << bits) - 1) as u8;
+    let value = (src[from / 8] >> (8 - bits - from % 8)) & mask;
+    let shift = 8 - bits - to % 8;
+
+    dst[to / 8] = (dst[to / 8] & !(mask << shift)) | (value << shift);
+}
+
+/// Checks that the length of a raster matches the image header.
+fn check_raster_len(ihdr: &Ihdr, raster: &[u8]) -> Result<()> {
+    let expected = ihdr
+        .scanline_len(ihdr.width())
+        .checked_mul(ihdr.height() as usize)
+        .context("invalid IHDR chunk: image is too large")?;
+
+    anyhow::ensure!(
+        raster.len() == expected,
+        "invalid scanlines: {} bytes given, but the IHDR chunk requires {}",
+        raster.len(),
+        expected
+    );
+
+    Ok(())
+}
+
+/// Returns the image header with Adam7 interlacing.
+fn adam7_header(ihdr: &Ihdr) -> Result<Ihdr> {
+    Ihdr::new(
+        ihdr.width(),
+        ihdr.height(),
+        ihdr.bit_depth(),
+        ihdr.color_type(),
+        InterlaceMethod::Adam7,
+    )
+}
+
+impl PNG {
+    /// Returns the unfiltered scanlines of the full image, without their
+    /// filter type bytes, de-interlacing the image data if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `IHDR` chunk is invalid, or if the image data
+    /// could not be decompressed or unfiltered.
+    pub fn scanlines(&self) -> Result<Vec<u8>> {
+        let ihdr = self.ihdr()?;
+        let raw = filter::unfilter_image(&ihdr, &self.inflate_image_data()?)?;
+
+        match ihdr.interlace_method() {
+            InterlaceMethod::None => Ok(raw),
+            InterlaceMethod::Adam7 => deinterlace(&ihdr, &raw),
+        }
+    }
+
+    /// Replaces the image data of the PNG with the scanlines of the full image,
+    /// interlacing them if the `IHDR` chunk says so, then filtering and
+    /// compressing them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `IHDR` chunk is invalid, if the length of the
+    /// scanlines does not match it, or if the options are invalid.
+    pub fn set_scanlines(
+        &mut self,
+        raster: &[u8],
+        strategy: FilterStrategy,
+        options: &DeflateOptions,
+    ) -> Result<()> {
+        let ihdr = self.ihdr()?;
+        check_raster_len(&ihdr, raster)?;
+
+        let filtered = match ihdr.interlace_method() {
+            InterlaceMethod::None => filter::filter_image(&ihdr, raster, strategy)?,
+            InterlaceMethod::Adam7 => {
+                filter::filter_image(&ihdr, &interlace(&ihdr, raster)?, strategy)?
+            }
+        };
+
+        self.deflate_image_data(&filtered, options)
+    }
+
+    /// Converts the image data of the PNG to the interlace method, updating
+    /// the `IHDR` chunk.
+    ///
+    /// All other chunks are kept as they are, in the same order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `IHDR` chunk is invalid, if the PNG is animated,
+    /// as its `fdAT` chunks would keep the original interlace method, or if
+    /// the image data could not be decoded or encoded.
+    pub fn set_interlace_method(
+        &mut self,
+        interlace_method: InterlaceMethod,
+        strategy: FilterStrategy,
+        options: &DeflateOptions,
+    ) -> Result<()> {
+        let ihdr = self.ihdr()?;
+        if ihdr.interlace_method() == interlace_method {
+            return Ok(());
+        }
+
+        anyhow::ensure!(
+            self.chunk_by_type("acTL").is_none(),
+            "can not change the interlace method of an animated PNG"
+        );
+
+        let raster = self.scanlines()?;
+        let converted = Ihdr::new(
+            ihdr.width(),
+            ihdr.height(),
+            ihdr.bit_depth(),
+            ihdr.color_type(),
+            interlace_method,
+        )?;
+
+        let original = self.replace_ihdr(converted)?;
+
+        // The original IHDR chunk is restored if the image data could not be
+        // encoded, leaving the PNG unchanged.
+        if let Err(e) = self.set_scanlines(&raster, strategy, options) {
+            self.replace_ihdr(Ihdr::try_from(&original)?)?;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the `IHDR` chunk of the PNG, returning the original one.
+    fn replace_ihdr(&mut self, ihdr: Ihdr) -> Result<Chunk> {
+        let chunk = Chunk::new(ChunkType::from_str("IHDR")?, ihdr.as_bytes().to_vec())?;
+        let original = self
+            .remove_chunk_at(0)
+            .context("invalid PNG datastream: missing IHDR chunk")?;
+
+        self.insert_chunk(0, chunk);
+
+        Ok(original)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apng::{self, FrameOptions};
+    use crate::builder::PngBuilder;
+    use crate::ihdr::ColorType;
+
+    fn ihdr(width: u32, height: u32, bit_depth: u8, color_type: ColorType) -> Ihdr {
+        Ihdr::new(width, height, bit_depth, color_type, InterlaceMethod::None).unwrap()
+    }
+
+    fn raster(ihdr: &Ihdr) -> Vec<u8> {
+        let len = ihdr.scanline_len(ihdr.width()) * ihdr.height() as usize;
+        (0..len).map(|i| (i * 89 % 256) as u8).collect()
+    }
+
+    #[test]
+    fn test_extract_passes() {
+        // Each pixel holds its own index in the 8x8 image.
+        let ihdr = ihdr(8, 8, 8, ColorType::Grayscale);
+        let raster: Vec<u8> = (0..64).collect();
+
+        let pass = |p| extract_pass(&ihdr, &raster, p).unwrap();
+        assert_eq!(pass(0), [0]);
+        assert_eq!(pass(1), [4]);
+        assert_eq!(pass(2), [32, 36]);
+        assert_eq!(pass(3), [2, 6, 34, 38]);
+        assert_eq!(pass(4), [16, 18, 20, 22, 48, 50, 52, 54]);
+        assert_eq!(
+            pass(5),
+            (0..8)
+                .step_by(2)
+                .flat_map(|y| (1..8).step_by(2).map(move |x| y * 8 + x))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            pass(6),
+            (1..8)
+                .step_by(2)
+                .flat_map(|y| (0..8).map(move |x| y * 8 + x))
+                .collect::<Vec<_>>()
+        );
+
+        assert!(extract_pass(&ihdr, &raster, PASSES).is_err());
+        assert!(extract_pass(&ihdr, &raster[1..], 0).is_err());
+    }
+
+    #[test]
+    fn test_extract_empty_pass() {
+        let ihdr = ihdr(3, 1, 8, ColorType::Grayscale);
+
+        assert!(extract_pass(&ihdr, &[1, 2, 3], 1).unwrap().is_empty());
+        assert!(extract_pass(&ihdr, &[1, 2, 3], 6).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_extract_sub_byte_pass() {
+        // 1-bit pixels of a single scanline: 1010 1100 1.
+        let ihdr = ihdr(9, 1, 1, ColorType::Grayscale);
+        let raster = [0b1010_1100, 0b1000_0000];
+
+        // Pass 6 holds columns 1, 3, 5, and 7: 0, 0, 1, 0.
+        assert_eq!(extract_pass(&ihdr, &raster, 5).unwrap(), [0b0010_0000]);
+        // Pass 4 holds columns 2 and 6: 1, 0.
+        assert_eq!(extract_pass(&ihdr, &raster, 3).unwrap(), [0b1000_0000]);
+    }
+
+    #[test]
+    fn test_interlace_roundtrip() {
+        for (bit_depth, color_type) in [
+            (1, ColorType::Grayscale),
+            (2, ColorType::Grayscale),
+            (4, ColorType::Indexed),
+            (8, ColorType::Grayscale),
+            (16, ColorType::Grayscale),
+            (8, ColorType::Truecolor),
+            (16, ColorType::GrayscaleAlpha),
+            (16, ColorType::TruecolorAlpha),
+        ] {
+            for (width, height) in [(1, 1), (3, 2), (8, 8), (13, 11)] {
+                let ihdr = ihdr(width, height, bit_depth, color_type);
+                let mut raster = raster(&ihdr);
+
+                // Padding bits at the end of each scanline are not preserved.
+                let row_len = ihdr.scanline_len(width);
+                let padding = row_len * 8 - width as usize * ihdr.bits_per_pixel();
+                for row in raster.chunks_exact_mut(row_len) {
+                    *row.last_mut().unwrap() &= !((1u16 << padding) - 1) as u8;
+                }
+
+                let passes = interlace(&ihdr, &raster).unwrap();
+                assert_eq!(
+                    deinterlace(&ihdr, &passes).unwrap(),
+                    raster,
+                    "{width}x{height} {bit_depth}-bit {color_type}"
+                );
+                assert!(deinterlace(&ihdr, &passes[1..]).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_png_convert_interlace_method() {
+        let ihdr = ihdr(13, 11, 8, ColorType::Truecolor);
+        let raster = raster(&ihdr);
+
+        let chunk = |ty: &str, data: &[u8]| {
+            Chunk::new(ChunkType::from_str(ty).unwrap(), data.to_vec()).unwrap()
+        };
+        let mut png = PNG::from_chunks(vec![
+            chunk("IHDR", &ihdr.as_bytes()),
+            chunk("gAMA", &[0, 0, 177, 143]),
+            chunk("IEND", &[]),
+        ]);
+        png.set_scanlines(
+            &raster,
+            FilterStrategy::MinSumAbs,
+            &DeflateOptions::default(),
+        )
+        .unwrap();
+        png.append_chunk(chunk("tEXt", b"Comment\0after the image data"));
+
+        let chunk_types = |png: &PNG| -> Vec<String> {
+            let mut types: Vec<_> = png
+                .chunks()
+                .iter()
+                .map(|c| c.chunk_type().to_string())
+                .collect();
+            types.dedup();
+            types
+        };
+        let original_types = chunk_types(&png);
+
+        png.set_interlace_method(
+            InterlaceMethod::Adam7,
+            FilterStrategy::MinSumAbs,
+            &DeflateOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            png.ihdr().unwrap().interlace_method(),
+            InterlaceMethod::Adam7
+        );
+        assert_eq!(png.scanlines().unwrap(), raster);
+        assert_eq!(chunk_types(&png), original_types);
+
+        png.set_interlace_method(
+            InterlaceMethod::None,
+            FilterStrategy::BruteForce,
+            &DeflateOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            png.ihdr().unwrap().interlace_method(),
+            InterlaceMethod::None
+        );
+        assert_eq!(png.scanlines().unwrap(), raster);
+        assert_eq!(chunk_types(&png), original_types);
+    }
+
+    #[test]
+    fn test_animated_png_is_rejected() {
+        let frame = |value: u8| {
+            let pixels = vec![value; 16];
+            let png = PngBuilder::new(4, 4, ColorType::Grayscale, 8, pixels)
+                .build()
+                .unwrap();
+            (png, FrameOptions::default())
+        };
+        let mut png = apng::assemble(&[frame(10), frame(20)], 0).unwrap();
+        let original = png.as_bytes();
+
+        let err = png
+            .set_interlace_method(
+                InterlaceMethod::Adam7,
+                FilterStrategy::MinSumAbs,
+                &DeflateOptions::default(),
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("animated PNG"), "{err}");
+
+        // The PNG is unchanged, and its frames still split.
+        assert_eq!(png.as_bytes(), original);
+        let frames = apng::split(&png).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].0.scanlines().unwrap(), [20; 16]);
+    }
+}
@@ -0,0 +1,71 @@
+//! Library for embedding and retrieving messages in PNG files.
+//!
+//! A [`PNG`] is an ordered sequence of [`Chunk`]s, each identified by a
+//! [`ChunkType`]. Messages are stored in ancillary, private chunks which
+//! decoders are free to ignore, leaving the image itself unchanged. Parsing
+//! them fails with a typed [`Error`] describing what is malformed and where.
+//!
+//! The [`commands`] module provides the encode, decode, and remove operations
+//! used by the `pngme` command-line tool, both for in-memory PNGs and for
+//! PNG files on disk.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::str::FromStr;
+//!
+//! use pngme::{Chunk, ChunkType, PNG, commands};
+//!
+//! let ihdr = Chunk::new(
+//!     ChunkType::from_str("IHDR")?,
+//!     vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0],
+//! )?;
+//! let iend = Chunk::new(ChunkType::from_str("IEND")?, Vec::new())?;
+//! let mut png = PNG::from_chunks(vec![ihdr, iend]);
+//!
+//! commands::encode(&mut png, "ruSt", "hidden message")?;
+//!
+//! assert_eq!(
+//!     commands::decode(&png, "ruSt").as_deref(),
+//!     Some("hidden message")
+//! );
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+#![deny(missing_docs)]
+#![warn(missing_debug_implementations)]
+#![warn(rust_2018_idioms)]
+
+pub mod apng;
+pub mod builder;
+pub mod chunk;
+pub mod chunk_type;
+pub mod commands;
+pub mod crypto;
+pub mod error;
+pub mod filter;
+pub mod fragment;
+pub mod idat;
+pub mod ihdr;
+pub mod inspect;
+pub mod interlace;
+pub mod lsb;
+pub mod ordering;
+pub mod payload;
+pub mod pixels;
+pub mod png;
+pub mod reader;
+pub mod signature;
+pub mod tail;
+pub mod text;
+
+pub use builder::PngBuilder;
+pub use chunk::Chunk;
+pub use chunk_type::ChunkType;
+pub use error::Error;
+pub use ihdr::Ihdr;
+pub use ordering::Validation;
+pub use payload::Payload;
+pub use png::PNG;
+pub use reader::ChunkReader;
+pub use text::TextChunk;
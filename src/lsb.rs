@@ -0,0 +1,446 @@
+//! Messages hidden in the least significant bits of the image data.
+//!
+//! Unlike messages stored in chunks, which metadata tools are free to strip,
+//! these messages are part of the image itself. The image data is decoded from
+//! the `IDAT` chunks, the least significant bit of each color sample is
+//! replaced with a bit of the message, and the image data is encoded again,
+//! using the same filter type for each scanline as before. Interlaced images
+//! are de-interlaced first, so the bits follow the pixels of the full image,
+//! and their passes are filtered anew.
+//!
+//! Only color samples carry message bits, so the alpha channel is left
+//! unchanged. Indexed-color images, where changing a palette index may change
+//! the color entirely, and 1-bit greyscale images, where the least significant
+//! bit is the whole sample, can not hide messages. Pixels which could become
+//! or stop being transparent because of a `tRNS` chunk are skipped.
+//!
+//! The message is prefixed with a header of the magic bytes `pmLS` and its
+//! length in bytes as a four-byte unsigned integer, and its bits are stored
+//! most significant bit first, in the order the samples appear in the image.
+
+use anyhow::{Context, Result};
+
+use crate::filter::{self, FilterStrategy, FilterType};
+use crate::idat::DeflateOptions;
+use crate::ihdr::{ColorType, Ihdr, InterlaceMethod};
+use crate::pixels::transparent_color;
+use crate::png::PNG;
+
+/// Magic bytes identifying a hidden message.
+const MAGIC: [u8; 4] = *b"pmLS";
+
+/// Length of the header preceding a hidden message in bytes.
+const HEADER_LEN: usize = MAGIC.len() + 4;
+
+/// Returns the maximum length in bytes of a message which can be hidden in the
+/// image data of the PNG.
+///
+/// # Errors
+///
+/// Returns an error if the image data could not be decoded, or if the image
+/// can not hide messages.
+pub fn capacity(png: &PNG) -> Result<usize> {
+    let raster = Raster::decode(png)?;
+    let key = transparent_color(png, &raster.ihdr)?;
+
+    Ok((raster.carriers(key.as_deref()).count() / 8).saturating_sub(HEADER_LEN))
+}
+
+/// Hides a message in the least significant bits of the image data of the
+/// PNG, replacing its `IDAT` chunks.
+///
+/// Any message previously hidden in the image data is overwritten.
+///
+/// # Errors
+///
+/// Returns an error if the image data could not be decoded, if the image can
+/// not hide messages, or if the message exceeds its [`capacity`].
+pub fn hide(png: &mut PNG, message: &[u8]) -> Result<()> {
+    let mut raster = Raster::decode(png)?;
+    let key = transparent_color(png, &raster.ihdr)?;
+
+    let length = u32::try_from(message.len()).ok();
+    let bits = (HEADER_LEN + message.len()) * 8;
+
+    let carriers: Vec<_> = raster.carriers(key.as_deref()).take(bits).collect();
+    if length.is_none() || carriers.len() < bits {
+        anyhow::bail!(
+            "message of {} bytes exceeds the capacity of the image ({} bytes)",
+            message.len(),
+            (raster.carriers(key.as_deref()).count() / 8).saturating_sub(HEADER_LEN)
+        );
+    }
+
+    let header = MAGIC
+        .into_iter()
+        .chain(length.unwrap_or_default().to_be_bytes());
+    let bytes = header.chain(message.iter().copied());
+    let bits = bytes.flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1 == 1));
+
+    for ((idx, mask), bit) in carriers.into_iter().zip(bits) {
+        if bit {
+            raster.data[idx] |= mask;
+        } else {
+            raster.data[idx] &= !mask;
+        }
+    }
+
+    raster.encode(png)
+}
+
+/// Reveals the message hidden in the image data of the PNG, or `None` if no
+/// message is hidden in it.
+///
+/// # Errors
+///
+/// Returns an error if the image data could not be decoded, if the image can
+/// not hide messages, or if the hidden message is truncated.
+pub fn reveal(png: &PNG) -> Result<Option<Vec<u8>>> {
+    let raster = Raster::decode(png)?;
+    let key = transparent_color(png, &raster.ihdr)?;
+
+    let mut bits = raster
+        .carriers(key.as_deref())
+        .map(|(idx, mask)| raster.data[idx] & mask != 0);
+    let mut next_byte = || {
+        (0..8).try_fold(0u8, |byte, _| {
+            bits.next().map(|bit| (byte << 1) | bit as u8)
+        })
+    };
+
+    let mut header = [0; HEADER_LEN];
+    for byte in &mut header {
+        match next_byte() {
+            Some(b) => *byte = b,
+            None => return Ok(None),
+        }
+    }
+
+    if header[..MAGIC.len()] != MAGIC {
+        return Ok(None);
+    }
+
+    let length = u32::from_be_bytes(header[MAGIC.len()..].try_into().unwrap()) as usize;
+
+    let mut message = Vec::new();
+    for _ in 0..length {
+        let byte = next_byte().with_context(|| {
+            format!("hidden message is truncated: expected {length} bytes, but the image can not hold them")
+        })?;
+        message.push(byte);
+    }
+
+    Ok(Some(message))
+}
+
+/// Decoded image data of a PNG, with the filter type of each scanline if the
+/// image is not interlaced.
+#[derive(Debug)]
+struct Raster {
+    ihdr: Ihdr,
+    /// Length of a scanline in bytes, excluding the filter type byte.
+    row_len: usize,
+    /// Unfiltered scanlines of the full image, without their filter type bytes.
+    data: Vec<u8>,
+    filter_types: Option<Vec<FilterType>>,
+}
+
+impl Raster {
+    /// Decompresses and unfilters the image data of the PNG.
+    fn decode(png: &PNG) -> Result<Self> {
+        let ihdr = png.ihdr()?;
+
+        anyhow::ensure!(
+            ihdr.color_type() != ColorType::Indexed,
+            "indexed-color images can not hide messages"
+        );
+        anyhow::ensure!(
+            ihdr.bit_depth() > 1,
+            "1-bit greyscale images can not hide messages"
+        );
+
+        let row_len = ihdr.scanline_len(ihdr.width());
+
+        if ihdr.interlace_method() == InterlaceMethod::Adam7 {
+            return Ok(Raster {
+                ihdr,
+                row_len,
+                data: png.scanlines()?,
+                filter_types: None,
+            });
+        }
+
+        let filtered = png.inflate_image_data()?;
+        let data = filter::unfilter(&filtered, row_len, ihdr.bytes_per_pixel())?;
+        let filter_types = filtered
+            .chunks_exact(row_len + 1)
+            .map(|line| FilterType::try_from(line[0]))
+            .collect::<Result<_>>()?;
+
+        Ok(Raster {
+            ihdr,
+            row_len,
+            data,
+            filter_types: Some(filter_types),
+        })
+    }
+
+    /// Replaces the image data of the PNG with the scanlines, filtered with
+    /// their original filter types if the image is not interlaced.
+    fn encode(&self, png: &mut PNG) -> Result<()> {
+        let Some(filter_types) = &self.filter_types else {
+            return png.set_scanlines(
+                &self.data,
+                FilterStrategy::default(),
+                &DeflateOptions::default(),
+            );
+        };
+
+        let bpp = self.ihdr.bytes_per_pixel();
+        let zeros = vec![0; self.row_len];
+
+        let mut filtered = Vec::with_capacity(self.data.len() + filter_types.len());
+        let mut prev = zeros.as_slice();
+
+        for (row, &filter_type) in self.data.chunks_exact(self.row_len).zip(filter_types) {
+            filtered.push(filter_type as u8);
+            filter::filter_row(filter_type, row, prev, bpp, &mut filtered);
+            prev = row;
+        }
+
+        png.deflate_image_data(&filtered, &DeflateOptions::default())
+    }
+
+    /// Returns the sample of the scanline starting at byte `row` whose bits
+    /// start at bit `offset` of the scanline.
+    fn sample(&self, row: usize, offset: usize) -> u16 {
+        let byte = row + offset / 8;
+
+        match self.ihdr.bit_depth() {
+            16 => u16::from_be_bytes([self.data[byte], self.data[byte + 1]]),
+            8 => self.data[byte] as u16,
+            depth => {
+                let shift = 8 - depth as usize - offset % 8;
+                ((self.data[byte] >> shift) & ((1 << depth) - 1)) as u16
+            }
+        }
+    }
+
+    /// Returns the byte index and bit mask of the least significant bit of
+    /// every color sample which can carry a bit of a message, in order.
+    ///
+    /// Pixels whose color matches the transparent color `key` in all but the
+    /// least significant bits are skipped, as are the alpha samples.
+    fn carriers<'a>(&'a self, key: Option<&'a [u16]>) -> impl Iterator<Item = (usize, u8)> + 'a {
+        let depth = self.ihdr.bit_depth() as usize;
+        let channels = self.ihdr.color_type().channels();
+        let color_channels = match self.ihdr.color_type() {
+            ColorType::GrayscaleAlpha | ColorType::TruecolorAlpha => channels - 1,
+            _ => channels,
+        };
+
+        (0..self.ihdr.height() as usize).flat_map(move |y| {
+            let row = y * self.row_len;
+
+            (0..self.ihdr.width() as usize).flat_map(move |x| {
+                let pixel = x * channels * depth;
+
+                let skipped = key.is_some_and(|key| {
+                    (0..color_channels)
+                        .all(|c| self.sample(row, pixel + c * depth) | 1 == key[c] | 1)
+                });
+                let carriers = if skipped { 0 } else { color_channels };
+
+                (0..carriers).map(move |c| {
+                    let lsb = pixel + c * depth + depth - 1;
+                    (row + lsb / 8, 0x80 >> (lsb % 8))
+                })
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+
+    /// Creates a PNG of the image with the given header, with each scanline
+    /// filtered with the next filter type in turn.
+    fn image_png(ihdr: Ihdr, data: &[u8], trns: Option<&[u8]>) -> PNG {
+        let row_len = (ihdr.width() as usize * ihdr.bits_per_pixel()).div_ceil(8);
+        let bpp = ihdr.bytes_per_pixel();
+        let zeros = vec![0; row_len];
+
+        let mut filtered = Vec::new();
+        let mut prev = zeros.as_slice();
+        for (y, row) in data.chunks_exact(row_len).enumerate() {
+            let filter_type = FilterType::try_from((y % 5) as u8).unwrap();
+            filtered.push(filter_type as u8);
+            filter::filter_row(filter_type, row, prev, bpp, &mut filtered);
+            prev = row;
+        }
+        let compressed = miniz_oxide::deflate::compress_to_vec_zlib(&filtered, 6);
+
+        let chunk =
+            |ty: &str, data: Vec<u8>| Chunk::new(ChunkType::from_str(ty).unwrap(), data).unwrap();
+
+        let mut chunks = vec![chunk("IHDR", ihdr.as_bytes().to_vec())];
+        if let Some(trns) = trns {
+            chunks.push(chunk("tRNS", trns.to_vec()));
+        }
+        // Split the image data across two chunks.
+        let (first, second) = compressed.split_at(compressed.len() / 2);
+        chunks.push(chunk("IDAT", first.to_vec()));
+        chunks.push(chunk("IDAT", second.to_vec()));
+        chunks.push(chunk("IEND", Vec::new()));
+
+        PNG::from_chunks(chunks)
+    }
+
+    fn test_image(width: u32, height: u32, bit_depth: u8, color_type: ColorType) -> (PNG, Vec<u8>) {
+        let ihdr = Ihdr::new(width, height, bit_depth, color_type, InterlaceMethod::None).unwrap();
+        let row_len = (width as usize * ihdr.bits_per_pixel()).div_ceil(8);
+        let data: Vec<u8> = (0..row_len * height as usize)
+            .map(|i| (i * 131 % 251) as u8)
+            .collect();
+
+        (image_png(ihdr, &data, None), data)
+    }
+
+    #[test]
+    fn test_hide_and_reveal() {
+        for (bit_depth, color_type) in [
+            (2, ColorType::Grayscale),
+            (4, ColorType::Grayscale),
+            (8, ColorType::Grayscale),
+            (16, ColorType::Grayscale),
+            (8, ColorType::Truecolor),
+            (16, ColorType::Truecolor),
+            (8, ColorType::GrayscaleAlpha),
+            (16, ColorType::TruecolorAlpha),
+        ] {
+            let (mut png, _) = test_image(23, 17, bit_depth, color_type);
+            let capacity = capacity(&png).unwrap();
+            let message: Vec<u8> = (0..capacity).map(|i| i as u8).collect();
+
+            assert_eq!(reveal(&png).unwrap(), None);
+
+            hide(&mut png, &message).unwrap();
+            assert_eq!(png.chunks_by_type("IDAT").count(), 1);
+            assert_eq!(
+                reveal(&png).unwrap(),
+                Some(message),
+                "{bit_depth}-bit {color_type}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        // 16x16 truecolor: 768 samples, 96 bytes, less the header.
+        let (png, _) = test_image(16, 16, 8, ColorType::Truecolor);
+        assert_eq!(capacity(&png).unwrap(), 96 - HEADER_LEN);
+
+        // Alpha samples carry no bits.
+        let (png, _) = test_image(16, 16, 16, ColorType::TruecolorAlpha);
+        assert_eq!(capacity(&png).unwrap(), 96 - HEADER_LEN);
+
+        // 2-bit greyscale: 4 samples per byte, one bit each.
+        let (png, _) = test_image(16, 16, 2, ColorType::Grayscale);
+        assert_eq!(capacity(&png).unwrap(), 32 - HEADER_LEN);
+    }
+
+    #[test]
+    fn test_hide_only_changes_color_lsbs() {
+        let (mut png, original) = test_image(16, 16, 8, ColorType::TruecolorAlpha);
+        hide(&mut png, b"hidden message").unwrap();
+
+        let raster = Raster::decode(&png).unwrap();
+        for (i, (&before, &after)) in original.iter().zip(&raster.data).enumerate() {
+            if i % 4 == 3 {
+                assert_eq!(before, after, "alpha sample {i} changed");
+            } else {
+                assert_eq!(before & !1, after & !1, "sample {i} changed beyond its LSB");
+            }
+        }
+    }
+
+    #[test]
+    fn test_hide_exceeding_capacity() {
+        let (mut png, _) = test_image(8, 8, 8, ColorType::Grayscale);
+        let capacity = capacity(&png).unwrap();
+
+        assert_eq!(capacity, 0);
+        assert!(hide(&mut png, b"x").is_err());
+        assert!(hide(&mut png, b"").is_ok());
+    }
+
+    #[test]
+    fn test_hide_skips_transparent_color() {
+        let ihdr = Ihdr::new(128, 1, 8, ColorType::Grayscale, InterlaceMethod::None).unwrap();
+        // Every third pixel matches the transparent grey level 100 in all but
+        // its least significant bit.
+        let data: Vec<u8> = (0..128)
+            .map(|i| if i % 3 == 0 { 100 + i % 2 } else { i % 90 })
+            .collect();
+        let mut png = image_png(ihdr, &data, Some(&[0, 100]));
+
+        hide(&mut png, &[0xff; 2]).unwrap();
+        assert_eq!(reveal(&png).unwrap(), Some(vec![0xff; 2]));
+
+        let raster = Raster::decode(&png).unwrap();
+        for i in (0..128).step_by(3) {
+            assert_eq!(raster.data[i], data[i], "transparent pixel {i} changed");
+        }
+    }
+
+    #[test]
+    fn test_hide_in_interlaced_image() {
+        let (mut png, data) = test_image(13, 11, 8, ColorType::Truecolor);
+        png.set_interlace_method(
+            InterlaceMethod::Adam7,
+            FilterStrategy::default(),
+            &DeflateOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(capacity(&png).unwrap(), 13 * 11 * 3 / 8 - HEADER_LEN);
+
+        hide(&mut png, b"interlaced").unwrap();
+        assert_eq!(
+            png.ihdr().unwrap().interlace_method(),
+            InterlaceMethod::Adam7
+        );
+        assert_eq!(reveal(&png).unwrap(), Some(b"interlaced".to_vec()));
+
+        let scanlines = png.scanlines().unwrap();
+        for (i, (&hidden, &original)) in scanlines.iter().zip(&data).enumerate() {
+            assert_eq!(
+                hidden | 1,
+                original | 1,
+                "sample {i} changed beyond its LSB"
+            );
+        }
+    }
+
+    #[test]
+    fn test_unsupported_images() {
+        let (png, _) = test_image(8, 8, 1, ColorType::Grayscale);
+        assert!(capacity(&png).is_err());
+
+        let (png, _) = test_image(8, 8, 8, ColorType::Indexed);
+        assert!(capacity(&png).is_err());
+    }
+
+    #[test]
+    fn test_truncated_image_data() {
+        let (png, _) = test_image(8, 8, 8, ColorType::Grayscale);
+        let mut chunks = png.chunks().to_vec();
+        chunks.remove(2);
+
+        assert!(capacity(&PNG::from_chunks(chunks)).is_err());
+    }
+}
@@ -4,24 +4,152 @@
 #![warn(missing_debug_implementations)]
 #![warn(rust_2018_idioms)]
 
-mod chunk;
-mod chunk_type;
-mod commands;
-mod png;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::{self, FromStr};
 
-use std::path::PathBuf;
+use anyhow::{Context, Result};
+use base64::prelude::{BASE64_STANDARD, Engine};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use pngme::apng::{BlendOp, DisposeOp, FrameOptions};
+use pngme::commands::{
+    self, ChunkSummary, DecodeOptions, Decryption, EncodeOptions, Encryption, InvalidPngError,
+    Output, SigningOptions, TextOptions,
+};
+use pngme::crypto::{AuthenticationError, PublicKey};
+use pngme::ihdr::InterlaceMethod;
+use pngme::inspect::ChunkInfo;
+use pngme::{ChunkType, Payload, Validation};
+use serde_json::{Value, json};
 
-use anyhow::Result;
-use clap::{Args, Parser, Subcommand};
+/// Environment variable holding the passphrase of encrypted messages.
+const PASSPHRASE_VAR: &str = "PNGME_PASSPHRASE";
 
 #[derive(Debug, Parser)]
 #[command(name = "pngme")]
 #[command(version, about = None, long_about = None)]
+#[command(after_help = AFTER_HELP)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Rejects PNG files which violate the chunk ordering rules, instead of
+    /// reporting them as warnings.
+    #[arg(long, global = true)]
+    strict: bool,
+    /// Output format of results and errors.
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Overwrites existing output files and backups, instead of refusing to.
+    #[arg(long, global = true)]
+    force: bool,
 }
 
+/// Notes on paths and exit statuses listed in the help message.
+const AFTER_HELP: &str =
+    "A PNG file given as '-' is read from standard input, or written to standard
+output, in which case results are printed to standard error instead.
+Existing files are never overwritten unless '--force' is given, except for
+the input file, which '--in-place' replaces.
+
+Exit status:
+  0  Success
+  1  Any other failure
+  2  Invalid command-line arguments
+  3  Message, hidden message, or signature not found
+  4  Invalid PNG file
+  5  I/O error
+  6  Authentication failed";
+
+/// Exit status of a failed command, telling apart the reasons it failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Status {
+    /// Any other failure.
+    Failure = 1,
+    /// The message, hidden message, or signature was not found.
+    NotFound = 3,
+    /// The input file is not a valid PNG file.
+    InvalidPng = 4,
+    /// Reading or writing a file failed.
+    Io = 5,
+    /// A message failed to decrypt, or a signature failed to verify.
+    AuthenticationFailed = 6,
+}
+
+impl Status {
+    /// Returns the status of the error, from the first cause in its chain
+    /// with a known status.
+    fn of(err: &anyhow::Error) -> Self {
+        if let Some(err) = find::<StatusError>(err) {
+            err.status
+        } else if find::<AuthenticationError>(err).is_some() {
+            Status::AuthenticationFailed
+        } else if let Some(err) = find::<pngme::Error>(err) {
+            // Errors creating chunks do not come from the input datastream.
+            match err {
+                pngme::Error::Io(_) => Status::Io,
+                pngme::Error::DataTooLong { .. } | pngme::Error::NoChunkHeader => Status::Failure,
+                _ => Status::InvalidPng,
+            }
+        } else if find::<InvalidPngError>(err).is_some() {
+            Status::InvalidPng
+        } else if find::<io::Error>(err).is_some() {
+            Status::Io
+        } else {
+            Status::Failure
+        }
+    }
+
+    /// Returns the name of the status in JSON output.
+    fn name(self) -> &'static str {
+        match self {
+            Status::Failure => "failure",
+            Status::NotFound => "not_found",
+            Status::InvalidPng => "invalid_png",
+            Status::Io => "io",
+            Status::AuthenticationFailed => "authentication_failed",
+        }
+    }
+}
+
+/// Returns the error of the given type in the chain of the error, including
+/// errors attached as context.
+fn find<E: Error + Send + Sync + 'static>(err: &anyhow::Error) -> Option<&E> {
+    err.downcast_ref::<E>()
+        .or_else(|| err.chain().find_map(|cause| cause.downcast_ref::<E>()))
+}
+
+/// Error failing a command with a specific exit status, along with fields
+/// added to the JSON error output.
+#[derive(Debug)]
+struct StatusError {
+    status: Status,
+    message: String,
+    details: Value,
+}
+
+impl StatusError {
+    /// Returns an error for a message which could not be found.
+    fn not_found(message: String) -> Self {
+        Self {
+            status: Status::NotFound,
+            message,
+            details: json!({ "found": false }),
+        }
+    }
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for StatusError {}
+
 #[derive(Debug, Subcommand)]
 #[command(subcommand_value_name = "command")]
 enum Commands {
@@ -30,16 +158,58 @@ enum Commands {
     Encode {
         #[command(flatten)]
         opts: CommandOpts,
-        #[arg(value_name = "message")]
-        message: String,
+        #[arg(value_name = "message", required_unless_present_any = ["file", "stdin"])]
+        message: Option<String>,
         #[arg(value_name = "outfile")]
         output_path: Option<PathBuf>,
+        #[command(flatten)]
+        in_place: InPlaceOpts,
+        /// With --in-place, appends the message by rewriting only the end of
+        /// the file, without reading the rest of it. Much faster for large
+        /// files, but not atomic: the file is edited directly, so use --backup
+        /// to keep a copy of it.
+        #[arg(long, requires = "in_place", conflicts_with_all = ["keyword", "sign"])]
+        tail: bool,
+        #[command(flatten)]
+        payload: PayloadOpts,
+        #[command(flatten)]
+        text: TextOpts,
+        /// Encrypts the message with a passphrase, read from the
+        /// PNGME_PASSPHRASE environment variable or prompted for.
+        #[arg(long, conflicts_with = "keyword")]
+        encrypt: bool,
+        /// Encrypts the message to the public key of a recipient. May be
+        /// repeated to encrypt to several recipients.
+        #[arg(long, value_name = "pubkey", conflicts_with_all = ["keyword", "encrypt"])]
+        recipient: Vec<PublicKey>,
+        /// Signs the message with the signing key in the key file.
+        #[arg(long, value_name = "keyfile")]
+        sign: Option<PathBuf>,
+        /// Makes the signature also cover the image data, so it is invalidated
+        /// if the image is altered.
+        #[arg(long, requires = "sign")]
+        sign_image: bool,
+        /// Splits the message into fragments of at most this many bytes, each
+        /// stored in its own chunk. Messages larger than 1 MiB are always
+        /// fragmented.
+        #[arg(long, value_name = "bytes", conflicts_with = "keyword")]
+        fragment_size: Option<usize>,
     },
     /// Decodes a message from a PNG file.
     #[command(arg_required_else_help = true)]
     Decode {
         #[command(flatten)]
         opts: CommandOpts,
+        /// Decrypts the message with a passphrase, read from the
+        /// PNGME_PASSPHRASE environment variable or prompted for.
+        #[arg(long, conflicts_with = "keyword")]
+        decrypt: bool,
+        /// Decrypts a message encrypted to recipients with the identity in the
+        /// key file.
+        #[arg(long, value_name = "keyfile", conflicts_with_all = ["keyword", "decrypt"])]
+        identity: Option<PathBuf>,
+        #[command(flatten)]
+        output: OutputOpts,
     },
     /// Removes a message from a PNG file.
     #[command(arg_required_else_help = true)]
@@ -48,6 +218,149 @@ enum Commands {
         opts: CommandOpts,
         #[arg(value_name = "outfile")]
         output_path: Option<PathBuf>,
+        #[command(flatten)]
+        in_place: InPlaceOpts,
+        /// With --in-place, removes the message from the last chunks of the
+        /// file by rewriting only its end, without reading the rest of it.
+        /// Much faster for large files, but not atomic: the file is edited
+        /// directly, so use --backup to keep a copy of it.
+        #[arg(long, requires = "in_place", conflicts_with = "keyword")]
+        tail: bool,
+    },
+    /// Verifies the signatures of messages in a PNG file.
+    #[command(arg_required_else_help = true)]
+    Verify {
+        #[arg(value_name = "infile")]
+        file_path: PathBuf,
+        /// Only verifies signatures of messages with this chunk type.
+        #[arg(value_name = "chunk_type")]
+        chunk_type: Option<String>,
+    },
+    /// Hides messages in the least significant bits of the image data.
+    #[command(arg_required_else_help = true)]
+    Lsb {
+        #[command(subcommand)]
+        command: LsbCommands,
+    },
+    /// Lists the chunks of a PNG file.
+    ///
+    /// The properties of each chunk type are shown as four letters, or "-" if
+    /// unset: C (critical), P (public), R (reserved bit valid), and S
+    /// (safe-to-copy).
+    #[command(arg_required_else_help = true)]
+    Inspect {
+        #[arg(value_name = "infile")]
+        file_path: PathBuf,
+    },
+    /// Assembles and splits animated PNG files.
+    #[command(arg_required_else_help = true)]
+    Apng {
+        #[command(subcommand)]
+        command: ApngCommands,
+    },
+    /// Converts a PNG file to or from Adam7 interlacing.
+    #[command(arg_required_else_help = true)]
+    Interlace {
+        #[arg(value_name = "infile")]
+        file_path: PathBuf,
+        #[arg(value_name = "outfile")]
+        output_path: Option<PathBuf>,
+        #[command(flatten)]
+        in_place: InPlaceOpts,
+        /// Converts an interlaced image to a non-interlaced one instead.
+        #[arg(long)]
+        none: bool,
+    },
+    /// Generates a key for decrypting or signing messages.
+    Keygen {
+        /// Writes the key to a key file, instead of standard output.
+        #[arg(short, long, value_name = "keyfile")]
+        output: Option<PathBuf>,
+        /// Generates a key for signing messages instead.
+        #[arg(long)]
+        signing: bool,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Human-readable text.
+    Text,
+    /// A single JSON document on standard output, including for errors.
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+#[command(subcommand_value_name = "command")]
+enum LsbCommands {
+    /// Hides a message in the image data of a PNG file.
+    #[command(arg_required_else_help = true)]
+    Hide {
+        #[arg(value_name = "infile")]
+        file_path: PathBuf,
+        #[arg(value_name = "message", required_unless_present_any = ["file", "stdin"])]
+        message: Option<String>,
+        #[arg(value_name = "outfile")]
+        output_path: Option<PathBuf>,
+        #[command(flatten)]
+        in_place: InPlaceOpts,
+        #[command(flatten)]
+        payload: PayloadOpts,
+    },
+    /// Reveals the message hidden in the image data of a PNG file.
+    #[command(arg_required_else_help = true)]
+    Reveal {
+        #[arg(value_name = "infile")]
+        file_path: PathBuf,
+        #[command(flatten)]
+        output: OutputOpts,
+    },
+    /// Reports how many bytes can be hidden in the image data of a PNG file.
+    #[command(arg_required_else_help = true)]
+    Capacity {
+        #[arg(value_name = "infile")]
+        file_path: PathBuf,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+#[command(subcommand_value_name = "command")]
+enum ApngCommands {
+    /// Assembles an animated PNG file from PNG files, one per frame.
+    ///
+    /// Frame options given once apply to every frame, or may be given once
+    /// per frame, in order.
+    #[command(arg_required_else_help = true)]
+    Build {
+        #[arg(value_name = "outfile")]
+        output_path: PathBuf,
+        #[arg(value_name = "frames", required = true)]
+        frame_paths: Vec<PathBuf>,
+        /// Delay after each frame, in milliseconds or as a fraction of a
+        /// second (e.g., "1/30").
+        #[arg(long, value_name = "delay", value_parser = parse_delay)]
+        delay: Vec<(u16, u16)>,
+        /// How each frame is disposed of: none, background, or previous.
+        #[arg(long, value_name = "op")]
+        dispose: Vec<DisposeOp>,
+        /// How each frame is blended onto the canvas: source or over.
+        #[arg(long, value_name = "op")]
+        blend: Vec<BlendOp>,
+        /// Position of each frame on the canvas (e.g., "10,20").
+        #[arg(long, value_name = "x,y", value_parser = parse_offset)]
+        offset: Vec<(u32, u32)>,
+        /// Number of times to play the animation, or 0 to loop forever.
+        #[arg(long, value_name = "count", default_value_t = 0)]
+        plays: u32,
+    },
+    /// Splits an animated PNG file into PNG files, one per frame.
+    #[command(arg_required_else_help = true)]
+    Split {
+        #[arg(value_name = "infile")]
+        file_path: PathBuf,
+        /// Directory to write the frames to, instead of the current directory.
+        #[arg(value_name = "outdir")]
+        output_dir: Option<PathBuf>,
     },
 }
 
@@ -57,31 +370,871 @@ struct CommandOpts {
     file_path: PathBuf,
     #[arg(value_name = "chunk_type")]
     chunk_type: String,
+    /// Keyword of the message, stored in a textual chunk (tEXt, zTXt, or iTXt)
+    /// given as the chunk type.
+    #[arg(long, value_name = "keyword")]
+    keyword: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct PayloadOpts {
+    /// Embeds the raw bytes of a file, along with its filename and MIME type.
+    /// The message argument is omitted.
+    #[arg(long, value_name = "path", conflicts_with = "stdin")]
+    file: Option<PathBuf>,
+    /// Embeds the raw bytes read from standard input. The message argument is
+    /// omitted.
+    #[arg(long)]
+    stdin: bool,
+    /// MIME type of the embedded bytes, instead of guessing it from the
+    /// filename.
+    #[arg(long, value_name = "type")]
+    mime_type: Option<String>,
+}
+
+#[derive(Args, Debug)]
+#[group(multiple = false)]
+struct OutputOpts {
+    /// Writes the message byte-for-byte to a file, or to standard output if
+    /// the path is "-". If the path is a directory, the original filename of
+    /// the message is restored within it.
+    #[arg(short, long, value_name = "path")]
+    output: Option<PathBuf>,
+    /// Prints the message as hexadecimal digits.
+    #[arg(long)]
+    hex: bool,
+    /// Prints the message encoded as base64.
+    #[arg(long)]
+    base64: bool,
+}
+
+#[derive(Args, Debug)]
+struct InPlaceOpts {
+    /// Replaces the input file atomically, instead of writing an output file.
+    #[arg(long, conflicts_with = "output_path")]
+    in_place: bool,
+    /// Keeps the original input file, named with this suffix appended (e.g.,
+    /// ".bak").
+    #[arg(long, value_name = "suffix", requires = "in_place")]
+    backup: Option<String>,
+}
+
+impl InPlaceOpts {
+    /// Returns the output of a command writing a PNG file.
+    fn output(self, output_path: Option<PathBuf>, force: bool) -> Output {
+        Output {
+            path: output_path,
+            in_place: self.in_place,
+            backup: self.backup,
+            force,
+            tail: false,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct TextOpts {
+    /// Language tag of an iTXt message (e.g., "en-US").
+    #[arg(long, value_name = "tag", requires = "keyword")]
+    language: Option<String>,
+    /// Keyword of an iTXt message translated into its language.
+    #[arg(long, value_name = "keyword", requires = "keyword")]
+    translated_keyword: Option<String>,
+    /// Compresses an iTXt message.
+    #[arg(long, requires = "keyword")]
+    compress: bool,
+}
+
+/// Returns the passphrase from the environment, or prompts for it on the
+/// terminal, asking for confirmation if `confirm` is set.
+fn read_passphrase(confirm: bool) -> Result<String> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_VAR) {
+        return Ok(passphrase);
+    }
+
+    let passphrase =
+        rpassword::prompt_password("Passphrase: ").context("failed to read passphrase")?;
+
+    if confirm {
+        let confirmation = rpassword::prompt_password("Confirm passphrase: ")
+            .context("failed to read passphrase")?;
+
+        anyhow::ensure!(passphrase == confirmation, "passphrases do not match");
+    }
+
+    anyhow::ensure!(!passphrase.is_empty(), "passphrase must not be empty");
+
+    Ok(passphrase)
+}
+
+/// Returns the message to encode, given either as the message argument or read
+/// from a file or standard input, along with the output file.
+fn read_message(
+    message: Option<String>,
+    output_path: Option<PathBuf>,
+    payload: PayloadOpts,
+) -> Result<(Payload, Option<PathBuf>)> {
+    match (payload.file, payload.stdin) {
+        (None, false) => {
+            let message = match payload.mime_type {
+                Some(mime_type) => {
+                    Payload::with_metadata(message.unwrap_or_default(), None, Some(mime_type))?
+                }
+                None => Payload::new(message.unwrap_or_default()),
+            };
+
+            Ok((message, output_path))
+        }
+        // Without a message argument, the remaining positional argument is the
+        // output file.
+        (file, _) => {
+            anyhow::ensure!(
+                message.is_none() || output_path.is_none(),
+                "the message argument can not be used with '--file' or '--stdin'"
+            );
+
+            let payload = match file {
+                Some(file) => commands::read_payload(file, payload.mime_type)?,
+                None => commands::read_payload_stdin(payload.mime_type)?,
+            };
+
+            Ok((payload, output_path.or(message.map(PathBuf::from))))
+        }
+    }
+}
+
+/// Parses the delay of a frame, given in milliseconds or as a fraction of a
+/// second.
+fn parse_delay(s: &str) -> Result<(u16, u16)> {
+    let delay = match s.split_once('/') {
+        Some((num, den)) => (num.parse()?, den.parse()?),
+        None => (s.parse()?, 1000),
+    };
+
+    anyhow::ensure!(delay.1 > 0, "denominator must be non-zero");
+
+    Ok(delay)
+}
+
+/// Parses the position of a frame on the canvas, given as "x,y".
+fn parse_offset(s: &str) -> Result<(u32, u32)> {
+    let (x, y) = s.split_once(',').context("expected \"x,y\"")?;
+
+    Ok((x.trim().parse()?, y.trim().parse()?))
+}
+
+/// Returns the option of each frame, repeating an option given once for every
+/// frame, or the default if none is given.
+fn per_frame<T: Clone + Default>(values: Vec<T>, frames: usize, name: &str) -> Result<Vec<T>> {
+    match values.len() {
+        0 => Ok(vec![T::default(); frames]),
+        1 => Ok(vec![values[0].clone(); frames]),
+        n if n == frames => Ok(values),
+        n => anyhow::bail!(
+            "--{name} given {n} times for {frames} frames: give it once, or once per frame"
+        ),
+    }
+}
+
+/// Prints the chunks of a PNG file as a table, with a column per field.
+fn print_chunk_table(chunks: &[ChunkInfo]) {
+    let flag = |set: bool, c: char| if set { c } else { '-' };
+
+    let rows: Vec<[String; 7]> = chunks
+        .iter()
+        .map(|chunk| {
+            let chunk_type = chunk.chunk_type();
+            let properties = [
+                flag(chunk_type.is_critical(), 'C'),
+                flag(chunk_type.is_public(), 'P'),
+                flag(chunk_type.is_reserved_bit_valid(), 'R'),
+                flag(chunk_type.is_safe_to_copy(), 'S'),
+            ];
+            let summary = match (chunk.summary(), chunk.error()) {
+                (_, Some(error)) => format!("error: {error}"),
+                (Some(summary), None) => summary.to_owned(),
+                (None, None) => String::new(),
+            };
+
+            [
+                chunk.index().to_string(),
+                chunk.offset().to_string(),
+                chunk_type.to_string(),
+                properties.iter().collect(),
+                chunk.length().to_string(),
+                format!("{:08x}", chunk.crc()),
+                summary,
+            ]
+        })
+        .collect();
+
+    let header = [
+        "INDEX", "OFFSET", "TYPE", "FLAGS", "LENGTH", "CRC", "SUMMARY",
+    ]
+    .map(String::from);
+    let mut widths = header.clone().map(|h| h.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<_> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+/// Returns the chunks of a PNG file as a JSON document.
+fn chunks_json(chunks: &[ChunkInfo]) -> serde_json::Value {
+    let chunks: Vec<_> = chunks
+        .iter()
+        .map(|chunk| {
+            let chunk_type = chunk.chunk_type();
+
+            serde_json::json!({
+                "index": chunk.index(),
+                "offset": chunk.offset(),
+                "type": chunk_type.to_string(),
+                "critical": chunk_type.is_critical(),
+                "public": chunk_type.is_public(),
+                "reserved_bit_valid": chunk_type.is_reserved_bit_valid(),
+                "safe_to_copy": chunk_type.is_safe_to_copy(),
+                "length": chunk.length(),
+                "crc": format!("{:08x}", chunk.crc()),
+                "summary": chunk.summary(),
+                "error": chunk.error(),
+            })
+        })
+        .collect();
+
+    serde_json::json!({ "chunks": chunks })
+}
+
+/// Returns the chunk type, length, and CRC of chunks in JSON output.
+fn chunk_summaries_json(chunks: &[ChunkSummary]) -> Value {
+    chunks
+        .iter()
+        .map(|chunk| {
+            json!({
+                "type": chunk.chunk_type.to_string(),
+                "length": chunk.length,
+                "crc": format!("{:08x}", chunk.crc),
+            })
+        })
+        .collect()
+}
+
+/// Returns a path in JSON output.
+fn path_json(path: &Path) -> Value {
+    Value::from(path.display().to_string())
+}
+
+/// Checks the chunk type argument of a command, failing with
+/// [`Status::Failure`] rather than [`Status::InvalidPng`] if it is invalid.
+fn check_chunk_type(chunk_type: &str) -> Result<()> {
+    ChunkType::from_str(chunk_type).map_err(|err| {
+        anyhow::Error::new(err).context(StatusError {
+            status: Status::Failure,
+            message: format!("invalid chunk type argument '{chunk_type}'"),
+            details: Value::Null,
+        })
+    })?;
+
+    Ok(())
+}
+
+/// Returns an error for a message which could not be found in the PNG file,
+/// identified by chunk type and keyword.
+fn message_not_found(file_path: &Path, chunk_type: &str, keyword: Option<&str>) -> StatusError {
+    StatusError::not_found(format!(
+        "no message with chunk type '{}'{} found in '{}'",
+        chunk_type,
+        match keyword {
+            Some(keyword) => format!(" and keyword '{keyword}'"),
+            None => String::new(),
+        },
+        file_path.display()
+    ))
+}
+
+/// Writes the decoded payload to a file, or prints it to standard output,
+/// returning its fields in JSON output, which include the message itself
+/// unless it is written to a file, which is only overwritten if `force` is set.
+///
+/// Binary messages are encoded as base64 in JSON output.
+fn output_payload(
+    payload: &Payload,
+    output: OutputOpts,
+    format: Format,
+    stderr: bool,
+    force: bool,
+) -> Result<Value> {
+    let mut fields = json!({
+        "length": payload.data().len(),
+        "filename": payload.filename(),
+        "mime_type": payload.mime_type(),
+    });
+
+    if let Some(out_path) = output.output {
+        let outfile = commands::write_payload(payload, out_path, force)?;
+        fields["output"] = path_json(&outfile);
+
+        return Ok(fields);
+    }
+
+    let text = str::from_utf8(payload.data());
+
+    let (encoding, message) = if output.hex {
+        ("hex", hex::encode(payload.data()))
+    } else if output.base64 || (text.is_err() && format == Format::Json) {
+        ("base64", BASE64_STANDARD.encode(payload.data()))
+    } else {
+        let Ok(text) = text else {
+            anyhow::bail!(
+                "message{} is binary data: use --output, --hex, or --base64 to write it",
+                match (payload.filename(), payload.mime_type()) {
+                    (Some(filename), Some(mime_type)) => format!(" '{filename}' ({mime_type})"),
+                    (Some(filename), None) => format!(" '{filename}'"),
+                    (None, Some(mime_type)) => format!(" ({mime_type})"),
+                    (None, None) => String::new(),
+                }
+            );
+        };
+
+        ("utf-8", text.to_owned())
+    };
+
+    match format {
+        Format::Text => say(stderr, &message),
+        Format::Json => {
+            fields["encoding"] = Value::from(encoding);
+            fields["message"] = Value::from(message);
+        }
+    }
+
+    Ok(fields)
 }
 
-fn main() -> Result<()> {
+/// Returns the JSON output for an error, with its exit status, message, and
+/// causes, along with the details of a [`StatusError`].
+fn error_json(err: &anyhow::Error, status: Status) -> Value {
+    let causes: Vec<_> = err.chain().skip(1).map(ToString::to_string).collect();
+
+    let mut output = json!({
+        "error": {
+            "status": status.name(),
+            "exit_code": status as u8,
+            "message": err.to_string(),
+            "causes": causes,
+        }
+    });
+
+    if let (Some(Value::Object(details)), Some(output)) = (
+        find::<StatusError>(err).map(|err| &err.details),
+        output.as_object_mut(),
+    ) {
+        output.extend(details.clone());
+    }
+
+    output
+}
+
+/// Returns `true` if the command writes a PNG file or a message to standard
+/// output, so results must be printed to standard error instead.
+fn writes_to_stdout(command: &Commands) -> bool {
+    let output = match command {
+        Commands::Encode {
+            message,
+            output_path,
+            payload,
+            ..
+        }
+        | Commands::Lsb {
+            command:
+                LsbCommands::Hide {
+                    message,
+                    output_path,
+                    payload,
+                    ..
+                },
+        } => message_output_path(message, output_path, payload),
+        Commands::Decode { output, .. }
+        | Commands::Lsb {
+            command: LsbCommands::Reveal { output, .. },
+        } => output.output.as_deref(),
+        Commands::Remove { output_path, .. } | Commands::Interlace { output_path, .. } => {
+            output_path.as_deref()
+        }
+        Commands::Apng {
+            command: ApngCommands::Build { output_path, .. },
+        } => Some(output_path.as_path()),
+        _ => None,
+    };
+
+    output.is_some_and(commands::is_stdio)
+}
+
+/// Returns the output file of a command taking a message, which is the
+/// message argument if the message is read from a file or standard input.
+fn message_output_path<'a>(
+    message: &'a Option<String>,
+    output_path: &'a Option<PathBuf>,
+    payload: &PayloadOpts,
+) -> Option<&'a Path> {
+    match (payload.file.is_some() || payload.stdin, output_path) {
+        (true, None) => message.as_deref().map(Path::new),
+        (_, output_path) => output_path.as_deref(),
+    }
+}
+
+/// Ensures the PNG file and the message are not both read from standard
+/// input.
+fn check_stdin(file_path: &Path, payload: &PayloadOpts) -> Result<()> {
+    anyhow::ensure!(
+        !(payload.stdin && commands::is_stdio(file_path)),
+        "the PNG file can not be read from standard input along with '--stdin'"
+    );
+
+    Ok(())
+}
+
+/// Prints a line of text to standard output, or to standard error if standard
+/// output is taken by a PNG file or message.
+fn say(stderr: bool, text: impl fmt::Display) {
+    if stderr {
+        eprintln!("{text}");
+    } else {
+        println!("{text}");
+    }
+}
+
+fn main() -> ExitCode {
     let args = Cli::parse();
+    let format = args.format;
+    let stderr = writes_to_stdout(&args.command);
 
-    match args.command {
+    match run(args, stderr) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            let status = Status::of(&err);
+
+            match format {
+                Format::Text => eprintln!("Error: {err:?}"),
+                Format::Json => say(stderr, format!("{:#}", error_json(&err, status))),
+            }
+
+            ExitCode::from(status as u8)
+        }
+    }
+}
+
+/// Runs the command, printing results to standard error instead of standard
+/// output if `stderr` is set.
+fn run(args: Cli, stderr: bool) -> Result<()> {
+    let format = args.format;
+    let force = args.force;
+    let validation = if args.strict {
+        Validation::Strict
+    } else {
+        Validation::Lenient
+    };
+
+    // Results are printed in text as they are produced, or collected into a
+    // single JSON document printed at the end.
+    let output = match args.command {
         Commands::Encode {
             opts,
             message,
             output_path,
+            in_place,
+            tail,
+            payload,
+            text,
+            encrypt,
+            recipient,
+            sign,
+            sign_image,
+            fragment_size,
         } => {
-            commands::invoke_encode(opts.file_path, opts.chunk_type, message, output_path)?;
+            check_chunk_type(&opts.chunk_type)?;
+            check_stdin(&opts.file_path, &payload)?;
+            let (message, output_path) = read_message(message, output_path, payload)?;
+            let text = opts.keyword.map(|keyword| TextOptions {
+                keyword,
+                language_tag: text.language.unwrap_or_default(),
+                translated_keyword: text.translated_keyword.unwrap_or_default(),
+                compressed: text.compress,
+            });
+            let encryption = if encrypt {
+                Some(Encryption::Passphrase(read_passphrase(true)?))
+            } else if !recipient.is_empty() {
+                Some(Encryption::Recipients(recipient))
+            } else {
+                None
+            };
+            let signing = sign
+                .map(|key| -> Result<_> {
+                    Ok(SigningOptions {
+                        key: commands::read_signing_key(key)?,
+                        covers_image: sign_image,
+                    })
+                })
+                .transpose()?;
+
+            let encoded = commands::invoke_encode(
+                opts.file_path,
+                opts.chunk_type,
+                message,
+                Output {
+                    tail,
+                    ..in_place.output(output_path, force)
+                },
+                EncodeOptions {
+                    text,
+                    encryption,
+                    signing,
+                    fragment_size,
+                },
+                validation,
+            )?;
+
+            json!({
+                "chunks": chunk_summaries_json(&encoded.chunks),
+                "output": path_json(&encoded.out_path),
+            })
         }
-        Commands::Decode { opts } => {
-            if let Some(message) = commands::invoke_decode(opts.file_path, opts.chunk_type)? {
-                println!("{message}");
+        Commands::Decode {
+            opts,
+            decrypt,
+            identity,
+            output,
+        } => {
+            check_chunk_type(&opts.chunk_type)?;
+            let decryption = if decrypt {
+                Some(Decryption::Passphrase(read_passphrase(false)?))
+            } else if let Some(identity) = identity {
+                Some(Decryption::Identity(commands::read_identity(identity)?))
+            } else {
+                None
+            };
+            let not_found =
+                message_not_found(&opts.file_path, &opts.chunk_type, opts.keyword.as_deref());
+
+            let decoded = commands::invoke_decode(
+                opts.file_path,
+                opts.chunk_type,
+                DecodeOptions {
+                    keyword: opts.keyword,
+                    decryption,
+                },
+                validation,
+            )?
+            .ok_or(not_found)?;
+
+            let mut fields = output_payload(&decoded.payload, output, format, stderr, force)?;
+            fields["found"] = Value::from(true);
+            fields["chunks"] = chunk_summaries_json(&decoded.chunks);
+
+            fields
+        }
+        Commands::Remove {
+            opts,
+            output_path,
+            in_place,
+            tail,
+        } => {
+            check_chunk_type(&opts.chunk_type)?;
+            let not_found =
+                message_not_found(&opts.file_path, &opts.chunk_type, opts.keyword.as_deref());
+
+            let removed = commands::invoke_remove(
+                opts.file_path,
+                opts.chunk_type,
+                opts.keyword,
+                Output {
+                    tail,
+                    ..in_place.output(output_path, force)
+                },
+                validation,
+            )?
+            .ok_or(not_found)?;
+
+            let text = str::from_utf8(removed.payload.data()).ok();
+
+            // Binary messages are not printed to the terminal.
+            if let (Format::Text, Some(text)) = (format, text) {
+                say(stderr, text);
             }
+
+            json!({
+                "found": true,
+                "chunks": chunk_summaries_json(&removed.chunks),
+                "output": path_json(&removed.out_path),
+                "message": text,
+            })
         }
-        Commands::Remove { opts, output_path } => {
-            if let Some(message) =
-                commands::invoke_remove(opts.file_path, opts.chunk_type, output_path)?
-            {
-                println!("{message}");
+        Commands::Verify {
+            file_path,
+            chunk_type,
+        } => {
+            if let Some(chunk_type) = &chunk_type {
+                check_chunk_type(chunk_type)?;
             }
+            let verifications = commands::invoke_verify(file_path, chunk_type, validation)?;
+
+            if verifications.is_empty() {
+                return Err(StatusError::not_found("no signatures found".to_owned()).into());
+            }
+
+            let signatures: Vec<_> = verifications
+                .iter()
+                .map(|verification| {
+                    let signature = &verification.signature;
+
+                    if format == Format::Text {
+                        println!(
+                            "{}: {} signature by {}{}",
+                            signature.chunk_type(),
+                            if verification.valid {
+                                "valid"
+                            } else {
+                                "INVALID"
+                            },
+                            signature.signer(),
+                            if signature.covers_image() {
+                                " (covers image data)"
+                            } else {
+                                ""
+                            }
+                        );
+                    }
+
+                    json!({
+                        "chunk_type": signature.chunk_type().to_string(),
+                        "valid": verification.valid,
+                        "signer": signature.signer().to_string(),
+                        "covers_image": signature.covers_image(),
+                    })
+                })
+                .collect();
+
+            if !verifications.iter().all(|v| v.valid) {
+                return Err(StatusError {
+                    status: Status::AuthenticationFailed,
+                    message: "signature verification failed".to_owned(),
+                    details: json!({ "signatures": signatures }),
+                }
+                .into());
+            }
+
+            json!({ "signatures": signatures })
         }
+        Commands::Lsb { command } => match command {
+            LsbCommands::Hide {
+                file_path,
+                message,
+                output_path,
+                in_place,
+                payload,
+            } => {
+                check_stdin(&file_path, &payload)?;
+                let (message, output_path) = read_message(message, output_path, payload)?;
+
+                let out_path = commands::invoke_hide(
+                    file_path,
+                    message,
+                    in_place.output(output_path, force),
+                    validation,
+                )?;
+
+                json!({ "output": path_json(&out_path) })
+            }
+            LsbCommands::Reveal { file_path, output } => {
+                let not_found = StatusError::not_found(format!(
+                    "no hidden message found in '{}'",
+                    file_path.display()
+                ));
+
+                let message = commands::invoke_reveal(file_path, validation)?.ok_or(not_found)?;
+
+                let mut fields = output_payload(&message, output, format, stderr, force)?;
+                fields["found"] = Value::from(true);
+
+                fields
+            }
+            LsbCommands::Capacity { file_path } => {
+                let capacity = commands::invoke_capacity(file_path, validation)?;
+
+                if format == Format::Text {
+                    println!("{capacity} bytes");
+                }
+
+                json!({ "capacity": capacity })
+            }
+        },
+        Commands::Inspect { file_path } => {
+            let chunks = commands::invoke_inspect(file_path)?;
+
+            if format == Format::Text {
+                print_chunk_table(&chunks);
+            }
+
+            chunks_json(&chunks)
+        }
+        Commands::Apng { command } => match command {
+            ApngCommands::Build {
+                output_path,
+                frame_paths,
+                delay,
+                dispose,
+                blend,
+                offset,
+                plays,
+            } => {
+                let frames = frame_paths.len();
+                let default = FrameOptions::default();
+                let delay = if delay.is_empty() {
+                    vec![(default.delay_num, default.delay_den)]
+                } else {
+                    delay
+                };
+
+                let options = per_frame(delay, frames, "delay")?
+                    .into_iter()
+                    .zip(per_frame(dispose, frames, "dispose")?)
+                    .zip(per_frame(blend, frames, "blend")?)
+                    .zip(per_frame(offset, frames, "offset")?)
+                    .map(
+                        |(
+                            (((delay_num, delay_den), dispose_op), blend_op),
+                            (x_offset, y_offset),
+                        )| {
+                            FrameOptions {
+                                x_offset,
+                                y_offset,
+                                delay_num,
+                                delay_den,
+                                dispose_op,
+                                blend_op,
+                            }
+                        },
+                    )
+                    .collect();
+
+                let out_path = commands::invoke_apng_build(
+                    frame_paths,
+                    options,
+                    plays,
+                    output_path,
+                    force,
+                    validation,
+                )?;
+
+                json!({ "output": path_json(&out_path), "frames": frames })
+            }
+            ApngCommands::Split {
+                file_path,
+                output_dir,
+            } => {
+                let frames: Vec<_> =
+                    commands::invoke_apng_split(file_path, output_dir, force, validation)?
+                        .into_iter()
+                        .map(|(path, control)| {
+                            if format == Format::Text {
+                                println!(
+                                    "{}: {}x{} at {},{}, delay {}/{}, dispose {}, blend {}",
+                                    path.display(),
+                                    control.width,
+                                    control.height,
+                                    control.x_offset,
+                                    control.y_offset,
+                                    control.delay_num,
+                                    control.delay_den,
+                                    control.dispose_op,
+                                    control.blend_op
+                                );
+                            }
+
+                            json!({
+                                "output": path_json(&path),
+                                "width": control.width,
+                                "height": control.height,
+                                "x_offset": control.x_offset,
+                                "y_offset": control.y_offset,
+                                "delay_num": control.delay_num,
+                                "delay_den": control.delay_den,
+                                "dispose": control.dispose_op.to_string(),
+                                "blend": control.blend_op.to_string(),
+                            })
+                        })
+                        .collect();
+
+                json!({ "frames": frames })
+            }
+        },
+        Commands::Interlace {
+            file_path,
+            output_path,
+            in_place,
+            none,
+        } => {
+            let interlace_method = if none {
+                InterlaceMethod::None
+            } else {
+                InterlaceMethod::Adam7
+            };
+
+            let out_path = commands::invoke_interlace(
+                file_path,
+                interlace_method,
+                in_place.output(output_path, force),
+                validation,
+            )?;
+
+            json!({ "output": path_json(&out_path) })
+        }
+        Commands::Keygen { output, signing } => {
+            let written = output.as_deref().map(path_json);
+
+            let (name, public_key, contents) = if signing {
+                let key = commands::invoke_keygen_signing(output)?;
+                (
+                    "verifying key",
+                    key.verifying_key().to_string(),
+                    key.to_file_contents(),
+                )
+            } else {
+                let identity = commands::invoke_keygen(output)?;
+                (
+                    "public key",
+                    identity.public_key().to_string(),
+                    identity.to_file_contents(),
+                )
+            };
+
+            match (format, &written) {
+                (Format::Text, Some(_)) => println!("{name}: {public_key}"),
+                (Format::Text, None) => print!("{}", *contents),
+                (Format::Json, _) => {}
+            }
+
+            // The key itself is only output if it was not written to a file.
+            json!({
+                name.replace(' ', "_"): public_key,
+                "output": written,
+                "key": if written.is_some() { None } else { Some(&*contents) },
+            })
+        }
+    };
+
+    if format == Format::Json {
+        say(stderr, format!("{output:#}"));
     }
 
     Ok(())
@@ -0,0 +1,412 @@
+//! Chunk ordering and multiplicity rules of a PNG datastream.
+//!
+//! Version 3.0 of the PNG specification constrains where each chunk type may
+//! appear relative to the `PLTE` and `IDAT` chunks, and how many times it may
+//! appear:
+//!
+//! - IHDR: exactly once, as the first chunk.
+//! - PLTE: at most once, before the first `IDAT` chunk.
+//! - IDAT: one or more, all of which must be consecutive.
+//! - IEND: exactly once, as the last chunk.
+//! - cHRM, cICP, cLLI, gAMA, iCCP, mDCV, sBIT, sRGB: at most once, before
+//!   `PLTE` and `IDAT`.
+//! - bKGD, hIST, tRNS: at most once, after `PLTE` and before `IDAT`.
+//! - acTL, eXIf, pHYs: at most once, before `IDAT`.
+//! - sPLT: any number, before `IDAT`.
+//! - fdAT: any number, after `IDAT`.
+//! - tIME: at most once, anywhere.
+//! - iTXt, tEXt, zTXt, and unknown chunks: any number, anywhere.
+
+use std::fmt;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+
+/// Chunk types which may appear at most once.
+const SINGLE: [&[u8; 4]; 18] = [
+    b"IHDR", b"PLTE", b"IEND", b"cHRM", b"cICP", b"cLLI", b"gAMA", b"iCCP", b"mDCV", b"sBIT",
+    b"sRGB", b"bKGD", b"hIST", b"tRNS", b"acTL", b"eXIf", b"pHYs", b"tIME",
+];
+
+/// Chunk types which must appear before `PLTE`.
+const BEFORE_PLTE: [&[u8; 4]; 8] = [
+    b"cHRM", b"cICP", b"cLLI", b"gAMA", b"iCCP", b"mDCV", b"sBIT", b"sRGB",
+];
+
+/// Chunk types which must appear after `PLTE`, if present.
+const AFTER_PLTE: [&[u8; 4]; 3] = [b"bKGD", b"hIST", b"tRNS"];
+
+/// Chunk types which must appear before the first `IDAT` chunk, in addition
+/// to those in [`BEFORE_PLTE`] and [`AFTER_PLTE`].
+const BEFORE_IDAT: [&[u8; 4]; 5] = [b"PLTE", b"acTL", b"eXIf", b"pHYs", b"sPLT"];
+
+/// Chunk types which must appear after the first `IDAT` chunk.
+const AFTER_IDAT: [&[u8; 4]; 1] = [b"fdAT"];
+
+/// How violations of the chunk ordering rules are handled when decoding.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Validation {
+    /// Any violation is an error.
+    Strict,
+    /// Violations are reported as warnings and the chunks are kept as-is.
+    #[default]
+    Lenient,
+}
+
+/// Rule of the chunk ordering table that was violated.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// The first chunk is not an `IHDR` chunk.
+    MissingIhdr,
+    /// No `IDAT` chunk is present.
+    MissingIdat,
+    /// No `IEND` chunk is present.
+    MissingIend,
+    /// The chunk appears after the `IEND` chunk.
+    AfterIend,
+    /// The chunk may appear at most once, but appears again.
+    Duplicate,
+    /// The chunk must appear before `PLTE`, but appears after it.
+    AfterPlte,
+    /// The chunk must appear after `PLTE`, but appears before it.
+    BeforePlte,
+    /// The chunk must appear before `IDAT`, but appears after it.
+    AfterIdat,
+    /// The chunk must appear after `IDAT`, but appears before it.
+    BeforeIdat,
+    /// The `IDAT` chunk is separated from the previous `IDAT` chunk.
+    NonConsecutiveIdat,
+}
+
+impl fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            ViolationKind::MissingIhdr => "first chunk must be IHDR",
+            ViolationKind::MissingIdat => "at least one IDAT chunk is required",
+            ViolationKind::MissingIend => "last chunk must be IEND",
+            ViolationKind::AfterIend => "chunk must not appear after IEND",
+            ViolationKind::Duplicate => "chunk must not appear more than once",
+            ViolationKind::AfterPlte => "chunk must appear before PLTE",
+            ViolationKind::BeforePlte => "chunk must appear after PLTE",
+            ViolationKind::AfterIdat => "chunk must appear before IDAT",
+            ViolationKind::BeforeIdat => "chunk must appear after IDAT",
+            ViolationKind::NonConsecutiveIdat => "IDAT chunks must be consecutive",
+        };
+
+        write!(f, "{description}")
+    }
+}
+
+/// Violation of the chunk ordering rules by a chunk within a PNG datastream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Violation {
+    index: usize,
+    chunk_type: Option<ChunkType>,
+    kind: ViolationKind,
+}
+
+impl Violation {
+    /// Creates a violation of the rule by the chunk at the index, or by a
+    /// missing chunk if the chunk type is `None`.
+    pub(crate) const fn new(
+        index: usize,
+        chunk_type: Option<ChunkType>,
+        kind: ViolationKind,
+    ) -> Self {
+        Self {
+            index,
+            chunk_type,
+            kind,
+        }
+    }
+
+    /// Returns the index of the offending chunk, or the number of chunks if
+    /// the violation concerns a missing chunk.
+    #[inline]
+    pub const fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the `ChunkType` of the offending chunk, or `None` if the
+    /// violation concerns a missing chunk.
+    #[inline]
+    pub const fn chunk_type(&self) -> Option<ChunkType> {
+        self.chunk_type
+    }
+
+    /// Returns the rule that was violated.
+    #[inline]
+    pub const fn kind(&self) -> ViolationKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.chunk_type {
+            Some(chunk_type) => write!(f, "chunk {} ({}): {}", self.index, chunk_type, self.kind),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+/// Incremental checker of the chunk ordering rules, fed one chunk type at a
+/// time so it can be used while streaming a PNG datastream.
+#[derive(Debug, Default)]
+pub struct OrderingChecker {
+    /// Number of chunk types checked so far.
+    count: usize,
+    /// Chunk types seen so far which may appear at most once.
+    seen: Vec<[u8; 4]>,
+    /// Indices and types of chunks which must appear after a `PLTE` chunk that
+    /// has not been seen yet.
+    awaiting_plte: Vec<(usize, ChunkType)>,
+    plte_seen: bool,
+    idat_seen: bool,
+    iend_seen: bool,
+    /// Whether the previous chunk was an `IDAT` chunk.
+    prev_idat: bool,
+    violations: Vec<Violation>,
+}
+
+impl OrderingChecker {
+    /// Creates a new ordering checker with no chunks checked.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks the next chunk type of the datastream, returning any violations
+    /// found as a result.
+    pub fn push(&mut self, chunk_type: ChunkType) -> &[Violation] {
+        let start = self.violations.len();
+        let index = self.count;
+        let bytes = chunk_type.bytes();
+        self.count += 1;
+
+        let mut violate = |kind| {
+            self.violations.push(Violation {
+                index,
+                chunk_type: Some(chunk_type),
+                kind,
+            })
+        };
+
+        if self.iend_seen {
+            violate(ViolationKind::AfterIend);
+        } else if index == 0 && &bytes != b"IHDR" {
+            violate(ViolationKind::MissingIhdr);
+        }
+
+        if SINGLE.contains(&&bytes) && self.seen.contains(&bytes) {
+            violate(ViolationKind::Duplicate);
+        }
+
+        if BEFORE_PLTE.contains(&&bytes) && self.plte_seen {
+            violate(ViolationKind::AfterPlte);
+        }
+
+        if self.idat_seen
+            && (BEFORE_IDAT.contains(&&bytes)
+                || BEFORE_PLTE.contains(&&bytes)
+                || AFTER_PLTE.contains(&&bytes))
+        {
+            violate(ViolationKind::AfterIdat);
+        }
+
+        if AFTER_IDAT.contains(&&bytes) && !self.idat_seen {
+            violate(ViolationKind::BeforeIdat);
+        }
+
+        if &bytes == b"IDAT" && self.idat_seen && !self.prev_idat {
+            violate(ViolationKind::NonConsecutiveIdat);
+        }
+
+        if AFTER_PLTE.contains(&&bytes) && !self.plte_seen && !self.idat_seen {
+            // Only a violation if a `PLTE` chunk follows.
+            self.awaiting_plte.push((index, chunk_type));
+        }
+
+        if &bytes == b"PLTE" && !self.plte_seen {
+            self.plte_seen = true;
+
+            for (index, chunk_type) in self.awaiting_plte.drain(..) {
+                self.violations.push(Violation {
+                    index,
+                    chunk_type: Some(chunk_type),
+                    kind: ViolationKind::BeforePlte,
+                });
+            }
+        }
+
+        if SINGLE.contains(&&bytes) && !self.seen.contains(&bytes) {
+            self.seen.push(bytes);
+        }
+
+        self.idat_seen |= &bytes == b"IDAT";
+        self.iend_seen |= &bytes == b"IEND";
+        self.prev_idat = &bytes == b"IDAT";
+
+        &self.violations[start..]
+    }
+
+    /// Returns all violations found so far.
+    #[inline]
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+
+    /// Checks for chunks missing from the end of the datastream, returning all
+    /// violations found.
+    pub fn finish(mut self) -> Vec<Violation> {
+        let index = self.count;
+        let mut violate = |kind| {
+            self.violations.push(Violation {
+                index,
+                chunk_type: None,
+                kind,
+            })
+        };
+
+        if index == 0 {
+            violate(ViolationKind::MissingIhdr);
+        }
+        if !self.idat_seen {
+            violate(ViolationKind::MissingIdat);
+        }
+        if !self.iend_seen {
+            violate(ViolationKind::MissingIend);
+        }
+
+        self.violations
+    }
+}
+
+/// Checks the provided chunks against the chunk ordering rules, returning all
+/// violations found.
+pub fn check_chunks(chunks: &[Chunk]) -> Vec<Violation> {
+    let mut checker = OrderingChecker::new();
+
+    for chunk in chunks {
+        checker.push(chunk.chunk_type());
+    }
+
+    checker.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn check(chunk_types: &[&str]) -> Vec<(usize, ViolationKind)> {
+        let mut checker = OrderingChecker::new();
+
+        for chunk_type in chunk_types {
+            checker.push(ChunkType::from_str(chunk_type).unwrap());
+        }
+
+        checker
+            .finish()
+            .into_iter()
+            .map(|v| (v.index(), v.kind()))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_ordering() {
+        let violations = check(&[
+            "IHDR", "gAMA", "PLTE", "tRNS", "pHYs", "tEXt", "IDAT", "IDAT", "tEXt", "ruSt", "IEND",
+        ]);
+        assert!(violations.is_empty());
+    }
+
+    #[test]
+    fn test_missing_chunks() {
+        let violations = check(&[]);
+        assert_eq!(
+            violations,
+            [
+                (0, ViolationKind::MissingIhdr),
+                (0, ViolationKind::MissingIdat),
+                (0, ViolationKind::MissingIend),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ihdr_not_first() {
+        let violations = check(&["tEXt", "IHDR", "IDAT", "IEND"]);
+        assert_eq!(violations, [(0, ViolationKind::MissingIhdr)]);
+    }
+
+    #[test]
+    fn test_duplicate_ihdr() {
+        let violations = check(&["IHDR", "IHDR", "IDAT", "IEND"]);
+        assert_eq!(violations, [(1, ViolationKind::Duplicate)]);
+    }
+
+    #[test]
+    fn test_iend_in_middle() {
+        let violations = check(&["IHDR", "IEND", "IDAT", "IEND"]);
+        assert_eq!(
+            violations,
+            [
+                (2, ViolationKind::AfterIend),
+                (3, ViolationKind::AfterIend),
+                (3, ViolationKind::Duplicate),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_non_consecutive_idat() {
+        let violations = check(&["IHDR", "IDAT", "tEXt", "IDAT", "IEND"]);
+        assert_eq!(violations, [(3, ViolationKind::NonConsecutiveIdat)]);
+    }
+
+    #[test]
+    fn test_plte_after_idat() {
+        let violations = check(&["IHDR", "IDAT", "PLTE", "IEND"]);
+        assert_eq!(violations, [(2, ViolationKind::AfterIdat)]);
+    }
+
+    #[test]
+    fn test_before_plte_rules() {
+        let violations = check(&["IHDR", "tRNS", "PLTE", "gAMA", "IDAT", "IEND"]);
+        assert_eq!(
+            violations,
+            [
+                (1, ViolationKind::BeforePlte),
+                (3, ViolationKind::AfterPlte)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trns_without_plte() {
+        let violations = check(&["IHDR", "tRNS", "IDAT", "IEND"]);
+        assert!(violations.is_empty());
+    }
+
+    #[test]
+    fn test_fdat_before_idat() {
+        let violations = check(&["IHDR", "acTL", "fcTL", "fdAT", "IDAT", "IEND"]);
+        assert_eq!(violations, [(3, ViolationKind::BeforeIdat)]);
+    }
+
+    #[test]
+    fn test_violation_display() {
+        let violations = check_chunks(&[
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]).unwrap(),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()).unwrap(),
+        ]);
+
+        assert_eq!(violations.len(), 1);
+        assert_eq!(
+            violations[0].to_string(),
+            "at least one IDAT chunk is required"
+        );
+    }
+}
@@ -0,0 +1,363 @@
+//! Binary payloads with their original filename and MIME type.
+//!
+//! A message is stored as its raw bytes, unless it carries a filename or MIME
+//! type, in which case it is prefixed with a small header:
+//!
+//! | Field     | Size          | Description                        |
+//! |-----------|---------------|------------------------------------|
+//! | Magic     | 4 bytes       | `pmPL`                             |
+//! | Version   | 1 byte        | Payload version (1)                |
+//! | Name len  | 1 byte        | Length of the filename, or 0       |
+//! | Filename  | Name len      | UTF-8 filename, without directories|
+//! | MIME len  | 1 byte        | Length of the MIME type, or 0      |
+//! | MIME type | MIME len      | ASCII MIME type (e.g., "image/png")|
+//!
+//! followed by the payload data. Messages without a header are decoded as
+//! payloads without a filename or MIME type.
+
+use std::path::Path;
+
+use anyhow::Result;
+
+/// Magic bytes identifying a payload header.
+const MAGIC: [u8; 4] = *b"pmPL";
+
+/// Current version of the payload header.
+const VERSION: u8 = 1;
+
+/// Maximum length of a filename or MIME type in bytes.
+const MAX_FIELD_LEN: usize = u8::MAX as usize;
+
+/// MIME type of payloads of unknown type.
+pub const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// MIME types of common file extensions.
+const MIME_TYPES: &[(&str, &str)] = &[
+    ("bin", DEFAULT_MIME_TYPE),
+    ("bmp", "image/bmp"),
+    ("csv", "text/csv"),
+    ("gif", "image/gif"),
+    ("gz", "application/gzip"),
+    ("htm", "text/html"),
+    ("html", "text/html"),
+    ("jpeg", "image/jpeg"),
+    ("jpg", "image/jpeg"),
+    ("json", "application/json"),
+    ("md", "text/markdown"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+    ("pdf", "application/pdf"),
+    ("png", "image/png"),
+    ("svg", "image/svg+xml"),
+    ("tar", "application/x-tar"),
+    ("txt", "text/plain"),
+    ("wav", "audio/wav"),
+    ("webp", "image/webp"),
+    ("xml", "application/xml"),
+    ("zip", "application/zip"),
+];
+
+/// Message payload, along with its original filename and MIME type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payload {
+    filename: Option<String>,
+    mime_type: Option<String>,
+    data: Vec<u8>,
+}
+
+impl Payload {
+    /// Creates a new payload without a filename or MIME type.
+    pub fn new(data: impl Into<Vec<u8>>) -> Self {
+        Payload {
+            filename: None,
+            mime_type: None,
+            data: data.into(),
+        }
+    }
+
+    /// Creates a new payload with the specified filename and MIME type.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the filename is empty, contains directories, or is
+    /// longer than 255 bytes, or if the MIME type is not of the form
+    /// "type/subtype" or is longer than 255 bytes.
+    pub fn with_metadata(
+        data: impl Into<Vec<u8>>,
+        filename: Option<String>,
+        mime_type: Option<String>,
+    ) -> Result<Self> {
+        if let Some(filename) = &filename {
+            check_filename(filename)?;
+        }
+
+        if let Some(mime_type) = &mime_type {
+            check_mime_type(mime_type)?;
+        }
+
+        Ok(Payload {
+            filename,
+            mime_type,
+            data: data.into(),
+        })
+    }
+
+    /// Returns the original filename of the payload, if any.
+    #[inline]
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    /// Returns the MIME type of the payload, if any.
+    #[inline]
+    pub fn mime_type(&self) -> Option<&str> {
+        self.mime_type.as_deref()
+    }
+
+    /// Returns a shared reference to the payload data.
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the payload data, consuming the payload.
+    #[inline]
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Returns `true` if the payload carries a filename or MIME type.
+    #[inline]
+    pub fn has_metadata(&self) -> bool {
+        self.filename.is_some() || self.mime_type.is_some()
+    }
+
+    /// Encodes the payload as a message.
+    ///
+    /// Payloads without a filename or MIME type are encoded as their raw
+    /// bytes, unless the data itself starts with a payload header.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        if !self.has_metadata() && !self.data.starts_with(&MAGIC) {
+            return self.data.clone();
+        }
+
+        let filename = self.filename.as_deref().unwrap_or_default();
+        let mime_type = self.mime_type.as_deref().unwrap_or_default();
+
+        let mut bytes = Vec::with_capacity(
+            MAGIC.len() + 3 + filename.len() + mime_type.len() + self.data.len(),
+        );
+        bytes.extend(MAGIC);
+        bytes.push(VERSION);
+        // Lengths are checked when constructed.
+        bytes.push(filename.len() as u8);
+        bytes.extend(filename.as_bytes());
+        bytes.push(mime_type.len() as u8);
+        bytes.extend(mime_type.as_bytes());
+        bytes.extend(&self.data);
+
+        bytes
+    }
+
+    /// Decodes a message as a payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message starts with a payload header which is
+    /// malformed or uses an unsupported version.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        if !bytes.starts_with(&MAGIC) {
+            return Ok(Payload::new(bytes));
+        }
+
+        let mut rest = &bytes[MAGIC.len()..];
+
+        let (&version, tail) = rest
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("invalid payload header: missing version"))?;
+        anyhow::ensure!(
+            version == VERSION,
+            "unsupported payload header version: {version}"
+        );
+        rest = tail;
+
+        let filename = read_field(&mut rest, "filename")?;
+        let mime_type = read_field(&mut rest, "MIME type")?;
+
+        let filename = (!filename.is_empty()).then(|| filename.to_owned());
+        let mime_type = (!mime_type.is_empty()).then(|| mime_type.to_owned());
+        let data = rest.to_vec();
+
+        Payload::with_metadata(data, filename, mime_type)
+            .map_err(|e| anyhow::anyhow!("invalid payload header: {e}"))
+    }
+}
+
+/// Reads a length-prefixed UTF-8 field of the payload header.
+fn read_field<'a>(rest: &mut &'a [u8], name: &str) -> Result<&'a str> {
+    let (&len, tail) = rest
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("invalid payload header: missing {name} length"))?;
+
+    anyhow::ensure!(
+        tail.len() >= len as usize,
+        "invalid payload header: {name} is truncated"
+    );
+
+    let (field, tail) = tail.split_at(len as usize);
+    *rest = tail;
+
+    std::str::from_utf8(field)
+        .map_err(|_| anyhow::anyhow!("invalid payload header: {name} is not valid UTF-8"))
+}
+
+/// Returns the MIME type of a file given its name, based on its extension.
+pub fn guess_mime_type(filename: &str) -> &'static str {
+    let extension = Path::new(filename)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+
+    extension
+        .and_then(|ext| {
+            MIME_TYPES
+                .iter()
+                .find(|(known, _)| *known == ext)
+                .map(|(_, mime_type)| *mime_type)
+        })
+        .unwrap_or(DEFAULT_MIME_TYPE)
+}
+
+/// Checks that a filename can be safely restored, without escaping the
+/// directory it is extracted to.
+fn check_filename(filename: &str) -> Result<()> {
+    anyhow::ensure!(
+        !filename.is_empty() && filename.len() <= MAX_FIELD_LEN,
+        "invalid filename '{filename}': must be 1-{MAX_FIELD_LEN} bytes"
+    );
+    anyhow::ensure!(
+        filename != "."
+            && filename != ".."
+            && !filename.contains(['/', '\\', '\0'])
+            && !filename.chars().any(char::is_control),
+        "invalid filename '{filename}': must not contain directories or control characters"
+    );
+
+    Ok(())
+}
+
+/// Checks that a MIME type is of the form "type/subtype".
+fn check_mime_type(mime_type: &str) -> Result<()> {
+    let valid = mime_type.len() <= MAX_FIELD_LEN
+        && mime_type.split_once('/').is_some_and(|(ty, subtype)| {
+            let token = |s: &str| {
+                !s.is_empty()
+                    && s.bytes()
+                        .all(|b| b.is_ascii_alphanumeric() || b"!#$&-^_.+".contains(&b))
+            };
+
+            token(ty) && token(subtype)
+        });
+
+    anyhow::ensure!(
+        valid,
+        "invalid MIME type '{mime_type}': must be of the form \"type/subtype\" (e.g., \"image/png\")"
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_without_metadata_is_raw() {
+        let payload = Payload::new(b"\x00\xffbinary".to_vec());
+
+        assert_eq!(payload.to_bytes(), b"\x00\xffbinary");
+        assert_eq!(Payload::from_bytes(payload.to_bytes()).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_payload_roundtrip() {
+        let payload = Payload::with_metadata(
+            b"\x89PNG\r\n\x1a\n".to_vec(),
+            Some("image.png".to_owned()),
+            Some("image/png".to_owned()),
+        )
+        .unwrap();
+
+        let bytes = payload.to_bytes();
+        assert!(bytes.starts_with(b"pmPL\x01\x09image.png\x09image/png"));
+
+        let decoded = Payload::from_bytes(bytes).unwrap();
+        assert_eq!(decoded, payload);
+        assert_eq!(decoded.filename(), Some("image.png"));
+        assert_eq!(decoded.mime_type(), Some("image/png"));
+        assert_eq!(decoded.data(), b"\x89PNG\r\n\x1a\n");
+    }
+
+    #[test]
+    fn test_payload_mime_type_only() {
+        let payload =
+            Payload::with_metadata(b"data".to_vec(), None, Some(DEFAULT_MIME_TYPE.to_owned()))
+                .unwrap();
+
+        assert_eq!(Payload::from_bytes(payload.to_bytes()).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_payload_data_starting_with_magic() {
+        let payload = Payload::new(b"pmPL\x01 looks like a header".to_vec());
+
+        assert_ne!(payload.to_bytes(), payload.data());
+        assert_eq!(Payload::from_bytes(payload.to_bytes()).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_payload_truncated_header() {
+        let payload = Payload::with_metadata(Vec::new(), Some("a.txt".to_owned()), None).unwrap();
+        let bytes = payload.to_bytes();
+
+        for len in MAGIC.len()..bytes.len() - 1 {
+            assert!(Payload::from_bytes(bytes[..len].to_vec()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_payload_unsupported_version() {
+        assert!(Payload::from_bytes(b"pmPL\x02\x00\x00".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_invalid_filename() {
+        for filename in ["", ".", "..", "../etc/passwd", "dir/file", "a\\b", "a\nb"] {
+            assert!(
+                Payload::with_metadata(Vec::new(), Some(filename.to_owned()), None).is_err(),
+                "{filename:?}"
+            );
+        }
+
+        assert!(Payload::with_metadata(Vec::new(), Some("a".repeat(256)), None).is_err());
+        assert!(Payload::from_bytes(b"pmPL\x01\x02..\x00".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_invalid_mime_type() {
+        for mime_type in ["", "text", "text/", "/plain", "text/pl ain"] {
+            assert!(
+                Payload::with_metadata(Vec::new(), None, Some(mime_type.to_owned())).is_err(),
+                "{mime_type:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_guess_mime_type() {
+        assert_eq!(guess_mime_type("notes.TXT"), "text/plain");
+        assert_eq!(guess_mime_type("photo.jpeg"), "image/jpeg");
+        assert_eq!(guess_mime_type("archive"), DEFAULT_MIME_TYPE);
+        assert_eq!(guess_mime_type("data.unknown"), DEFAULT_MIME_TYPE);
+    }
+}
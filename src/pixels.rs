@@ -0,0 +1,486 @@
+//! Decoding of the image data into RGBA pixels.
+//!
+//! Every combination of color type and bit depth allowed in the `IHDR` chunk
+//! is normalized to red, green, blue, and alpha samples, so that images can be
+//! compared or inspected without knowing how their pixels are stored:
+//!
+//! - Greyscale samples are copied to all three color channels, scaled from bit
+//!   depths of 1, 2, and 4 to the full range of 8 bits.
+//! - Palette indices are looked up in the `PLTE` chunk, with the alpha of each
+//!   entry taken from the `tRNS` chunk, or fully opaque if it holds none.
+//! - Greyscale and truecolor pixels matching the color of a `tRNS` chunk are
+//!   fully transparent, and all others are fully opaque.
+//!
+//! Images with a bit depth of 16 keep their precision as [`Pixels::Rgba16`],
+//! while all others decode to [`Pixels::Rgba8`].
+
+use anyhow::{Context, Result};
+
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::PNG;
+
+/// Decoded pixels of an image, in row-major order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pixels {
+    /// Pixels with 8-bit red, green, blue, and alpha samples.
+    Rgba8(Vec<[u8; 4]>),
+    /// Pixels with 16-bit red, green, blue, and alpha samples.
+    Rgba16(Vec<[u16; 4]>),
+}
+
+/// Image decoded into RGBA pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Pixels,
+}
+
+impl Image {
+    /// Returns the width of the image in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the image in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the pixels of the image, in row-major order.
+    pub fn pixels(&self) -> &Pixels {
+        &self.pixels
+    }
+
+    /// Consumes the image, returning its pixels.
+    pub fn into_pixels(self) -> Pixels {
+        self.pixels
+    }
+
+    /// Returns the pixels of the image with 8-bit samples, rounding 16-bit
+    /// samples to the nearest 8-bit value, so that images of any bit depth
+    /// can be compared.
+    pub fn to_rgba8(&self) -> Vec<[u8; 4]> {
+        match &self.pixels {
+            Pixels::Rgba8(pixels) => pixels.clone(),
+            Pixels::Rgba16(pixels) => pixels
+                .iter()
+                .map(|pixel| pixel.map(|sample| ((sample as u32 * 255 + 32767) / 65535) as u8))
+                .collect(),
+        }
+    }
+}
+
+impl PNG {
+    /// Decodes the image data of the PNG into RGBA pixels, applying the
+    /// palette and transparency of its `PLTE` and `tRNS` chunks.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the image data could not be decoded, if the `PLTE`
+    /// or `tRNS` chunk is invalid for the image, or if a pixel references a
+    /// palette entry which does not exist.
+    pub fn decode_pixels(&self) -> Result<Image> {
+        let ihdr = self.ihdr()?;
+        let raster = self.scanlines()?;
+
+        let depth = ihdr.bit_depth() as usize;
+        let channels = ihdr.color_type().channels();
+        let row_len = ihdr.scanline_len(ihdr.width());
+        let max = ((1u32 << depth) - 1) as u16;
+
+        // Samples of less than 8 bits are scaled to the full 8-bit range.
+        let scale = |sample: u16| match depth {
+            1 | 2 | 4 => (sample as u32 * 255 / max as u32) as u16,
+            _ => sample,
+        };
+        let opaque = scale(max);
+
+        let palette = match ihdr.color_type() {
+            ColorType::Indexed => Some(palette(self, &ihdr)?),
+            _ => None,
+        };
+        let key = transparent_color(self, &ihdr)?;
+
+        let mut pixels = Vec::with_capacity(ihdr.width() as usize * ihdr.height() as usize);
+        let mut samples = [0u16; 4];
+
+        for row in raster.chunks_exact(row_len.max(1)) {
+            for x in 0..ihdr.width() as usize {
+                for (c, sample) in samples[..channels].iter_mut().enumerate() {
+                    *sample = read_sample(row, (x * channels + c) * depth, depth);
+                }
+
+                let alpha = |color: &[u16]| match &key {
+                    Some(key) if key[..] == *color => 0,
+                    _ => opaque,
+                };
+
+                let pixel = match ihdr.color_type() {
+                    ColorType::Grayscale => {
+                        let v = scale(samples[0]);
+                        [v, v, v, alpha(&samples[..1])]
+                    }
+                    ColorType::Truecolor => {
+                        let [r, g, b, _] = samples;
+                        [r, g, b, alpha(&samples[..3])]
+                    }
+                    ColorType::Indexed => {
+                        let palette = palette.as_deref().unwrap_or_default();
+                        let index = samples[0] as usize;
+
+                        let entry = palette.get(index).with_context(|| {
+                            format!(
+                                "invalid image data: palette index {} exceeds the {} entries of the PLTE chunk",
+                                index,
+                                palette.len()
+                            )
+                        })?;
+                        entry.map(u16::from)
+                    }
+                    ColorType::GrayscaleAlpha => {
+                        let [v, a, _, _] = samples;
+                        [v, v, v, a]
+                    }
+                    ColorType::TruecolorAlpha => samples,
+                };
+
+                pixels.push(pixel);
+            }
+        }
+
+        let pixels = if depth == 16 {
+            Pixels::Rgba16(pixels)
+        } else {
+            Pixels::Rgba8(pixels.into_iter().map(|p| p.map(|s| s as u8)).collect())
+        };
+
+        Ok(Image {
+            width: ihdr.width(),
+            height: ihdr.height(),
+            pixels,
+        })
+    }
+}
+
+/// Returns the sample of `depth` bits starting at bit `offset` of the
+/// scanline.
+fn read_sample(row: &[u8], offset: usize, depth: usize) -> u16 {
+    let byte = offset / 8;
+
+    match depth {
+        16 => u16::from_be_bytes([row[byte], row[byte + 1]]),
+        8 => row[byte] as u16,
+        _ => {
+            let shift = 8 - depth - offset % 8;
+            ((row[byte] >> shift) & ((1 << depth) - 1)) as u16
+        }
+    }
+}
+
+/// Returns the RGBA entries of the palette of an indexed-color PNG, with the
+/// alpha of each entry taken from the `tRNS` chunk.
+fn palette(png: &PNG, ihdr: &Ihdr) -> Result<Vec<[u8; 4]>> {
+    let plte = png.chunk_by_type("PLTE");
+    ihdr.check_palette(plte)?;
+
+    // `check_palette` rejects indexed-color images without a palette.
+    let plte = plte.context("invalid PNG datastream: missing PLTE chunk")?;
+    let alphas = png
+        .chunk_by_type("tRNS")
+        .map_or(&[][..], |trns| trns.data());
+    let entries = plte.data().len() / 3;
+
+    anyhow::ensure!(
+        alphas.len() <= entries,
+        "invalid tRNS chunk: {} alpha values exceed the {} entries of the PLTE chunk",
+        alphas.len(),
+        entries
+    );
+
+    let palette = plte
+        .data()
+        .chunks_exact(3)
+        .enumerate()
+        .map(|(i, rgb)| {
+            [
+                rgb[0],
+                rgb[1],
+                rgb[2],
+                alphas.get(i).copied().unwrap_or(255),
+            ]
+        })
+        .collect();
+
+    Ok(palette)
+}
+
+/// Returns the samples of the transparent color given by the `tRNS` chunk of
+/// a greyscale or truecolor PNG, marking pixels of that color as fully
+/// transparent.
+pub(crate) fn transparent_color(png: &PNG, ihdr: &Ihdr) -> Result<Option<Vec<u16>>> {
+    let Some(trns) = png.chunk_by_type("tRNS") else {
+        return Ok(None);
+    };
+
+    let channels = match ihdr.color_type() {
+        ColorType::Grayscale => 1,
+        ColorType::Truecolor => 3,
+        _ => return Ok(None),
+    };
+
+    anyhow::ensure!(
+        trns.data().len() == channels * 2,
+        "invalid tRNS chunk: expected {} bytes for color type {}, but received {}",
+        channels * 2,
+        ihdr.color_type(),
+        trns.data().len()
+    );
+
+    let key = trns
+        .data()
+        .chunks_exact(2)
+        .map(|sample| u16::from_be_bytes([sample[0], sample[1]]))
+        .collect();
+
+    Ok(Some(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::filter::FilterStrategy;
+    use crate::idat::DeflateOptions;
+    use crate::ihdr::InterlaceMethod;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec()).unwrap()
+    }
+
+    /// Creates a PNG of the raster, with the given chunks between the `IHDR`
+    /// and `IDAT` chunks.
+    fn image_png(ihdr: Ihdr, raster: &[u8], chunks: &[(&str, &[u8])]) -> PNG {
+        let mut png = PNG::from_chunks(
+            std::iter::once(chunk("IHDR", &ihdr.as_bytes()))
+                .chain(chunks.iter().map(|&(ty, data)| chunk(ty, data)))
+                .chain(std::iter::once(chunk("IEND", &[])))
+                .collect(),
+        );
+        png.set_scanlines(
+            raster,
+            FilterStrategy::default(),
+            &DeflateOptions::default(),
+        )
+        .unwrap();
+
+        png
+    }
+
+    fn header(width: u32, height: u32, bit_depth: u8, color_type: ColorType) -> Ihdr {
+        Ihdr::new(width, height, bit_depth, color_type, InterlaceMethod::None).unwrap()
+    }
+
+    fn rgba8(png: &PNG) -> Vec<[u8; 4]> {
+        match png.decode_pixels().unwrap().into_pixels() {
+            Pixels::Rgba8(pixels) => pixels,
+            Pixels::Rgba16(_) => panic!("expected 8-bit pixels"),
+        }
+    }
+
+    fn rgba16(png: &PNG) -> Vec<[u16; 4]> {
+        match png.decode_pixels().unwrap().into_pixels() {
+            Pixels::Rgba16(pixels) => pixels,
+            Pixels::Rgba8(_) => panic!("expected 16-bit pixels"),
+        }
+    }
+
+    fn grey(v: u8) -> [u8; 4] {
+        [v, v, v, 255]
+    }
+
+    #[test]
+    fn test_decode_low_bit_depth_grayscale() {
+        let png = image_png(
+            header(9, 1, 1, ColorType::Grayscale),
+            &[0b1010_0001, 0b1000_0000],
+            &[],
+        );
+        let expected = [255, 0, 255, 0, 0, 0, 0, 255, 255].map(grey);
+        assert_eq!(rgba8(&png), expected);
+
+        let png = image_png(
+            header(5, 1, 2, ColorType::Grayscale),
+            &[0b0001_1011, 0b1100_0000],
+            &[],
+        );
+        assert_eq!(rgba8(&png), [0, 85, 170, 255, 255].map(grey));
+
+        let png = image_png(header(3, 1, 4, ColorType::Grayscale), &[0x0f, 0x70], &[]);
+        assert_eq!(rgba8(&png), [0, 255, 119].map(grey));
+    }
+
+    #[test]
+    fn test_decode_grayscale_transparency() {
+        // The transparent color is compared before scaling to 8 bits.
+        let png = image_png(
+            header(4, 1, 2, ColorType::Grayscale),
+            &[0b0001_1011],
+            &[("tRNS", &[0, 2])],
+        );
+        assert_eq!(
+            rgba8(&png),
+            [grey(0), grey(85), [170, 170, 170, 0], grey(255)]
+        );
+
+        let png = image_png(
+            header(2, 1, 16, ColorType::Grayscale),
+            &[0x12, 0x34, 0xab, 0xcd],
+            &[("tRNS", &[0xab, 0xcd])],
+        );
+        assert_eq!(
+            rgba16(&png),
+            [
+                [0x1234, 0x1234, 0x1234, 0xffff],
+                [0xabcd, 0xabcd, 0xabcd, 0]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_indexed() {
+        let plte = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let png = image_png(
+            header(4, 1, 2, ColorType::Indexed),
+            &[0b0001_1000],
+            &[("PLTE", &plte), ("tRNS", &[128])],
+        );
+        assert_eq!(
+            rgba8(&png),
+            [
+                [255, 0, 0, 128],
+                [0, 255, 0, 255],
+                [0, 0, 255, 255],
+                [255, 0, 0, 128]
+            ]
+        );
+
+        let png = image_png(
+            header(2, 1, 8, ColorType::Indexed),
+            &[0, 3],
+            &[("PLTE", &plte)],
+        );
+        assert!(png.decode_pixels().is_err());
+
+        let png = image_png(
+            header(1, 1, 8, ColorType::Indexed),
+            &[0],
+            &[("PLTE", &plte), ("tRNS", &[0, 0, 0, 0])],
+        );
+        assert!(png.decode_pixels().is_err());
+
+        let png = image_png(header(1, 1, 8, ColorType::Indexed), &[0], &[]);
+        assert!(png.decode_pixels().is_err());
+    }
+
+    #[test]
+    fn test_decode_truecolor() {
+        let png = image_png(
+            header(2, 1, 8, ColorType::Truecolor),
+            &[1, 2, 3, 4, 5, 6],
+            &[("tRNS", &[0, 4, 0, 5, 0, 6])],
+        );
+        assert_eq!(rgba8(&png), [[1, 2, 3, 255], [4, 5, 6, 0]]);
+
+        let png = image_png(
+            header(1, 1, 16, ColorType::Truecolor),
+            &[1, 2, 3, 4, 5, 6],
+            &[("tRNS", &[0, 4, 0, 5, 0, 6])],
+        );
+        assert_eq!(rgba16(&png), [[0x0102, 0x0304, 0x0506, 0xffff]]);
+
+        let png = image_png(
+            header(1, 1, 8, ColorType::Truecolor),
+            &[1, 2, 3],
+            &[("tRNS", &[0, 1])],
+        );
+        assert!(png.decode_pixels().is_err());
+    }
+
+    #[test]
+    fn test_decode_alpha() {
+        let png = image_png(
+            header(2, 1, 8, ColorType::GrayscaleAlpha),
+            &[10, 20, 30, 40],
+            &[],
+        );
+        assert_eq!(rgba8(&png), [[10, 10, 10, 20], [30, 30, 30, 40]]);
+
+        let png = image_png(
+            header(1, 1, 16, ColorType::GrayscaleAlpha),
+            &[1, 2, 3, 4],
+            &[],
+        );
+        assert_eq!(rgba16(&png), [[0x0102, 0x0102, 0x0102, 0x0304]]);
+
+        let png = image_png(
+            header(1, 1, 8, ColorType::TruecolorAlpha),
+            &[1, 2, 3, 4],
+            &[],
+        );
+        assert_eq!(rgba8(&png), [[1, 2, 3, 4]]);
+
+        let png = image_png(
+            header(1, 1, 16, ColorType::TruecolorAlpha),
+            &[1, 2, 3, 4, 5, 6, 7, 8],
+            &[],
+        );
+        assert_eq!(rgba16(&png), [[0x0102, 0x0304, 0x0506, 0x0708]]);
+    }
+
+    #[test]
+    fn test_decode_interlaced() {
+        let ihdr = header(13, 11, 4, ColorType::Grayscale);
+        let raster: Vec<u8> = (0..ihdr.scanline_len(13) * 11)
+            .map(|i| (i * 37 % 256) as u8)
+            .map(|b| b & 0xf0)
+            .collect();
+
+        let mut png = image_png(ihdr, &raster, &[]);
+        let expected = png.decode_pixels().unwrap();
+        assert_eq!((expected.width(), expected.height()), (13, 11));
+
+        png.set_interlace_method(
+            InterlaceMethod::Adam7,
+            FilterStrategy::default(),
+            &DeflateOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(png.decode_pixels().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_to_rgba8() {
+        let png = image_png(
+            header(1, 2, 16, ColorType::TruecolorAlpha),
+            &[
+                0, 0, 0x80, 0x7f, 0xff, 0xff, 0x01, 0x00, 0, 0x80, 0x12, 0x34, 0, 0, 0, 0,
+            ],
+            &[],
+        );
+        assert_eq!(
+            png.decode_pixels().unwrap().to_rgba8(),
+            [[0, 128, 255, 1], [0, 18, 0, 0]]
+        );
+
+        let png = image_png(
+            header(1, 1, 8, ColorType::TruecolorAlpha),
+            &[1, 2, 3, 4],
+            &[],
+        );
+        assert_eq!(png.decode_pixels().unwrap().to_rgba8(), [[1, 2, 3, 4]]);
+    }
+}
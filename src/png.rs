@@ -10,14 +10,19 @@
 //! are used for storing metadata or hints about how to interpret the image.
 
 use std::convert::TryFrom;
+use std::io::Read;
 use std::{fmt, result};
 
 use crate::chunk::Chunk;
+use crate::error::{Error, Result};
+use crate::ihdr::Ihdr;
+use crate::ordering::{self, OrderingChecker, Validation, Violation, ViolationKind};
+use crate::reader::ChunkReader;
 
 /// PNG (Portable Network Graphics) datastream.
 #[derive(Debug)]
 #[allow(clippy::upper_case_acronyms)]
-pub(crate) struct PNG {
+pub struct PNG {
     chunks: Vec<Chunk>,
 }
 
@@ -25,25 +30,124 @@ impl PNG {
     /// PNG signature indicating the remainder of the datastream contains a
     /// single PNG image, consisting of a series of chunks beginning with an
     /// `IHDR` chunk and ending with an `IEND` chunk.
-    const MAGIC: [u8; 8] = [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
+    pub const MAGIC: [u8; 8] = [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
 
     /// Creates a new PNG with the specified chunks.
+    ///
+    /// The chunks are not checked against the chunk ordering rules; use
+    /// [`PNG::try_from`] to construct a PNG from chunks which must form a
+    /// valid datastream.
     #[inline]
-    pub(crate) const fn from_chunks(chunks: Vec<Chunk>) -> Self {
-        // TODO: Ensure the chunks form a valid PNG (`TryFrom`).
+    pub const fn from_chunks(chunks: Vec<Chunk>) -> Self {
         Self { chunks }
     }
 
+    /// Reads a PNG datastream from the provided reader, one chunk at a time.
+    ///
+    /// Unlike [`PNG::try_from`], the datastream does not need to be buffered
+    /// in memory before it is parsed. Violations of the chunk ordering rules
+    /// are tolerated, and can be retrieved with [`PNG::violations`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the datastream could not be read, or if it is not a
+    /// valid PNG datastream.
+    #[inline]
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
+        Self::from_reader_with(reader, Validation::Lenient)
+    }
+
+    /// Reads a PNG datastream from the provided reader, one chunk at a time,
+    /// handling violations of the chunk ordering rules as specified.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the datastream could not be read, if it is not a
+    /// valid PNG datastream, or [`Error::Ordering`] if it violates the chunk
+    /// ordering rules and `validation` is [`Validation::Strict`]. A missing
+    /// `IHDR` chunk is always an [`Error::Ordering`].
+    pub fn from_reader_with<R: Read>(reader: R, validation: Validation) -> Result<Self> {
+        let mut reader = ChunkReader::new(reader)?;
+        let mut checker = OrderingChecker::new();
+        let mut chunks: Vec<Chunk> = Vec::with_capacity(3);
+        let mut ihdr = None;
+
+        while let Some(chunk) = reader.next_chunk()? {
+            let violations = checker.push(chunk.chunk_type());
+
+            if chunks.is_empty() {
+                if let Some(violation) = violations
+                    .iter()
+                    .find(|v| v.kind() == ViolationKind::MissingIhdr)
+                {
+                    return Err(Error::Ordering(*violation));
+                }
+
+                ihdr = Some(Self::parse_ihdr(&chunk)?);
+            }
+
+            if let (Validation::Strict, Some(violation)) = (validation, violations.first()) {
+                return Err(Error::Ordering(*violation));
+            }
+
+            chunks.push(chunk);
+        }
+
+        let Some(ihdr) = ihdr else {
+            return Err(Error::Ordering(Violation::new(
+                0,
+                None,
+                ViolationKind::MissingIhdr,
+            )));
+        };
+
+        // A datastream ending on a chunk boundary without an `IEND` chunk has
+        // been truncated.
+        if !chunks.iter().any(|c| c.chunk_type().bytes() == *b"IEND") {
+            return Err(Error::Truncated {
+                offset: reader.offset(),
+                index: chunks.len(),
+            });
+        }
+
+        if let (Validation::Strict, Some(violation)) = (validation, checker.finish().first()) {
+            return Err(Error::Ordering(*violation));
+        }
+
+        Self::check_palette(&ihdr, &chunks)?;
+
+        Ok(PNG::from_chunks(chunks))
+    }
+
     /// Appends the provided chunk to the PNG.
+    ///
+    /// The chunk is inserted before the `IEND` chunk, or after the last chunk
+    /// if there is none.
     #[inline]
-    pub(crate) fn append_chunk(&mut self, chunk: Chunk) {
-        // Need to insert before the `IEND` chunk.
-        self.chunks.insert(self.chunks.len() - 1, chunk)
+    pub fn append_chunk(&mut self, chunk: Chunk) {
+        let idx = self
+            .chunks
+            .iter()
+            .position(|c| c.chunk_type().bytes() == *b"IEND")
+            .unwrap_or(self.chunks.len());
+
+        self.chunks.insert(idx, chunk)
+    }
+
+    /// Inserts the provided chunk into the PNG at the given index, shifting
+    /// all chunks after it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the number of chunks.
+    #[inline]
+    pub fn insert_chunk(&mut self, index: usize, chunk: Chunk) {
+        self.chunks.insert(index, chunk)
     }
 
     /// Removes the first occurrence of the chunk with a matching chunk type
     /// from the PNG and returns it, or `None` if it could not be found.
-    pub(crate) fn remove_chunk(&mut self, chunk_type: &str) -> Option<Chunk> {
+    pub fn remove_chunk(&mut self, chunk_type: &str) -> Option<Chunk> {
         // Chunk type must be a 4 byte sequence.
         if chunk_type.len() != 4 {
             return None;
@@ -61,21 +165,49 @@ impl PNG {
         }
     }
 
+    /// Removes the chunk at the given index from the PNG and returns it, or
+    /// `None` if the index is out of bounds.
+    pub fn remove_chunk_at(&mut self, index: usize) -> Option<Chunk> {
+        if index < self.chunks.len() {
+            // Need to keep the ordering of chunks.
+            Some(self.chunks.remove(index))
+        } else {
+            None
+        }
+    }
+
     /// Returns the header of the PNG.
     #[inline]
-    pub(crate) const fn header(&self) -> [u8; 8] {
+    pub const fn header(&self) -> [u8; 8] {
         Self::MAGIC
     }
 
+    /// Returns the decoded image header of the PNG.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the first chunk is not a valid `IHDR` chunk, which
+    /// can only occur for PNGs created with [`PNG::from_chunks`].
+    pub fn ihdr(&self) -> Result<Ihdr> {
+        match self.chunks.first() {
+            Some(chunk) if chunk.chunk_type().bytes() == *b"IHDR" => Self::parse_ihdr(chunk),
+            first => Err(Error::Ordering(Violation::new(
+                0,
+                first.map(Chunk::chunk_type),
+                ViolationKind::MissingIhdr,
+            ))),
+        }
+    }
+
     /// Returns a shared reference to the chunks of the PNG.
     #[inline]
-    pub(crate) fn chunks(&self) -> &[Chunk] {
+    pub fn chunks(&self) -> &[Chunk] {
         &self.chunks
     }
 
     /// Returns the first occurrence of the chunk with a matching chunk type
     /// from the PNG, or `None` if it could not be found.
-    pub(crate) fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
         // Chunk type must be a 4 byte sequence.
         if chunk_type.len() != 4 {
             return None;
@@ -86,9 +218,23 @@ impl PNG {
             .find(|c| c.chunk_type().bytes() == chunk_type.as_bytes())
     }
 
+    /// Returns all chunks with a matching chunk type from the PNG, in the order
+    /// they appear in the datastream.
+    pub fn chunks_by_type<'a>(&'a self, chunk_type: &'a str) -> impl Iterator<Item = &'a Chunk> {
+        self.chunks
+            .iter()
+            .filter(move |c| c.chunk_type().bytes() == chunk_type.as_bytes())
+    }
+
+    /// Returns all violations of the chunk ordering rules by the chunks of the
+    /// PNG.
+    pub fn violations(&self) -> Vec<Violation> {
+        ordering::check_chunks(self.chunks())
+    }
+
     /// Returns the memory representation of the PNG datastream as a byte array
     /// in big-endian (network) byte order.
-    pub(crate) fn as_bytes(&self) -> Vec<u8> {
+    pub fn as_bytes(&self) -> Vec<u8> {
         // Minimum PNG datastream size is 64 bytes:
         let mut bytes = Vec::with_capacity(64);
 
@@ -101,92 +247,53 @@ impl PNG {
 
         bytes
     }
-}
-
-impl TryFrom<&[u8]> for PNG {
-    type Error = anyhow::Error;
 
-    fn try_from(bytes: &[u8]) -> result::Result<Self, Self::Error> {
-        // TODO: Check ordering of chunks when decoding.
-
-        // Minimum PNG datastream size is 64 bytes, based on the following:
-        //
-        // - 8 bytes: PNG signature
-        //
-        // - 25 bytes: IHDR chunk (13 bytes of data + 12 bytes for length, type,
-        // and CRC)
-        //
-        // - 19 bytes: IDAT chunk (at least 7 bytes of compressed data + 12
-        // bytes for length, type, and CRC)
-        //
-        // Deflate-compressed datastreams within PNG are stored in the zlib
-        // format, which has the structure:
-        //
-        // - zlib compression method/flags code 	1 byte
-        // - Additional flags/check bits 	        1 byte
-        // - Compressed data blocks 	            n bytes (at least 1 byte)
-        // - Check value 	                        4 bytes
-        //
-        // - 12 bytes: IEND chunk (no data, just length, type, and CRC)
-        let min = 64;
-        let len = bytes.len();
-
-        anyhow::ensure!(
-            len >= min,
-            "invalid PNG datastream: input must be at least 59 bytes, but received: {}",
-            len
-        );
-
-        let mut offset = 0;
-
-        let header: [u8; 8] = [
-            bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
-        ];
-        anyhow::ensure!(
-            header == Self::MAGIC,
-            "invalid PNG datastream: malformed PNG signature: {:?}",
-            header
-        );
-        offset += 8;
+    /// Decodes the `IHDR` chunk.
+    fn parse_ihdr(chunk: &Chunk) -> Result<Ihdr> {
+        Ihdr::try_from(chunk).map_err(|err| Error::InvalidChunk {
+            chunk_type: chunk.chunk_type(),
+            source: err.into(),
+        })
+    }
 
-        let mut chunks: Vec<Chunk> = Vec::with_capacity(3);
+    /// Checks the `PLTE` chunk of the provided chunks, if any, against the
+    /// image header.
+    fn check_palette(ihdr: &Ihdr, chunks: &[Chunk]) -> Result<()> {
+        let plte = chunks.iter().find(|c| c.chunk_type().bytes() == *b"PLTE");
 
-        // 25-byte IHDR chunk.
-        let ihdr = Chunk::try_from(&bytes[offset..offset + 25])?;
-        anyhow::ensure!(
-            ihdr.chunk_type().bytes() == *b"IHDR",
-            "invalid PNG datastream: missing IHDR chunk"
-        );
-        anyhow::ensure!(
-            ihdr.data().len() == 13,
-            "invalid PNG datastream: malformed IHDR chunk: {}",
-            ihdr
-        );
-        offset += 25;
+        ihdr.check_palette(plte).map_err(|err| Error::InvalidChunk {
+            // A missing palette is reported against the header requiring it.
+            chunk_type: plte.unwrap_or(&chunks[0]).chunk_type(),
+            source: err.into(),
+        })
+    }
+}
 
-        chunks.push(ihdr);
+impl TryFrom<&[u8]> for PNG {
+    type Error = Error;
 
-        // TODO: Check color_type of IHDR to determine whether a PLTE chunk is
-        // expected.
-        // let color_type = ihdr.data()[9];
+    fn try_from(bytes: &[u8]) -> result::Result<Self, Self::Error> {
+        // Every read from the datastream is bounds-checked by the chunk reader,
+        // so truncated input and hostile length fields are reported as errors
+        // along with the byte offset and index of the offending chunk.
+        PNG::from_reader(bytes)
+    }
+}
 
-        while offset < len {
-            // Length of the chunk data (first 4 bytes of each chunk).
-            let data_len = u32::from_be_bytes([
-                bytes[offset],
-                bytes[offset + 1],
-                bytes[offset + 2],
-                bytes[offset + 3],
-            ]) as usize;
+impl TryFrom<Vec<Chunk>> for PNG {
+    type Error = Error;
 
-            // Chunk Size = 4 (len) + 4 (type) + data_len + 4 (CRC)
-            let chunk_size = data_len + 12;
-            let chunk = Chunk::try_from(&bytes[offset..offset + chunk_size])?;
+    /// Creates a new PNG with the specified chunks, ensuring they satisfy the
+    /// chunk ordering rules.
+    fn try_from(chunks: Vec<Chunk>) -> result::Result<Self, Self::Error> {
+        if let Some(violation) = ordering::check_chunks(&chunks).first() {
+            return Err(Error::Ordering(*violation));
+        }
 
-            chunks.push(chunk);
+        // The first chunk is an `IHDR` chunk, as checked above.
+        let ihdr = Self::parse_ihdr(&chunks[0])?;
 
-            offset += chunk_size;
-        }
+        Self::check_palette(&ihdr, &chunks)?;
 
         Ok(PNG::from_chunks(chunks))
     }
@@ -206,8 +313,7 @@ mod tests {
     use super::*;
     use crate::chunk::Chunk;
     use crate::chunk_type::ChunkType;
-
-    use anyhow::Result;
+    use crate::ihdr::ColorType;
 
     fn chunk_from_parts(chunk_type: &str, data: &[u8]) -> Result<Chunk> {
         let chunk_type = ChunkType::from_str(chunk_type)?;
@@ -295,7 +401,7 @@ mod tests {
 
         let png = PNG::try_from(bytes.as_ref());
 
-        assert!(png.is_err());
+        assert!(matches!(png, Err(Error::InvalidSignature { .. })));
     }
 
     #[test]
@@ -320,6 +426,184 @@ mod tests {
         assert!(png.is_err());
     }
 
+    #[test]
+    fn test_png_truncated_never_panics() {
+        for len in 0..PNG_FILE.len() {
+            let png = PNG::try_from(&PNG_FILE[..len]);
+            assert!(png.is_err(), "accepted PNG truncated to {len} bytes");
+        }
+    }
+
+    #[test]
+    fn test_png_truncated_reports_location() {
+        // Truncate within the data of the `IDAT` chunk (index 4).
+        let err = PNG::try_from(&PNG_FILE[..100]).unwrap_err();
+
+        assert!(
+            matches!(
+                err,
+                Error::Truncated {
+                    offset: 83,
+                    index: 4
+                }
+            ),
+            "{err:?}"
+        );
+        assert!(
+            err.to_string()
+                .starts_with("invalid PNG datastream: failed to parse chunk 4 at byte offset 83"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_png_hostile_length() {
+        let mut bytes = PNG_FILE.to_vec();
+        // Length field of the chunk following `IHDR`.
+        bytes[33..37].copy_from_slice(&u32::MAX.to_be_bytes());
+
+        let png = PNG::try_from(&bytes[..]);
+        assert!(png.is_err());
+    }
+
+    #[test]
+    fn test_png_strict_rejects_violations() {
+        let bytes: Vec<u8> = PNG::MAGIC
+            .iter()
+            .copied()
+            .chain(generate_chunks().iter().flat_map(|c| c.as_bytes()))
+            .collect();
+
+        // Lenient decoding reports the missing `IDAT` chunk as a violation.
+        let png = PNG::from_reader(&bytes[..]).unwrap();
+        assert_eq!(png.violations().len(), 1);
+
+        let png = PNG::from_reader_with(&bytes[..], Validation::Strict);
+        assert!(matches!(
+            png,
+            Err(Error::Ordering(violation)) if violation.kind() == ViolationKind::MissingIdat
+        ));
+    }
+
+    #[test]
+    fn test_png_strict_accepts_image_file() {
+        let png = PNG::from_reader_with(&PNG_FILE[..], Validation::Strict).unwrap();
+        assert!(png.violations().is_empty());
+    }
+
+    #[test]
+    fn test_png_try_from_chunks() {
+        let mut chunks = generate_chunks();
+        chunks.insert(1, chunk_from_parts("IDAT", b"").unwrap());
+        assert!(PNG::try_from(chunks.clone()).is_ok());
+
+        // `IDAT` chunks must be consecutive.
+        chunks.insert(3, chunk_from_parts("IDAT", b"").unwrap());
+        assert!(PNG::try_from(chunks).is_err());
+    }
+
+    #[test]
+    fn test_png_append_chunk_before_iend() {
+        let mut chunks = generate_chunks();
+        chunks.push(chunk_from_parts("AfTr", b"I am after IEND").unwrap());
+
+        let mut png = PNG::from_chunks(chunks);
+        png.append_chunk(chunk_from_parts("TeSt", b"Message").unwrap());
+
+        assert_eq!(png.chunks()[4].chunk_type().to_string(), "TeSt");
+        assert_eq!(png.chunks()[5].chunk_type().to_string(), "IEND");
+    }
+
+    #[test]
+    fn test_png_ihdr() {
+        let png = PNG::try_from(&PNG_FILE[..]).unwrap();
+        let ihdr = png.ihdr().unwrap();
+
+        assert_eq!(ihdr.width(), 50);
+        assert_eq!(ihdr.height(), 50);
+        assert_eq!(ihdr.bit_depth(), 8);
+        assert_eq!(ihdr.color_type(), ColorType::TruecolorAlpha);
+    }
+
+    #[test]
+    fn test_png_invalid_ihdr() {
+        let mut chunks = generate_chunks();
+        // Truecolor images do not allow a bit depth of 4.
+        chunks[0] = chunk_from_parts(
+            "IHDR",
+            b"\x00\x00\x00\x01\x00\x00\x00\x01\x04\x02\x00\x00\x00",
+        )
+        .unwrap();
+
+        let bytes: Vec<u8> = PNG::MAGIC
+            .iter()
+            .copied()
+            .chain(chunks.iter().flat_map(|c| c.as_bytes()))
+            .collect();
+
+        assert!(PNG::try_from(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn test_png_missing_required_plte() {
+        let mut chunks = generate_chunks();
+        // Indexed-color images require a `PLTE` chunk.
+        chunks[0] = chunk_from_parts(
+            "IHDR",
+            b"\x00\x00\x00\x01\x00\x00\x00\x01\x08\x03\x00\x00\x00",
+        )
+        .unwrap();
+
+        let bytes: Vec<u8> = PNG::MAGIC
+            .iter()
+            .copied()
+            .chain(chunks.iter().flat_map(|c| c.as_bytes()))
+            .collect();
+
+        assert!(matches!(
+            PNG::try_from(&bytes[..]),
+            Err(Error::InvalidChunk { chunk_type, .. }) if chunk_type.to_string() == "IHDR"
+        ));
+
+        chunks.insert(1, chunk_from_parts("PLTE", &[0, 0, 0]).unwrap());
+        assert!(PNG::try_from(chunks).is_err());
+    }
+
+    #[test]
+    fn test_png_missing_ihdr() {
+        let mut chunks = generate_chunks();
+        chunks.remove(0);
+
+        let bytes: Vec<u8> = PNG::MAGIC
+            .iter()
+            .copied()
+            .chain(chunks.iter().flat_map(|c| c.as_bytes()))
+            .collect();
+
+        // A missing `IHDR` chunk is an error even when decoding leniently.
+        let err = PNG::from_reader(&bytes[..]).unwrap_err();
+        assert!(
+            matches!(err, Error::Ordering(violation) if violation.kind() == ViolationKind::MissingIhdr),
+            "{err:?}"
+        );
+
+        let png = PNG::from_chunks(chunks);
+        assert!(matches!(png.ihdr(), Err(Error::Ordering(_))));
+    }
+
+    #[test]
+    fn test_png_crc_mismatch_reports_offset() {
+        let mut bytes = PNG_FILE.to_vec();
+        // Corrupt the last byte of the CRC of the `IHDR` chunk.
+        bytes[32] ^= 0xFF;
+
+        let err = PNG::try_from(&bytes[..]).unwrap_err();
+        assert!(
+            matches!(err, Error::CrcMismatch { offset: 8, index: 0, expected, computed } if expected != computed),
+            "{err:?}"
+        );
+    }
+
     #[test]
     fn test_png_list_chunks() {
         let png = generate_png();
@@ -336,6 +620,18 @@ mod tests {
         assert_eq!(chunk.to_string(), "I am the first chunk");
     }
 
+    #[test]
+    fn test_png_chunks_by_type() {
+        let mut png = generate_png();
+        png.append_chunk(chunk_from_parts("FrSt", b"I am another first chunk").unwrap());
+
+        let chunks: Vec<_> = png.chunks_by_type("FrSt").map(|c| c.to_string()).collect();
+        assert_eq!(chunks, ["I am the first chunk", "I am another first chunk"]);
+
+        assert_eq!(png.chunks_by_type("NoNe").count(), 0);
+        assert_eq!(png.chunks_by_type("FrStX").count(), 0);
+    }
+
     #[test]
     fn test_png_append_chunk() {
         let mut png = generate_png();
@@ -362,6 +658,20 @@ mod tests {
         assert!(png.is_ok());
     }
 
+    #[test]
+    fn test_png_from_reader() {
+        let png = PNG::from_reader(&PNG_FILE[..]).unwrap();
+        let expected = PNG::try_from(&PNG_FILE[..]).unwrap();
+
+        assert_eq!(png.chunks(), expected.chunks());
+    }
+
+    #[test]
+    fn test_png_from_reader_missing_ihdr() {
+        let png = PNG::from_reader(&PNG::MAGIC[..]);
+        assert!(png.is_err());
+    }
+
     #[test]
     fn test_png_as_bytes() {
         let png = PNG::try_from(&PNG_FILE[..]).unwrap();
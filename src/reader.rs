@@ -0,0 +1,486 @@
+//! Streaming access to the chunks of a PNG datastream.
+//!
+//! [`ChunkReader`] pulls one chunk at a time from any [`Read`] implementation,
+//! verifying the CRC of each chunk as its data is consumed. Chunk data that is
+//! not needed can be skipped without being buffered, so memory usage is bounded
+//! by the largest chunk that is actually kept rather than by the size of the
+//! datastream.
+
+use std::io::{self, Read};
+
+use crate::chunk::{Chunk, Crc};
+use crate::chunk_type::ChunkType;
+use crate::error::{Error, Result};
+use crate::png::PNG;
+
+/// Upper bound on the number of bytes reserved up front when reading chunk
+/// data, so a hostile length field cannot force a large allocation before any
+/// data has been read.
+const MAX_PREALLOC: usize = 64 * 1024;
+
+/// Size of the scratch buffer used when skipping chunk data.
+const SKIP_BUF_SIZE: usize = 8 * 1024;
+
+/// Length and chunk type of a chunk whose data has not yet been consumed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ChunkHeader {
+    length: u32,
+    chunk_type: ChunkType,
+}
+
+impl ChunkHeader {
+    /// Returns the length of the chunk data in bytes.
+    #[inline]
+    pub const fn length(&self) -> u32 {
+        self.length
+    }
+
+    /// Returns the `ChunkType` of the chunk.
+    #[inline]
+    pub const fn chunk_type(&self) -> ChunkType {
+        self.chunk_type
+    }
+}
+
+/// Reader yielding the chunks of a PNG datastream one at a time.
+#[derive(Debug)]
+pub struct ChunkReader<R> {
+    inner: R,
+    /// Header of the current chunk, if its data has not been consumed yet.
+    pending: Option<ChunkHeader>,
+    /// Number of bytes consumed from the datastream so far.
+    offset: u64,
+    /// Number of chunk headers read so far.
+    chunks_read: usize,
+    /// Byte offset of the start of the most recently read chunk.
+    chunk_offset: u64,
+    /// Index of the chunk being read, reported in errors.
+    index: usize,
+}
+
+impl<R: Read> ChunkReader<R> {
+    /// Creates a new chunk reader, consuming and validating the PNG signature
+    /// from the provided reader.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Truncated`] if the datastream ends within the
+    /// signature, [`Error::InvalidSignature`] if the signature is malformed,
+    /// or [`Error::Io`] if reading fails.
+    pub fn new(mut inner: R) -> Result<Self> {
+        let mut header = [0u8; 8];
+        inner
+            .read_exact(&mut header)
+            .map_err(|e| read_error(e, 0, 0))?;
+
+        if header != PNG::MAGIC {
+            return Err(Error::InvalidSignature { found: header });
+        }
+
+        Ok(ChunkReader {
+            inner,
+            pending: None,
+            offset: header.len() as u64,
+            chunks_read: 0,
+            chunk_offset: header.len() as u64,
+            index: 0,
+        })
+    }
+
+    /// Returns the number of bytes consumed from the datastream so far,
+    /// including the PNG signature.
+    #[inline]
+    pub const fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the index of the most recently read chunk, or `None` if no
+    /// chunk header has been read yet.
+    #[inline]
+    pub const fn chunk_index(&self) -> Option<usize> {
+        self.chunks_read.checked_sub(1)
+    }
+
+    /// Returns the byte offset of the start of the most recently read chunk.
+    #[inline]
+    pub const fn chunk_offset(&self) -> u64 {
+        self.chunk_offset
+    }
+
+    /// Consumes the chunk reader, returning the underlying reader.
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads the header of the next chunk, or returns `None` if the end of the
+    /// datastream has been reached.
+    ///
+    /// If the data of the previous chunk has not been consumed, it is skipped
+    /// (and its CRC verified) first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data of the previous chunk could not be
+    /// skipped, or if the header is truncated, has a length exceeding
+    /// [`i32::MAX`], or has an invalid chunk type.
+    pub fn next_header(&mut self) -> Result<Option<ChunkHeader>> {
+        if self.pending.is_some() {
+            self.skip_data()?;
+        }
+
+        self.chunk_offset = self.offset;
+        self.index = self.chunks_read;
+
+        self.read_header()
+    }
+
+    /// Reads the data and CRC of the chunk whose header was last returned by
+    /// [`ChunkReader::next_header`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no pending chunk header, the chunk data is
+    /// truncated, or the CRC does not match the chunk data.
+    pub fn read_data(&mut self) -> Result<Chunk> {
+        let header = self.take_pending()?;
+        let length = header.length() as usize;
+
+        let mut data = Vec::new();
+        data.try_reserve_exact(length.min(MAX_PREALLOC))
+            .map_err(|_| io::Error::from(io::ErrorKind::OutOfMemory))?;
+
+        (&mut self.inner)
+            .take(length as u64)
+            .read_to_end(&mut data)?;
+        self.offset += data.len() as u64;
+
+        if data.len() != length {
+            return Err(Error::Truncated {
+                offset: self.chunk_offset,
+                index: self.index,
+            });
+        }
+
+        let mut crc = Crc::new();
+        crc.update(&header.chunk_type().bytes());
+        crc.update(&data);
+
+        let crc = self.verify_crc(crc.finalize())?;
+
+        Ok(Chunk::from_verified_parts(header.chunk_type(), data, crc))
+    }
+
+    /// Skips the data of the chunk whose header was last returned by
+    /// [`ChunkReader::next_header`], verifying its CRC without buffering the
+    /// chunk data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no pending chunk header, the chunk data is
+    /// truncated, or the CRC does not match the chunk data.
+    pub fn skip_data(&mut self) -> Result<()> {
+        let header = self.take_pending()?;
+
+        let mut crc = Crc::new();
+        crc.update(&header.chunk_type().bytes());
+
+        let mut buf = [0u8; SKIP_BUF_SIZE];
+        let mut remaining = header.length() as usize;
+
+        while remaining > 0 {
+            let len = remaining.min(buf.len());
+            let n = match self.inner.read(&mut buf[..len]) {
+                Ok(0) => {
+                    return Err(Error::Truncated {
+                        offset: self.chunk_offset,
+                        index: self.index,
+                    });
+                }
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Error::Io(e)),
+            };
+
+            crc.update(&buf[..n]);
+            remaining -= n;
+            self.offset += n as u64;
+        }
+
+        self.verify_crc(crc.finalize())?;
+
+        Ok(())
+    }
+
+    /// Reads the next chunk in full, or returns `None` if the end of the
+    /// datastream has been reached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chunk is malformed or truncated.
+    pub fn next_chunk(&mut self) -> Result<Option<Chunk>> {
+        match self.next_header()? {
+            Some(_) => self.read_data().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads and parses the next chunk header.
+    fn read_header(&mut self) -> Result<Option<ChunkHeader>> {
+        let mut buf = [0u8; 8];
+        if !self.read_header_bytes(&mut buf)? {
+            return Ok(None);
+        }
+
+        let length = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        if length > i32::MAX as u32 {
+            return Err(Error::InvalidLength {
+                length,
+                offset: self.chunk_offset,
+                index: self.index,
+            });
+        }
+
+        let chunk_type = ChunkType::try_from([buf[4], buf[5], buf[6], buf[7]])?;
+
+        let header = ChunkHeader { length, chunk_type };
+        self.pending = Some(header);
+        self.chunks_read += 1;
+
+        Ok(Some(header))
+    }
+
+    /// Returns the pending chunk header, marking its data as consumed.
+    fn take_pending(&mut self) -> Result<ChunkHeader> {
+        self.pending.take().ok_or(Error::NoChunkHeader)
+    }
+
+    /// Reads the CRC of the current chunk and compares it to the computed CRC,
+    /// returning the CRC if they match.
+    fn verify_crc(&mut self, computed_crc: u32) -> Result<u32> {
+        let mut buf = [0u8; 4];
+        self.inner
+            .read_exact(&mut buf)
+            .map_err(|e| read_error(e, self.chunk_offset, self.index))?;
+        self.offset += buf.len() as u64;
+
+        let crc = u32::from_be_bytes(buf);
+
+        if crc != computed_crc {
+            return Err(Error::CrcMismatch {
+                expected: crc,
+                computed: computed_crc,
+                offset: self.chunk_offset,
+                index: self.index,
+            });
+        }
+
+        Ok(crc)
+    }
+
+    /// Fills the buffer with the bytes of the next chunk header, returning
+    /// `false` if the datastream ended cleanly before any bytes were read.
+    fn read_header_bytes(&mut self, buf: &mut [u8; 8]) -> Result<bool> {
+        let mut filled = 0;
+
+        while filled < buf.len() {
+            match self.inner.read(&mut buf[filled..]) {
+                Ok(0) if filled == 0 => return Ok(false),
+                Ok(0) => {
+                    return Err(Error::Truncated {
+                        offset: self.chunk_offset,
+                        index: self.index,
+                    });
+                }
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Error::Io(e)),
+            }
+        }
+
+        self.offset += filled as u64;
+
+        Ok(true)
+    }
+}
+
+/// Returns the error for a failed read of the signature or chunk with the
+/// index starting at the byte offset, which is truncated if the datastream
+/// ended early.
+pub(crate) fn read_error(err: io::Error, offset: u64, index: usize) -> Error {
+    match err.kind() {
+        io::ErrorKind::UnexpectedEof => Error::Truncated { offset, index },
+        _ => Error::Io(err),
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_chunk().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn chunk_from_parts(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec()).unwrap()
+    }
+
+    fn generate_bytes() -> Vec<u8> {
+        let chunks = [
+            chunk_from_parts(
+                "IHDR",
+                b"\x00\x00\x00\x01\x00\x00\x00\x01\x08\x02\x00\x00\x00",
+            ),
+            chunk_from_parts("ruSt", b"This is where your secret message will be!"),
+            chunk_from_parts("IEND", b""),
+        ];
+
+        PNG::MAGIC
+            .iter()
+            .copied()
+            .chain(chunks.iter().flat_map(|c| c.as_bytes()))
+            .collect()
+    }
+
+    #[test]
+    fn test_reader_reads_all_chunks() {
+        let bytes = generate_bytes();
+        let chunks: Vec<Chunk> = ChunkReader::new(&bytes[..])
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+
+        let types: Vec<String> = chunks.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "ruSt", "IEND"]);
+        assert_eq!(
+            chunks[1].data(),
+            b"This is where your secret message will be!"
+        );
+    }
+
+    #[test]
+    fn test_reader_skips_data() {
+        let bytes = generate_bytes();
+        let mut reader = ChunkReader::new(&bytes[..]).unwrap();
+
+        let mut found = None;
+        while let Some(header) = reader.next_header().unwrap() {
+            if header.chunk_type().to_string() == "IEND" {
+                found = Some(reader.read_data().unwrap());
+            }
+        }
+
+        assert_eq!(found.unwrap().length(), 0);
+        assert_eq!(reader.offset(), bytes.len() as u64);
+    }
+
+    #[test]
+    fn test_reader_invalid_signature() {
+        let mut bytes = generate_bytes();
+        bytes[0] = 13;
+
+        assert!(matches!(
+            ChunkReader::new(&bytes[..]),
+            Err(Error::InvalidSignature { found }) if found[0] == 13
+        ));
+        assert!(matches!(
+            ChunkReader::new(&bytes[..5]),
+            Err(Error::Truncated {
+                offset: 0,
+                index: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn test_reader_skip_detects_crc_mismatch() {
+        let mut bytes = generate_bytes();
+        // Corrupt the first byte of the `ruSt` chunk data.
+        bytes[8 + 25 + 8] ^= 0xFF;
+
+        let mut reader = ChunkReader::new(&bytes[..]).unwrap();
+        reader.next_chunk().unwrap();
+        reader.next_header().unwrap();
+
+        assert!(matches!(
+            reader.skip_data(),
+            Err(Error::CrcMismatch {
+                offset: 33,
+                index: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_reader_truncated_data() {
+        let bytes = generate_bytes();
+        let mut reader = ChunkReader::new(&bytes[..8 + 25 + 20]).unwrap();
+        reader.next_chunk().unwrap();
+
+        assert!(reader.next_chunk().is_err());
+    }
+
+    #[test]
+    fn test_reader_truncated_header() {
+        let bytes = generate_bytes();
+        let mut reader = ChunkReader::new(&bytes[..8 + 25 + 3]).unwrap();
+        reader.next_chunk().unwrap();
+
+        assert!(reader.next_header().is_err());
+    }
+
+    #[test]
+    fn test_reader_error_reports_location() {
+        let bytes = generate_bytes();
+        let mut reader = ChunkReader::new(&bytes[..8 + 25 + 20]).unwrap();
+        reader.next_chunk().unwrap();
+
+        let err = reader.next_chunk().unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::Truncated {
+                    offset: 33,
+                    index: 1
+                }
+            ),
+            "{err:?}"
+        );
+        assert_eq!(reader.chunk_index(), Some(1));
+        assert_eq!(reader.chunk_offset(), 33);
+    }
+
+    #[test]
+    fn test_reader_hostile_length() {
+        let mut bytes = generate_bytes();
+        // Claim the `ruSt` chunk holds i32::MAX bytes of data.
+        bytes[8 + 25..8 + 25 + 4].copy_from_slice(&(i32::MAX as u32).to_be_bytes());
+
+        let mut reader = ChunkReader::new(&bytes[..]).unwrap();
+        reader.next_chunk().unwrap();
+
+        assert!(matches!(
+            reader.next_chunk(),
+            Err(Error::Truncated {
+                offset: 33,
+                index: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn test_reader_read_data_without_header() {
+        let bytes = generate_bytes();
+        let mut reader = ChunkReader::new(&bytes[..]).unwrap();
+
+        assert!(matches!(reader.read_data(), Err(Error::NoChunkHeader)));
+    }
+}
@@ -0,0 +1,451 @@
+//! Ed25519 signatures over messages.
+//!
+//! A signature proves who embedded a message and that it was not altered
+//! since. It is stored in a `pmSG` chunk placed immediately after the message
+//! chunk it signs:
+//!
+//! | Field      | Size     | Description                                 |
+//! |------------|----------|---------------------------------------------|
+//! | Version    | 1 byte   | Signature version (1)                       |
+//! | Algorithm  | 1 byte   | Signature algorithm (1: Ed25519)            |
+//! | Flags      | 1 byte   | Bit 0: the signature covers the image data  |
+//! | Chunk type | 4 bytes  | Chunk type of the signed message chunk      |
+//! | Signer     | 32 bytes | Ed25519 public key of the signer            |
+//! | Signature  | 64 bytes | Ed25519 signature                           |
+//!
+//! The signature is computed over a SHA-256 digest of the flags, and the chunk
+//! type and data of the message chunk and, if the signature covers the image
+//! data, of the `IHDR` chunk data and the concatenated `IDAT` chunk data. The
+//! signature chunk type is unsafe-to-copy, so editors which modify the image
+//! discard signatures they can not preserve.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::result;
+use std::str::FromStr;
+
+use chacha20poly1305::aead::OsRng;
+use ed25519_dalek::{Signer, Verifier};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use anyhow::{Context, Result};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::PNG;
+
+/// Chunk type of a signature chunk.
+pub const SIGNATURE_CHUNK_TYPE: &str = "pmSG";
+
+/// Current version of the signature format.
+const VERSION: u8 = 1;
+
+/// Identifier of the Ed25519 signature algorithm.
+const ALGORITHM_ED25519: u8 = 1;
+
+/// Flag set if the signature covers the image data.
+const FLAG_IMAGE: u8 = 0x01;
+
+/// Domain separation string prefixed to the signed digest.
+const CONTEXT: &[u8] = b"pngme-signature-v1";
+
+const KEY_LEN: usize = ed25519_dalek::PUBLIC_KEY_LENGTH;
+const SIGNATURE_LEN: usize = ed25519_dalek::SIGNATURE_LENGTH;
+
+/// Length of the signature chunk data in bytes.
+const SIGNATURE_CHUNK_LEN: usize = 3 + 4 + KEY_LEN + SIGNATURE_LEN;
+
+/// Prefix of an encoded verifying key.
+const VERIFYING_KEY_PREFIX: &str = "pngme-sign-pk-";
+
+/// Prefix of an encoded signing key.
+const SIGNING_KEY_PREFIX: &str = "PNGME-SIGN-SK-";
+
+/// Ed25519 public key which verifies signatures, encoded as `pngme-sign-pk-`
+/// followed by 64 hexadecimal digits.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct VerifyingKey(ed25519_dalek::VerifyingKey);
+
+impl VerifyingKey {
+    /// Returns the raw bytes of the verifying key.
+    pub fn as_bytes(&self) -> &[u8; KEY_LEN] {
+        self.0.as_bytes()
+    }
+}
+
+impl FromStr for VerifyingKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let key = s
+            .strip_prefix(VERIFYING_KEY_PREFIX)
+            .and_then(|hex| decode_key(hex).ok())
+            .and_then(|bytes| ed25519_dalek::VerifyingKey::from_bytes(&bytes).ok())
+            .with_context(|| {
+                format!(
+                    "invalid verifying key '{s}': must be \"{VERIFYING_KEY_PREFIX}\" followed by {} hexadecimal digits",
+                    KEY_LEN * 2
+                )
+            })?;
+
+        Ok(VerifyingKey(key))
+    }
+}
+
+impl fmt::Display for VerifyingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{VERIFYING_KEY_PREFIX}{}", hex::encode(self.as_bytes()))
+    }
+}
+
+impl fmt::Debug for VerifyingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "VerifyingKey({self})")
+    }
+}
+
+/// Ed25519 secret key which signs messages.
+///
+/// Signing keys are stored in key files, which contain the secret key encoded
+/// as `PNGME-SIGN-SK-` followed by 64 hexadecimal digits. Empty lines and lines
+/// starting with `#` are ignored.
+#[derive(Clone)]
+pub struct SigningKey(ed25519_dalek::SigningKey);
+
+impl SigningKey {
+    /// Generates a new random signing key.
+    pub fn generate() -> Self {
+        SigningKey(ed25519_dalek::SigningKey::generate(&mut OsRng))
+    }
+
+    /// Returns the verifying key of the signing key.
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey(self.0.verifying_key())
+    }
+
+    /// Returns the contents of a key file storing the signing key.
+    pub fn to_file_contents(&self) -> Zeroizing<String> {
+        Zeroizing::new(format!(
+            "# verifying key: {}\n{SIGNING_KEY_PREFIX}{}\n",
+            self.verifying_key(),
+            hex::encode_upper(self.0.as_bytes())
+        ))
+    }
+}
+
+impl FromStr for SigningKey {
+    type Err = anyhow::Error;
+
+    /// Parses the contents of a key file.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut keys = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+        let (Some(key), None) = (keys.next(), keys.next()) else {
+            anyhow::bail!("invalid signing key: expected exactly one secret key");
+        };
+
+        let bytes = key
+            .strip_prefix(SIGNING_KEY_PREFIX)
+            .and_then(|hex| decode_key(hex).ok())
+            .with_context(|| {
+                format!(
+                    "invalid signing key: secret key must be \"{SIGNING_KEY_PREFIX}\" followed by {} hexadecimal digits",
+                    KEY_LEN * 2
+                )
+            })?;
+
+        Ok(SigningKey(ed25519_dalek::SigningKey::from_bytes(&bytes)))
+    }
+}
+
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SigningKey({})", self.verifying_key())
+    }
+}
+
+/// Decodes a key from hexadecimal digits.
+fn decode_key(hex: &str) -> Result<Zeroizing<[u8; KEY_LEN]>> {
+    let mut key = Zeroizing::new([0; KEY_LEN]);
+    hex::decode_to_slice(hex, key.as_mut())?;
+
+    Ok(key)
+}
+
+/// Decoded contents of a signature chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    chunk_type: ChunkType,
+    covers_image: bool,
+    signer: VerifyingKey,
+    signature: ed25519_dalek::Signature,
+}
+
+impl Signature {
+    /// Signs the message chunk, and the image data of the PNG if
+    /// `covers_image` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the signature covers the image data and the PNG has
+    /// no `IHDR` chunk.
+    pub fn sign(key: &SigningKey, message: &Chunk, png: &PNG, covers_image: bool) -> Result<Self> {
+        let digest = digest(message, covers_image.then_some(png))?;
+
+        Ok(Signature {
+            chunk_type: message.chunk_type(),
+            covers_image,
+            signer: key.verifying_key(),
+            signature: key.0.sign(&digest),
+        })
+    }
+
+    /// Returns the chunk type of the signed message chunk.
+    #[inline]
+    pub fn chunk_type(&self) -> ChunkType {
+        self.chunk_type
+    }
+
+    /// Returns `true` if the signature covers the image data.
+    #[inline]
+    pub fn covers_image(&self) -> bool {
+        self.covers_image
+    }
+
+    /// Returns the verifying key of the signer.
+    #[inline]
+    pub fn signer(&self) -> VerifyingKey {
+        self.signer
+    }
+
+    /// Returns `true` if the signature is valid for the message chunk, and
+    /// the image data of the PNG if the signature covers it.
+    pub fn verify(&self, message: &Chunk, png: &PNG) -> bool {
+        if message.chunk_type() != self.chunk_type {
+            return false;
+        }
+
+        digest(message, self.covers_image.then_some(png))
+            .is_ok_and(|digest| self.signer.0.verify(&digest, &self.signature).is_ok())
+    }
+
+    /// Encodes the signature as a signature chunk.
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = Vec::with_capacity(SIGNATURE_CHUNK_LEN);
+        data.extend([
+            VERSION,
+            ALGORITHM_ED25519,
+            if self.covers_image { FLAG_IMAGE } else { 0 },
+        ]);
+        data.extend(self.chunk_type.bytes());
+        data.extend(self.signer.as_bytes());
+        data.extend(self.signature.to_bytes());
+
+        Chunk::new(
+            ChunkType::from_str(SIGNATURE_CHUNK_TYPE).expect("valid signature chunk type"),
+            data,
+        )
+        .expect("signature chunk fits in a chunk")
+    }
+}
+
+impl TryFrom<&Chunk> for Signature {
+    type Error = anyhow::Error;
+
+    fn try_from(chunk: &Chunk) -> result::Result<Self, Self::Error> {
+        anyhow::ensure!(
+            chunk.chunk_type().bytes() == SIGNATURE_CHUNK_TYPE.as_bytes(),
+            "invalid signature chunk: expected chunk type '{}', found '{}'",
+            SIGNATURE_CHUNK_TYPE,
+            chunk.chunk_type()
+        );
+
+        let data = chunk.data();
+        anyhow::ensure!(
+            data.len() == SIGNATURE_CHUNK_LEN,
+            "invalid signature chunk: expected {} bytes, found {}",
+            SIGNATURE_CHUNK_LEN,
+            data.len()
+        );
+
+        let [version, algorithm, flags] = [data[0], data[1], data[2]];
+        anyhow::ensure!(
+            version == VERSION,
+            "unsupported signature version: {version}"
+        );
+        anyhow::ensure!(
+            algorithm == ALGORITHM_ED25519,
+            "unsupported signature algorithm: {algorithm}"
+        );
+        anyhow::ensure!(
+            flags & !FLAG_IMAGE == 0,
+            "invalid signature chunk: unknown flags: {flags:#04x}"
+        );
+
+        let chunk_type = ChunkType::try_from(<[u8; 4]>::try_from(&data[3..7]).unwrap())
+            .context("invalid signature chunk: invalid signed chunk type")?;
+
+        let signer =
+            ed25519_dalek::VerifyingKey::from_bytes(&data[7..7 + KEY_LEN].try_into().unwrap())
+                .map_err(|_| {
+                    anyhow::anyhow!("invalid signature chunk: invalid signer public key")
+                })?;
+
+        let signature =
+            ed25519_dalek::Signature::from_bytes(&data[7 + KEY_LEN..].try_into().unwrap());
+
+        Ok(Signature {
+            chunk_type,
+            covers_image: flags & FLAG_IMAGE != 0,
+            signer: VerifyingKey(signer),
+            signature,
+        })
+    }
+}
+
+/// Returns the digest signed for the message chunk, and the image data of the
+/// PNG if provided.
+fn digest(message: &Chunk, png: Option<&PNG>) -> Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+
+    hasher.update([if png.is_some() { FLAG_IMAGE } else { 0 }]);
+    hasher.update(message.chunk_type().bytes());
+    hasher.update(message.length().to_be_bytes());
+    hasher.update(message.data());
+
+    if let Some(png) = png {
+        let ihdr = png
+            .chunk_by_type("IHDR")
+            .context("invalid PNG datastream: missing IHDR chunk")?;
+        hasher.update(ihdr.data());
+
+        for idat in png
+            .chunks()
+            .iter()
+            .filter(|c| c.chunk_type().bytes() == *b"IDAT")
+        {
+            hasher.update(idat.data());
+        }
+    }
+
+    Ok(CONTEXT.iter().copied().chain(hasher.finalize()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec()).unwrap()
+    }
+
+    fn png() -> PNG {
+        PNG::from_chunks(vec![
+            chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]),
+            chunk("IDAT", b"image data"),
+            chunk("IEND", &[]),
+        ])
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let key = SigningKey::generate();
+        let message = chunk("ruSt", b"message");
+
+        for covers_image in [false, true] {
+            let signature = Signature::sign(&key, &message, &png(), covers_image).unwrap();
+
+            assert_eq!(signature.signer(), key.verifying_key());
+            assert_eq!(signature.covers_image(), covers_image);
+            assert!(signature.verify(&message, &png()));
+        }
+    }
+
+    #[test]
+    fn test_verify_altered_message() {
+        let key = SigningKey::generate();
+        let signature = Signature::sign(&key, &chunk("ruSt", b"message"), &png(), false).unwrap();
+
+        assert!(!signature.verify(&chunk("ruSt", b"massage"), &png()));
+        assert!(!signature.verify(&chunk("ruSs", b"message"), &png()));
+    }
+
+    #[test]
+    fn test_verify_altered_image() {
+        let key = SigningKey::generate();
+        let message = chunk("ruSt", b"message");
+        let mut altered = png();
+        altered.append_chunk(chunk("IDAT", b"more image data"));
+
+        let signature = Signature::sign(&key, &message, &png(), false).unwrap();
+        assert!(signature.verify(&message, &altered));
+
+        let signature = Signature::sign(&key, &message, &png(), true).unwrap();
+        assert!(!signature.verify(&message, &altered));
+    }
+
+    #[test]
+    fn test_signature_chunk_roundtrip() {
+        let key = SigningKey::generate();
+        let message = chunk("ruSt", b"message");
+        let signature = Signature::sign(&key, &message, &png(), true).unwrap();
+
+        let chunk = signature.to_chunk();
+        assert_eq!(chunk.chunk_type().to_string(), SIGNATURE_CHUNK_TYPE);
+        assert!(!chunk.chunk_type().is_safe_to_copy());
+
+        let decoded = Signature::try_from(&chunk).unwrap();
+        assert_eq!(decoded, signature);
+        assert!(decoded.verify(&message, &png()));
+    }
+
+    #[test]
+    fn test_flipped_flag_fails_verification() {
+        let key = SigningKey::generate();
+        let message = chunk("ruSt", b"message");
+        let signature = Signature::sign(&key, &message, &png(), false).unwrap();
+
+        let mut data = signature.to_chunk().data().to_vec();
+        data[2] = FLAG_IMAGE;
+        let flipped = Signature::try_from(&chunk(SIGNATURE_CHUNK_TYPE, &data)).unwrap();
+
+        assert!(!flipped.verify(&message, &png()));
+    }
+
+    #[test]
+    fn test_invalid_signature_chunk() {
+        let key = SigningKey::generate();
+        let signature = Signature::sign(&key, &chunk("ruSt", b"message"), &png(), false).unwrap();
+        let data = signature.to_chunk().data().to_vec();
+
+        assert!(Signature::try_from(&chunk("ruSt", &data)).is_err());
+        assert!(Signature::try_from(&chunk(SIGNATURE_CHUNK_TYPE, &data[1..])).is_err());
+
+        let mut unsupported = data.clone();
+        unsupported[0] = 2;
+        assert!(Signature::try_from(&chunk(SIGNATURE_CHUNK_TYPE, &unsupported)).is_err());
+
+        let mut flags = data;
+        flags[2] = 0x80;
+        assert!(Signature::try_from(&chunk(SIGNATURE_CHUNK_TYPE, &flags)).is_err());
+    }
+
+    #[test]
+    fn test_signing_key_roundtrip() {
+        let key = SigningKey::generate();
+
+        let parsed = SigningKey::from_str(&key.to_file_contents()).unwrap();
+        assert_eq!(parsed.verifying_key(), key.verifying_key());
+
+        let verifying_key = key.verifying_key().to_string();
+        assert_eq!(
+            VerifyingKey::from_str(&verifying_key).unwrap(),
+            key.verifying_key()
+        );
+        assert!(VerifyingKey::from_str("pngme-sign-pk-00").is_err());
+    }
+}
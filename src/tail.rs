@@ -0,0 +1,369 @@
+//! Editing the end of a PNG file without rewriting the rest of it.
+//!
+//! Appending a chunk to a PNG only changes the bytes from the `IEND` chunk
+//! onwards, so instead of parsing and rewriting the whole datastream, the new
+//! chunks and a fresh `IEND` chunk can be written over the old one in place.
+//! Likewise, removing the last chunk before `IEND` only moves `IEND` back and
+//! shortens the file. Either way, the result is byte-identical to rewriting
+//! the whole datastream after [`PNG::append_chunk`] or [`PNG::remove_chunk_at`].
+//!
+//! Only the signature and the chunk headers are read to locate the `IEND`
+//! chunk, skipping over the chunk data, so the datastream is assumed to be
+//! otherwise valid.
+
+use std::fs;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::{Error, Result};
+use crate::png::PNG;
+use crate::reader::read_error;
+
+/// Bytes of the `IEND` chunk, which has no data.
+const IEND: [u8; 12] = [
+    0x00, 0x00, 0x00, 0x00, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82,
+];
+
+/// Size of the length, chunk type, and CRC fields of a chunk.
+const CHUNK_OVERHEAD: u64 = 12;
+
+/// Byte offset of the first chunk, following the PNG signature.
+const FIRST_CHUNK: u64 = 8;
+
+/// Seekable datastream which can be shortened, such as a file.
+pub trait Truncate: Read + Write + Seek {
+    /// Truncates the datastream to the length in bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the datastream could not be truncated.
+    fn truncate(&mut self, len: u64) -> io::Result<()>;
+}
+
+impl Truncate for fs::File {
+    #[inline]
+    fn truncate(&mut self, len: u64) -> io::Result<()> {
+        self.set_len(len)
+    }
+}
+
+impl Truncate for Cursor<Vec<u8>> {
+    #[inline]
+    fn truncate(&mut self, len: u64) -> io::Result<()> {
+        let len = usize::try_from(len).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        self.get_mut().truncate(len);
+
+        Ok(())
+    }
+}
+
+/// Appends the chunks to the PNG datastream before its `IEND` chunk, by
+/// writing them and a fresh `IEND` chunk over the old one.
+///
+/// Only the signature and the headers of the chunks are read.
+///
+/// # Errors
+///
+/// Returns an error if the datastream could not be read or written, has an
+/// invalid signature, has no `IEND` chunk, or [`Error::TrailingData`] if data
+/// follows its `IEND` chunk.
+pub fn append_chunks<F: Read + Write + Seek>(file: &mut F, chunks: &[Chunk]) -> Result<()> {
+    let (_, iend, _) = locate_iend(file)?;
+
+    let mut bytes = Vec::with_capacity(chunks.iter().map(Chunk::size).sum::<usize>() + IEND.len());
+    for chunk in chunks {
+        bytes.extend(chunk.as_bytes());
+    }
+    bytes.extend(IEND);
+
+    file.seek(SeekFrom::Start(iend))?;
+    file.write_all(&bytes)?;
+    file.flush()?;
+
+    Ok(())
+}
+
+/// Returns the last chunk before the `IEND` chunk of the PNG datastream, or
+/// `None` if there is none.
+///
+/// Only the signature and the headers of the chunks are read, along with the
+/// chunk returned.
+///
+/// # Errors
+///
+/// Returns an error if the datastream could not be read, has an invalid
+/// signature, has no `IEND` chunk, or [`Error::TrailingData`] if data follows
+/// its `IEND` chunk, or if the chunk is malformed.
+pub fn last_chunk<F: Read + Seek>(file: &mut F) -> Result<Option<Chunk>> {
+    read_last_chunk(file).map(|last| last.map(|(_, chunk)| chunk))
+}
+
+/// Removes the last chunk before the `IEND` chunk of the PNG datastream and
+/// returns it, by writing the `IEND` chunk over it and truncating the
+/// datastream, or returns `None` if that chunk is critical or does not have
+/// the chunk type.
+///
+/// Only the signature and the headers of the chunks are read, along with the
+/// chunk removed.
+///
+/// # Errors
+///
+/// Returns an error if the datastream could not be read or written, has an
+/// invalid signature, has no `IEND` chunk, or [`Error::TrailingData`] if data
+/// follows its `IEND` chunk, or if the chunk removed is malformed.
+pub fn remove_last_chunk<F: Truncate>(
+    file: &mut F,
+    chunk_type: ChunkType,
+) -> Result<Option<Chunk>> {
+    remove_last_chunk_if(file, |chunk| chunk.chunk_type() == chunk_type)
+}
+
+/// Removes the last chunk before the `IEND` chunk of the PNG datastream if the
+/// predicate holds for it and returns it, or returns `None` if that chunk is
+/// critical or the predicate does not hold.
+///
+/// Only the signature and the headers of the chunks are read, along with the
+/// chunk passed to the predicate.
+///
+/// # Errors
+///
+/// Returns an error if the datastream could not be read or written, has an
+/// invalid signature, has no `IEND` chunk, or [`Error::TrailingData`] if data
+/// follows its `IEND` chunk, or if the last chunk is malformed.
+pub fn remove_last_chunk_if<F: Truncate>(
+    file: &mut F,
+    predicate: impl FnOnce(&Chunk) -> bool,
+) -> Result<Option<Chunk>> {
+    let Some((offset, chunk)) = read_last_chunk(file)? else {
+        return Ok(None);
+    };
+
+    if chunk.chunk_type().is_critical() || !predicate(&chunk) {
+        return Ok(None);
+    }
+
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(&IEND)?;
+    file.truncate(offset + CHUNK_OVERHEAD)?;
+    file.flush()?;
+
+    Ok(Some(chunk))
+}
+
+/// Reads the last chunk before the `IEND` chunk, returning its byte offset
+/// along with it, or `None` if there is none.
+fn read_last_chunk<F: Read + Seek>(file: &mut F) -> Result<Option<(u64, Chunk)>> {
+    let (last, iend, iend_index) = locate_iend(file)?;
+
+    let Some(offset) = last else {
+        return Ok(None);
+    };
+    let index = iend_index - 1;
+
+    let mut bytes = vec![0u8; (iend - offset) as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut bytes)
+        .map_err(|e| read_error(e, offset, index))?;
+
+    // Errors are located within the datastream, not the bytes of the chunk.
+    let chunk = Chunk::try_from(bytes.as_slice()).map_err(|err| match err {
+        Error::CrcMismatch {
+            expected, computed, ..
+        } => Error::CrcMismatch {
+            expected,
+            computed,
+            offset,
+            index,
+        },
+        err => err,
+    })?;
+
+    Ok(Some((offset, chunk)))
+}
+
+/// Walks the chunk headers of the datastream, returning the byte offsets of
+/// the chunk before the `IEND` chunk, if any, and of the `IEND` chunk, which
+/// must end the datastream, along with the index of the `IEND` chunk.
+fn locate_iend<F: Read + Seek>(file: &mut F) -> Result<(Option<u64>, u64, usize)> {
+    read_signature(file)?;
+
+    let len = file.seek(SeekFrom::End(0))?;
+    let mut last = None;
+    let mut offset = FIRST_CHUNK;
+    let mut index = 0;
+
+    while offset < len {
+        let mut header = [0u8; 8];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header)
+            .map_err(|e| read_error(e, offset, index))?;
+
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        if length > i32::MAX as u32 {
+            return Err(Error::InvalidLength {
+                length,
+                offset,
+                index,
+            });
+        }
+
+        let end = offset + CHUNK_OVERHEAD + u64::from(length);
+        if end > len {
+            return Err(Error::Truncated { offset, index });
+        }
+
+        if header[4..] == *b"IEND" {
+            if end != len {
+                return Err(Error::TrailingData { offset: end });
+            }
+
+            return Ok((last, offset, index));
+        }
+
+        last = Some(offset);
+        offset = end;
+        index += 1;
+    }
+
+    Err(Error::Truncated { offset, index })
+}
+
+/// Reads and checks the PNG signature at the start of the datastream.
+fn read_signature<F: Read + Seek>(file: &mut F) -> Result<()> {
+    let mut signature = [0u8; 8];
+
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut signature)
+        .map_err(|e| read_error(e, 0, 0))?;
+
+    if signature != PNG::MAGIC {
+        return Err(Error::InvalidSignature { found: signature });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn chunk_from_parts(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec()).unwrap()
+    }
+
+    fn generate_png() -> PNG {
+        PNG::from_chunks(vec![
+            chunk_from_parts(
+                "IHDR",
+                b"\x00\x00\x00\x01\x00\x00\x00\x01\x08\x02\x00\x00\x00",
+            ),
+            chunk_from_parts("IDAT", b"\x78\x9c\x63\x60\x60\x60\x00\x00\x00\x04\x00\x01"),
+            chunk_from_parts("ruSt", b"This is where your secret message will be!"),
+            chunk_from_parts("IEND", b""),
+        ])
+    }
+
+    #[test]
+    fn test_iend_bytes() {
+        assert_eq!(chunk_from_parts("IEND", b"").as_bytes(), IEND);
+    }
+
+    #[test]
+    fn test_append_chunks_matches_rewrite() {
+        let chunks = [
+            chunk_from_parts("teSt", b"first"),
+            chunk_from_parts("teSt", b"second"),
+        ];
+
+        let mut png = generate_png();
+        let mut file = Cursor::new(png.as_bytes());
+        append_chunks(&mut file, &chunks).unwrap();
+
+        for chunk in chunks {
+            png.append_chunk(chunk);
+        }
+
+        assert_eq!(file.into_inner(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_remove_last_chunk_matches_rewrite() {
+        let mut png = generate_png();
+        let mut file = Cursor::new(png.as_bytes());
+
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let removed = remove_last_chunk(&mut file, chunk_type).unwrap().unwrap();
+
+        assert_eq!(Some(removed), png.remove_chunk_at(2));
+        assert_eq!(file.into_inner(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_remove_last_chunk_other_chunk_type() {
+        let bytes = generate_png().as_bytes();
+        let mut file = Cursor::new(bytes.clone());
+
+        let chunk_type = ChunkType::from_str("teSt").unwrap();
+        assert!(remove_last_chunk(&mut file, chunk_type).unwrap().is_none());
+
+        // Critical chunks are never removed.
+        let mut png = generate_png();
+        png.remove_chunk_at(2);
+        let mut file = Cursor::new(png.as_bytes());
+
+        let chunk_type = ChunkType::from_str("IDAT").unwrap();
+        assert!(remove_last_chunk(&mut file, chunk_type).unwrap().is_none());
+        assert_eq!(file.into_inner(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut bytes = generate_png().as_bytes();
+        let len = bytes.len() as u64;
+        bytes.extend(b"trailing");
+
+        let chunk = chunk_from_parts("teSt", b"");
+        let err = append_chunks(&mut Cursor::new(bytes), &[chunk]).unwrap_err();
+
+        assert!(matches!(err, Error::TrailingData { offset } if offset == len));
+    }
+
+    #[test]
+    fn test_concatenated_pngs() {
+        let mut bytes = generate_png().as_bytes();
+        let len = bytes.len() as u64;
+        bytes.extend(generate_png().as_bytes());
+
+        assert!(PNG::try_from(bytes.as_slice()).is_err());
+
+        let chunk = chunk_from_parts("teSt", b"");
+        let err = append_chunks(&mut Cursor::new(bytes), &[chunk]).unwrap_err();
+
+        assert!(matches!(err, Error::TrailingData { offset } if offset == len));
+    }
+
+    #[test]
+    fn test_missing_iend() {
+        let mut bytes = generate_png().as_bytes();
+        let len = bytes.len() - IEND.len();
+        bytes.truncate(len);
+
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let err = remove_last_chunk(&mut Cursor::new(bytes), chunk_type).unwrap_err();
+
+        assert!(matches!(err, Error::Truncated { offset, index: 3 } if offset == len as u64));
+    }
+
+    #[test]
+    fn test_invalid_signature() {
+        let mut bytes = generate_png().as_bytes();
+        bytes[0] = 0;
+
+        let err = append_chunks(&mut Cursor::new(bytes), &[]).unwrap_err();
+
+        assert!(matches!(err, Error::InvalidSignature { .. }));
+    }
+}
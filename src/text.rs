@@ -0,0 +1,437 @@
+//! Textual information is stored in three public chunk types, each associating
+//! a text string with a keyword describing it (e.g., "Title", "Comment"):
+//!
+//! - tEXt: uncompressed Latin-1 text.
+//!
+//! - zTXt: deflate-compressed Latin-1 text.
+//!
+//! - iTXt: UTF-8 text, optionally compressed, along with a language tag and a
+//!   translation of the keyword into that language.
+//!
+//! Keywords are 1-79 bytes of printable Latin-1 characters, without leading,
+//! trailing, or consecutive spaces. Unlike private chunk types, textual chunks
+//! are understood by standard image tools such as exiftool and ImageMagick.
+
+use std::convert::TryFrom;
+use std::result;
+use std::str::{self, FromStr};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+
+use anyhow::{Context, Result};
+
+/// Maximum length of a keyword in bytes.
+const MAX_KEYWORD_LEN: usize = 79;
+
+/// Maximum length in bytes of decompressed text, so a small compressed chunk
+/// cannot expand without bound.
+const MAX_TEXT_LEN: usize = 16 * 1024 * 1024;
+
+/// Compression level used for compressed text (0-10).
+const COMPRESSION_LEVEL: u8 = 6;
+
+/// Encoding of a textual chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextKind {
+    /// Uncompressed Latin-1 text (`tEXt`).
+    Text,
+    /// Compressed Latin-1 text (`zTXt`).
+    Compressed,
+    /// UTF-8 text (`iTXt`).
+    International {
+        /// Whether the text is compressed.
+        compressed: bool,
+        /// Language of the text (e.g., "en-US"), or empty if unknown.
+        language_tag: String,
+        /// Keyword translated into the language of the text, or empty.
+        translated_keyword: String,
+    },
+}
+
+impl TextKind {
+    /// Returns the kind of textual chunk with the given chunk type, or `None`
+    /// if the chunk type is not a textual chunk type.
+    ///
+    /// International text is uncompressed, without a language tag or
+    /// translated keyword.
+    pub fn from_chunk_type(chunk_type: &str) -> Option<Self> {
+        match chunk_type {
+            "tEXt" => Some(TextKind::Text),
+            "zTXt" => Some(TextKind::Compressed),
+            "iTXt" => Some(TextKind::International {
+                compressed: false,
+                language_tag: String::new(),
+                translated_keyword: String::new(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns the chunk type of the textual chunk.
+    pub const fn chunk_type(&self) -> &'static str {
+        match self {
+            TextKind::Text => "tEXt",
+            TextKind::Compressed => "zTXt",
+            TextKind::International { .. } => "iTXt",
+        }
+    }
+}
+
+/// Decoded contents of a `tEXt`, `zTXt`, or `iTXt` chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    kind: TextKind,
+    keyword: String,
+    text: String,
+}
+
+impl TextChunk {
+    /// Creates a new textual chunk with the specified keyword and text.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keyword is invalid, if the text of a `tEXt` or
+    /// `zTXt` chunk can not be represented in Latin-1, or if any field
+    /// contains a null character.
+    pub fn new(
+        kind: TextKind,
+        keyword: impl Into<String>,
+        text: impl Into<String>,
+    ) -> Result<Self> {
+        let keyword = keyword.into();
+        let text = text.into();
+
+        check_keyword(&keyword)?;
+
+        match &kind {
+            TextKind::Text | TextKind::Compressed => {
+                anyhow::ensure!(
+                    text.chars().all(|c| c != '\0' && (c as u32) <= 0xFF),
+                    "invalid {} chunk: text must consist of Latin-1 characters other than null",
+                    kind.chunk_type()
+                );
+            }
+            TextKind::International {
+                language_tag,
+                translated_keyword,
+                ..
+            } => {
+                anyhow::ensure!(
+                    language_tag
+                        .bytes()
+                        .all(|b| b.is_ascii_alphanumeric() || b == b'-'),
+                    "invalid iTXt chunk: language tag must consist of ASCII letters, digits, and hyphens: \"{}\"",
+                    language_tag
+                );
+                anyhow::ensure!(
+                    !translated_keyword.contains('\0') && !text.contains('\0'),
+                    "invalid iTXt chunk: translated keyword and text must not contain null characters"
+                );
+            }
+        }
+
+        Ok(TextChunk {
+            kind,
+            keyword,
+            text,
+        })
+    }
+
+    /// Returns the encoding of the textual chunk.
+    #[inline]
+    pub fn kind(&self) -> &TextKind {
+        &self.kind
+    }
+
+    /// Returns the keyword of the textual chunk.
+    #[inline]
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    /// Returns the text of the textual chunk.
+    #[inline]
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns `true` if the chunk type is `tEXt`, `zTXt`, or `iTXt`.
+    pub fn is_text_chunk_type(chunk_type: ChunkType) -> bool {
+        matches!(&chunk_type.bytes(), b"tEXt" | b"zTXt" | b"iTXt")
+    }
+
+    /// Encodes the textual chunk as a PNG chunk.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the encoded data is too large to fit in a chunk.
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        // Keywords are restricted to Latin-1 when constructed.
+        let mut data = latin1_encode(&self.keyword);
+        data.push(0);
+
+        match &self.kind {
+            TextKind::Text => data.extend(latin1_encode(&self.text)),
+            TextKind::Compressed => {
+                data.push(0); // Compression method: deflate.
+                data.extend(miniz_oxide::deflate::compress_to_vec_zlib(
+                    &latin1_encode(&self.text),
+                    COMPRESSION_LEVEL,
+                ));
+            }
+            TextKind::International {
+                compressed,
+                language_tag,
+                translated_keyword,
+            } => {
+                data.push(*compressed as u8);
+                data.push(0); // Compression method: deflate.
+                data.extend(language_tag.as_bytes());
+                data.push(0);
+                data.extend(translated_keyword.as_bytes());
+                data.push(0);
+
+                if *compressed {
+                    data.extend(miniz_oxide::deflate::compress_to_vec_zlib(
+                        self.text.as_bytes(),
+                        COMPRESSION_LEVEL,
+                    ));
+                } else {
+                    data.extend(self.text.as_bytes());
+                }
+            }
+        }
+
+        Ok(Chunk::new(
+            ChunkType::from_str(self.kind.chunk_type())?,
+            data,
+        )?)
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = anyhow::Error;
+
+    fn try_from(chunk: &Chunk) -> result::Result<Self, Self::Error> {
+        let chunk_type = chunk.chunk_type();
+        let data = chunk.data();
+
+        let (keyword, rest) = split_null(data)
+            .with_context(|| format!("invalid {chunk_type} chunk: missing keyword separator"))?;
+        let keyword = latin1_decode(keyword);
+
+        match &chunk_type.bytes() {
+            b"tEXt" => TextChunk::new(TextKind::Text, keyword, latin1_decode(rest)),
+            b"zTXt" => {
+                let (&method, compressed) = rest
+                    .split_first()
+                    .context("invalid zTXt chunk: missing compression method")?;
+                check_compression_method(method)?;
+
+                let text = inflate(compressed).context("invalid zTXt chunk")?;
+
+                TextChunk::new(TextKind::Compressed, keyword, latin1_decode(&text))
+            }
+            b"iTXt" => {
+                let [flag, method, rest @ ..] = rest else {
+                    anyhow::bail!("invalid iTXt chunk: missing compression flag and method");
+                };
+
+                let compressed = match flag {
+                    0 => false,
+                    1 => true,
+                    _ => anyhow::bail!("invalid iTXt chunk: invalid compression flag: {}", flag),
+                };
+                if compressed {
+                    check_compression_method(*method)?;
+                }
+
+                let (language_tag, rest) =
+                    split_null(rest).context("invalid iTXt chunk: missing language tag")?;
+                let (translated_keyword, text) =
+                    split_null(rest).context("invalid iTXt chunk: missing translated keyword")?;
+
+                let text = if compressed {
+                    inflate(text).context("invalid iTXt chunk")?
+                } else {
+                    text.to_vec()
+                };
+
+                let kind = TextKind::International {
+                    compressed,
+                    language_tag: String::from_utf8(language_tag.to_vec())
+                        .context("invalid iTXt chunk: language tag is not valid UTF-8")?,
+                    translated_keyword: String::from_utf8(translated_keyword.to_vec())
+                        .context("invalid iTXt chunk: translated keyword is not valid UTF-8")?,
+                };
+                let text = String::from_utf8(text)
+                    .context("invalid iTXt chunk: text is not valid UTF-8")?;
+
+                TextChunk::new(kind, keyword, text)
+            }
+            _ => anyhow::bail!(
+                "invalid textual chunk: unexpected chunk type '{}'",
+                chunk_type
+            ),
+        }
+    }
+}
+
+/// Ensures the keyword is 1-79 bytes of printable Latin-1 characters, without
+/// leading, trailing, or consecutive spaces.
+fn check_keyword(keyword: &str) -> Result<()> {
+    let printable = |c: char| matches!(c as u32, 0x20..=0x7E | 0xA1..=0xFF);
+
+    anyhow::ensure!(
+        keyword.chars().all(printable),
+        "invalid keyword: must consist of printable Latin-1 characters: \"{}\"",
+        keyword
+    );
+
+    // Each character is encoded as a single byte in Latin-1.
+    let len = keyword.chars().count();
+    anyhow::ensure!(
+        (1..=MAX_KEYWORD_LEN).contains(&len),
+        "invalid keyword: must be between 1 and {} bytes, but received: {}",
+        MAX_KEYWORD_LEN,
+        len
+    );
+
+    anyhow::ensure!(
+        !keyword.starts_with(' ') && !keyword.ends_with(' ') && !keyword.contains("  "),
+        "invalid keyword: must not contain leading, trailing, or consecutive spaces: \"{}\"",
+        keyword
+    );
+
+    Ok(())
+}
+
+fn check_compression_method(method: u8) -> Result<()> {
+    anyhow::ensure!(
+        method == 0,
+        "invalid textual chunk: unknown compression method: {}",
+        method
+    );
+
+    Ok(())
+}
+
+/// Splits the bytes at the first null separator, excluding the separator.
+fn split_null(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let idx = bytes.iter().position(|&b| b == 0)?;
+    Some((&bytes[..idx], &bytes[idx + 1..]))
+}
+
+/// Decompresses a zlib datastream, bounded by [`MAX_TEXT_LEN`].
+fn inflate(compressed: &[u8]) -> Result<Vec<u8>> {
+    miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(compressed, MAX_TEXT_LEN)
+        .map_err(|e| anyhow::anyhow!("failed to decompress text: {}", e))
+}
+
+/// Encodes a string as Latin-1, where each character maps to a single byte.
+///
+/// Characters outside of Latin-1 must have been rejected beforehand.
+fn latin1_encode(s: &str) -> Vec<u8> {
+    s.chars().map(|c| c as u32 as u8).collect()
+}
+
+/// Decodes Latin-1 bytes, where each byte maps to the character with the same
+/// code point.
+fn latin1_decode(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn international(compressed: bool) -> TextKind {
+        TextKind::International {
+            compressed,
+            language_tag: String::from("fr"),
+            translated_keyword: String::from("Commentaire"),
+        }
+    }
+
+    fn roundtrip(text: &TextChunk) -> TextChunk {
+        TextChunk::try_from(&text.to_chunk().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_text_roundtrip() {
+        let text = TextChunk::new(TextKind::Text, "Comment", "café au lait").unwrap();
+        let chunk = text.to_chunk().unwrap();
+
+        assert_eq!(chunk.chunk_type().to_string(), "tEXt");
+        // Latin-1 encodes 'é' as a single byte.
+        assert_eq!(chunk.data(), b"Comment\0caf\xe9 au lait");
+        assert_eq!(roundtrip(&text), text);
+    }
+
+    #[test]
+    fn test_compressed_text_roundtrip() {
+        let text = TextChunk::new(TextKind::Compressed, "Description", "a".repeat(1000)).unwrap();
+        let chunk = text.to_chunk().unwrap();
+
+        assert_eq!(chunk.chunk_type().to_string(), "zTXt");
+        assert!(chunk.data().len() < 1000);
+        assert_eq!(roundtrip(&text), text);
+    }
+
+    #[test]
+    fn test_international_text_roundtrip() {
+        for compressed in [false, true] {
+            let text =
+                TextChunk::new(international(compressed), "Comment", "un message 🦀").unwrap();
+            let chunk = text.to_chunk().unwrap();
+
+            assert_eq!(chunk.chunk_type().to_string(), "iTXt");
+            assert_eq!(roundtrip(&text), text);
+        }
+    }
+
+    #[test]
+    fn test_text_from_chunk() {
+        let chunk = Chunk::new(
+            ChunkType::from_str("iTXt").unwrap(),
+            b"Title\0\0\0en\0Title\0Hello".to_vec(),
+        )
+        .unwrap();
+        let text = TextChunk::try_from(&chunk).unwrap();
+
+        assert_eq!(text.keyword(), "Title");
+        assert_eq!(text.text(), "Hello");
+    }
+
+    #[test]
+    fn test_text_invalid_keyword() {
+        assert!(TextChunk::new(TextKind::Text, "", "text").is_err());
+        assert!(TextChunk::new(TextKind::Text, " Comment", "text").is_err());
+        assert!(TextChunk::new(TextKind::Text, "Com  ment", "text").is_err());
+        assert!(TextChunk::new(TextKind::Text, "k".repeat(80), "text").is_err());
+        assert!(TextChunk::new(TextKind::Text, "Comment\n", "text").is_err());
+    }
+
+    #[test]
+    fn test_text_not_latin1() {
+        assert!(TextChunk::new(TextKind::Text, "Comment", "🦀").is_err());
+        assert!(TextChunk::new(TextKind::Compressed, "Comment", "🦀").is_err());
+    }
+
+    #[test]
+    fn test_text_missing_separator() {
+        let chunk = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Comment".to_vec()).unwrap();
+        assert!(TextChunk::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_compressed_text_corrupt() {
+        let chunk = Chunk::new(
+            ChunkType::from_str("zTXt").unwrap(),
+            b"Comment\0\0not zlib".to_vec(),
+        )
+        .unwrap();
+        assert!(TextChunk::try_from(&chunk).is_err());
+    }
+}
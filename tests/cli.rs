@@ -0,0 +1,246 @@
+//! Tests running the `pngme` binary, checking its output and exit status.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use pngme::ihdr::ColorType;
+use pngme::{PNG, PngBuilder};
+use serde_json::Value;
+
+/// Returns an empty directory for the test, removing any left over from a
+/// previous run.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pngme-cli-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
+
+/// Writes a small PNG file into the directory, returning its path.
+fn write_png(dir: &Path, name: &str) -> PathBuf {
+    let png = PngBuilder::new(2, 2, ColorType::Grayscale, 8, vec![0, 64, 128, 255])
+        .build()
+        .unwrap();
+
+    let path = dir.join(name);
+    fs::write(&path, png.as_bytes()).unwrap();
+
+    path
+}
+
+/// Returns a command running the binary within the directory.
+fn pngme(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_pngme"));
+    command.current_dir(dir).env_remove("PNGME_PASSPHRASE");
+
+    command
+}
+
+/// Runs the binary with the bytes piped to its standard input, returning its
+/// output.
+fn run_piped(dir: &Path, args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = pngme(dir)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+
+    child.wait_with_output().unwrap()
+}
+
+/// Runs the binary with JSON output, returning its exit status and the JSON
+/// document printed to standard output.
+fn run_json(dir: &Path, args: &[&str], passphrase: Option<&str>) -> (i32, Value) {
+    let mut command = pngme(dir);
+    command.arg("--format").arg("json").args(args);
+
+    if let Some(passphrase) = passphrase {
+        command.env("PNGME_PASSPHRASE", passphrase);
+    }
+
+    let Output { status, stdout, .. } = command.output().unwrap();
+
+    (
+        status.code().unwrap(),
+        serde_json::from_slice(&stdout).unwrap(),
+    )
+}
+
+/// Asserts the JSON document describes an error with the status and exit code.
+fn assert_error(json: &Value, status: &str, exit_code: i32) {
+    let error = &json["error"];
+
+    assert_eq!(error["status"], status, "{json:#}");
+    assert_eq!(error["exit_code"], exit_code, "{json:#}");
+    assert!(error["message"].is_string(), "{json:#}");
+    assert!(error["causes"].is_array(), "{json:#}");
+}
+
+#[test]
+fn test_json_encode_and_decode() {
+    let dir = test_dir("json-found");
+    write_png(&dir, "image.png");
+
+    let (code, json) = run_json(
+        &dir,
+        &["encode", "image.png", "ruSt", "hello", "out.png"],
+        None,
+    );
+    assert_eq!(code, 0, "{json:#}");
+    assert_eq!(json["output"], "out.png");
+    assert_eq!(json["chunks"][0]["type"], "ruSt");
+    assert_eq!(json["chunks"][0]["length"], 5);
+
+    let (code, json) = run_json(&dir, &["decode", "out.png", "ruSt"], None);
+    assert_eq!(code, 0, "{json:#}");
+    assert_eq!(json["found"], true);
+    assert_eq!(json["message"], "hello");
+    assert_eq!(json["encoding"], "utf-8");
+    assert_eq!(json["length"], 5);
+    assert_eq!(json["chunks"][0]["type"], "ruSt");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_json_not_found() {
+    let dir = test_dir("json-not-found");
+    write_png(&dir, "image.png");
+
+    let (code, json) = run_json(&dir, &["decode", "image.png", "ruSt"], None);
+    assert_eq!(code, 3);
+    assert_error(&json, "not_found", 3);
+    assert_eq!(json["found"], false);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_json_invalid_png() {
+    let dir = test_dir("json-invalid-png");
+    fs::write(dir.join("image.png"), b"not a PNG file").unwrap();
+
+    let (code, json) = run_json(&dir, &["decode", "image.png", "ruSt"], None);
+    assert_eq!(code, 4);
+    assert_error(&json, "invalid_png", 4);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_json_io_error() {
+    let dir = test_dir("json-io");
+
+    let (code, json) = run_json(&dir, &["decode", "missing.png", "ruSt"], None);
+    assert_eq!(code, 5);
+    assert_error(&json, "io", 5);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_json_authentication_failed() {
+    let dir = test_dir("json-auth");
+    write_png(&dir, "image.png");
+
+    let args = [
+        "encode",
+        "image.png",
+        "ruSt",
+        "secret",
+        "out.png",
+        "--encrypt",
+    ];
+    let (code, json) = run_json(&dir, &args, Some("right"));
+    assert_eq!(code, 0, "{json:#}");
+
+    let args = ["decode", "out.png", "ruSt", "--decrypt"];
+    let (code, json) = run_json(&dir, &args, Some("wrong"));
+    assert_eq!(code, 6);
+    assert_error(&json, "authentication_failed", 6);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_text_error_exit_status() {
+    let dir = test_dir("text-status");
+    write_png(&dir, "image.png");
+
+    let output = pngme(&dir)
+        .args(["decode", "image.png", "ruSt"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(3));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Error: "));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_pipe_png_through_stdin_and_stdout() {
+    let dir = test_dir("pipe");
+    let png = fs::read(write_png(&dir, "image.png")).unwrap();
+
+    let output = run_piped(&dir, &["encode", "-", "ruSt", "msg", "-"], &png);
+    assert!(output.status.success(), "{output:?}");
+
+    // Standard output holds nothing but the PNG file.
+    let encoded = PNG::try_from(output.stdout.as_slice()).unwrap();
+    assert_eq!(encoded.chunk_by_type("ruSt").unwrap().data(), b"msg");
+    assert_eq!(encoded.as_bytes(), output.stdout);
+
+    let output = run_piped(&dir, &["decode", "-", "ruSt"], &encoded.as_bytes());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout, b"msg\n");
+
+    // Nothing is written to the working directory.
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_pipe_results_move_to_stderr() {
+    let dir = test_dir("pipe-stderr");
+    let png = fs::read(write_png(&dir, "image.png")).unwrap();
+
+    let args = ["--format", "json", "encode", "-", "ruSt", "msg", "-"];
+    let output = run_piped(&dir, &args, &png);
+    assert!(output.status.success(), "{output:?}");
+
+    assert!(PNG::try_from(output.stdout.as_slice()).is_ok());
+
+    let json: Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(json["output"], "-");
+    assert_eq!(json["chunks"][0]["type"], "ruSt");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_json_invalid_chunk_type_argument() {
+    let dir = test_dir("json-chunk-type");
+    write_png(&dir, "image.png");
+
+    let args = ["encode", "image.png", "Ru1t", "msg", "out.png"];
+    let (code, json) = run_json(&dir, &args, None);
+    assert_eq!(code, 1);
+    assert_error(&json, "failure", 1);
+    assert!(!dir.join("out.png").exists());
+
+    let (code, json) = run_json(&dir, &["decode", "image.png", "Ru1t"], None);
+    assert_eq!(code, 1);
+    assert_error(&json, "failure", 1);
+
+    fs::remove_dir_all(&dir).unwrap();
+}